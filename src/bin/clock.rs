@@ -1,10 +1,11 @@
 use cgmath::{Matrix3, Rad, Vector3};
-use ray_tracer_challenge::canvas::Canvas;
+use ray_tracer_challenge::canvas::{Canvas, Origin};
 use rgb::RGB;
 use std::{f32::consts::FRAC_PI_6, fs};
 
 fn main() {
     let mut canvas = Canvas::new(240, 240);
+    canvas.origin = Origin::BottomLeft;
     let radius = (canvas.width * 3 / 8) as f32;
 
     let twelve = Vector3::new(0., 0., 1.);
@@ -12,9 +13,9 @@ fn main() {
 
     let mut hour = twelve;
     for _ in 1..=12 {
-        let y = (canvas.height / 2) as isize - (hour.z * radius) as isize;
+        let y = (canvas.height / 2) as isize + (hour.z * radius) as isize;
         let x = (canvas.width / 2) as isize + (hour.x * radius) as isize;
-        canvas.pixels[y as usize][x as usize] = RGB::new(1., 1., 1.);
+        let _ = canvas.set(x as usize, y as usize, RGB::new(1., 1., 1.));
         hour = rotate_one_hour * hour;
     }
     let _ = fs::create_dir("output");
@@ -6,7 +6,6 @@ use ray_tracer_challenge::{
     camera::Camera,
     light::Light,
     material::Material,
-    pattern::Pattern,
     shape::{plane::Plane, sphere::Sphere, Shape},
     world::World,
 };
@@ -14,36 +13,31 @@ use rgb::RGB;
 use std::fs;
 
 fn main() {
-    let mut room_material = Material::default();
-    room_material.pattern = Pattern::Solid(RGB::new(1., 0.9, 0.9));
-    room_material.specular = 0.;
+    let room_material = Material::default()
+        .with_color(RGB::new(1., 0.9, 0.9))
+        .with_specular(0.);
 
     let floor = Plane::new(Matrix4::identity(), room_material, None);
 
-    let mut sphere_material = Material::default();
-    sphere_material.diffuse = 0.7;
-    sphere_material.specular = 0.3;
+    let sphere_material = Material::default().with_diffuse(0.7).with_specular(0.3);
 
-    let mut middle = Sphere::new(
+    let middle = Sphere::new(
         Matrix4::from_translation(Vector3::new(-0.5, 1., 0.5)),
-        sphere_material,
+        sphere_material.clone().with_color(RGB::new(0.1, 1., 0.5)),
         None,
     );
-    middle.material.pattern = Pattern::Solid(RGB::new(0.1, 1., 0.5));
 
-    let mut right = Sphere::new(
+    let right = Sphere::new(
         Matrix4::from_translation(Vector3::new(1.5, 0.5, -0.5)) * Matrix4::from_scale(0.5),
-        sphere_material,
+        sphere_material.clone().with_color(RGB::new(0.5, 1., 0.1)),
         None,
     );
-    right.material.pattern = Pattern::Solid(RGB::new(0.5, 1., 0.1));
 
-    let mut left = Sphere::new(
+    let left = Sphere::new(
         Matrix4::from_translation(Vector3::new(-1.5, 0.33, -0.75)) * Matrix4::from_scale(0.33),
-        sphere_material,
+        sphere_material.with_color(RGB::new(1., 0.8, 0.1)),
         None,
     );
-    left.material.pattern = Pattern::Solid(RGB::new(1., 0.8, 0.1));
 
     let light = Light::new(Point3::new(-10., 10., -10.), RGB::new(1., 1., 1.));
     let mut camera = Camera::from(400, 200, FRAC_PI_3);
@@ -61,10 +55,9 @@ fn main() {
             Shape::Sphere(middle),
             Shape::Sphere(right),
         ],
-        5,
     );
 
-    let canvas = camera.render(world);
+    let canvas = camera.render(&world);
     let _ = fs::create_dir("output");
     fs::write("output/plane.ppm", canvas.to_ppm()).expect("Unable to write file");
 }
@@ -1,5 +1,5 @@
 use cgmath::{BaseFloat, InnerSpace, Point3, Vector3};
-use ray_tracer_challenge::canvas::Canvas;
+use ray_tracer_challenge::canvas::{Canvas, Origin};
 use rgb::RGB;
 use std::fs;
 
@@ -32,10 +32,11 @@ fn main() {
     let wind = -0.01 * Vector3::unit_x();
     let environment = Environment { gravity, wind };
     let mut canvas = Canvas::new(900, 550);
+    canvas.origin = Origin::BottomLeft;
 
     while proj.position.y >= 0.0 && proj.position.x >= 0.0 {
-        canvas.pixels[canvas.height - (proj.position.y as usize)][proj.position.x as usize] =
-            RGB::new(1., 0., 0.);
+        let (x, y) = (proj.position.x as usize, proj.position.y as usize);
+        let _ = canvas.set(x, y, RGB::new(1., 0., 0.));
         proj = tick(environment, proj);
     }
     let _ = fs::create_dir("output");
@@ -32,7 +32,7 @@ fn main() {
             let xs = shape.intersect(r);
             if let Some(hit) = hit(&xs) {
                 let point = r.position(hit.t);
-                let object = hit.object;
+                let object = hit.object.borrow();
                 let normal = object.normal_at(point, hit.uv).unwrap();
                 let eye = -r.direction.normalize();
                 canvas.pixels[y][x] = object
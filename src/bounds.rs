@@ -1,4 +1,4 @@
-use crate::ray::Ray;
+use crate::{ray::Ray, scalar::Scalar};
 use cgmath::{BaseFloat, Matrix4, Point3};
 use std::cmp::Ordering::Less;
 
@@ -8,13 +8,33 @@ pub struct Bounds<T> {
     pub maximum: Point3<T>,
 }
 
-fn check_axis<T: BaseFloat>(origin: T, direction: T, minimum: T, maximum: T) -> (T, T) {
+fn check_axis<T: Scalar>(origin: T, direction: T, minimum: T, maximum: T) -> (T, T) {
     let (tmin_numerator, tmax_numerator) = (minimum - origin, maximum - origin);
     let (tmin, tmax) = (tmin_numerator / direction, tmax_numerator / direction);
-    if tmin < tmax {
-        (tmin, tmax)
-    } else {
-        (tmax, tmin)
+    (tmin.simd_min(tmax), tmin.simd_max(tmax))
+}
+
+impl<T: Scalar> Bounds<T> {
+    pub fn check_axes(&self, ray: Ray<T>) -> Bounds<T> {
+        let (xmin, xmax) = check_axis(
+            ray.origin.x,
+            ray.direction.x,
+            self.minimum.x,
+            self.maximum.x,
+        );
+        let (ymin, ymax) = check_axis(
+            ray.origin.y,
+            ray.direction.y,
+            self.minimum.y,
+            self.maximum.y,
+        );
+        let (zmin, zmax) = check_axis(
+            ray.origin.z,
+            ray.direction.z,
+            self.minimum.z,
+            self.maximum.z,
+        );
+        Bounds::new(Point3::new(xmin, ymin, zmin), Point3::new(xmax, ymax, zmax))
     }
 }
 
@@ -75,28 +95,6 @@ impl<T: BaseFloat> Bounds<T> {
         ]
     }
 
-    pub fn check_axes(&self, ray: Ray<T>) -> Bounds<T> {
-        let (xmin, xmax) = check_axis(
-            ray.origin.x,
-            ray.direction.x,
-            self.minimum.x,
-            self.maximum.x,
-        );
-        let (ymin, ymax) = check_axis(
-            ray.origin.y,
-            ray.direction.y,
-            self.minimum.y,
-            self.maximum.y,
-        );
-        let (zmin, zmax) = check_axis(
-            ray.origin.z,
-            ray.direction.z,
-            self.minimum.z,
-            self.maximum.z,
-        );
-        Bounds::new(Point3::new(xmin, ymin, zmin), Point3::new(xmax, ymax, zmax))
-    }
-
     pub fn transform(&self, transform: Matrix4<T>) -> Vec<Point3<T>> {
         self.all_points()
             .iter()
@@ -113,6 +111,53 @@ impl<T: BaseFloat> Bounds<T> {
         let (tmin, tmax) = self.check_axes(ray).minmax();
         tmin <= tmax
     }
+
+    // The smallest box holding both boxes.
+    pub fn merge(&self, other: &Bounds<T>) -> Bounds<T> {
+        let (a, b) = (self, other);
+        Bounds::new(
+            Point3::new(
+                a.minimum.x.min(b.minimum.x),
+                a.minimum.y.min(b.minimum.y),
+                a.minimum.z.min(b.minimum.z),
+            ),
+            Point3::new(
+                a.maximum.x.max(b.maximum.x),
+                a.maximum.y.max(b.maximum.y),
+                a.maximum.z.max(b.maximum.z),
+            ),
+        )
+    }
+
+    // Points on the faces count as inside.
+    pub fn contains_point(&self, point: Point3<T>) -> bool {
+        (0..3).all(|axis| self.minimum[axis] <= point[axis] && point[axis] <= self.maximum[axis])
+    }
+
+    pub fn contains_box(&self, other: &Bounds<T>) -> bool {
+        self.contains_point(other.minimum) && self.contains_point(other.maximum)
+    }
+
+    // Halve the box across its longest axis, preferring x, then y, on ties.
+    pub fn split(&self) -> (Bounds<T>, Bounds<T>) {
+        let size = self.maximum - self.minimum;
+        let greatest = size.x.max(size.y).max(size.z);
+        let axis = if greatest == size.x {
+            0
+        } else if greatest == size.y {
+            1
+        } else {
+            2
+        };
+        let middle = self.minimum[axis] + size[axis] / T::from(2).unwrap();
+        let (mut left_max, mut right_min) = (self.maximum, self.minimum);
+        left_max[axis] = middle;
+        right_min[axis] = middle;
+        (
+            Bounds::new(self.minimum, left_max),
+            Bounds::new(right_min, self.maximum),
+        )
+    }
 }
 
 #[cfg(test)]
@@ -145,4 +190,117 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn check_axes() {
+        // The slab test as written before it was made generic over Scalar.
+        let reference = |origin: f32, direction: f32, minimum: f32, maximum: f32| {
+            let (tmin, tmax) = (
+                (minimum - origin) / direction,
+                (maximum - origin) / direction,
+            );
+            if tmin < tmax {
+                (tmin, tmax)
+            } else {
+                (tmax, tmin)
+            }
+        };
+        let b = Bounds::<f32>::new(Point3::new(-1., -2., 0.5), Point3::new(3., 1., 2.));
+        for ray in [
+            Ray::new(Point3::new(5., 0.5, 1.), Vector3::new(-1., 0.2, 0.)),
+            Ray::new(Point3::new(0., -4., 0.), Vector3::new(0.1, 1., 0.3)),
+            Ray::new(Point3::new(2., 2., -2.), Vector3::new(0., 0., 1.)),
+        ] {
+            let checked = b.check_axes(ray);
+            for axis in 0..3 {
+                let (tmin, tmax) = reference(
+                    ray.origin[axis],
+                    ray.direction[axis],
+                    b.minimum[axis],
+                    b.maximum[axis],
+                );
+                assert_eq!((checked.minimum[axis], checked.maximum[axis]), (tmin, tmax));
+            }
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let a = Bounds::<f32>::new(Point3::new(-5., -2., 0.), Point3::new(7., 4., 4.));
+        let b = Bounds::new(Point3::new(8., -7., -2.), Point3::new(14., 2., 8.));
+        assert_eq!(
+            a.merge(&b),
+            Bounds::new(Point3::new(-5., -7., -2.), Point3::new(14., 4., 8.))
+        );
+        assert_eq!(b.merge(&a), a.merge(&b));
+        assert_eq!(a.merge(&a), a);
+    }
+
+    #[test]
+    fn contains_point() {
+        let b = Bounds::<f32>::new(Point3::new(5., -2., 0.), Point3::new(11., 4., 7.));
+        for (point, inside) in [
+            (Point3::new(5., -2., 0.), true),
+            (Point3::new(11., 4., 7.), true),
+            (Point3::new(8., 1., 3.), true),
+            (Point3::new(3., 0., 3.), false),
+            (Point3::new(8., -4., 3.), false),
+            (Point3::new(8., 1., -1.), false),
+            (Point3::new(13., 1., 3.), false),
+            (Point3::new(8., 5., 3.), false),
+            (Point3::new(8., 1., 8.), false),
+        ] {
+            assert_eq!(b.contains_point(point), inside);
+        }
+    }
+
+    #[test]
+    fn contains_box() {
+        let b = Bounds::<f32>::new(Point3::new(5., -2., 0.), Point3::new(11., 4., 7.));
+        for (min, max, inside) in [
+            (Point3::new(5., -2., 0.), Point3::new(11., 4., 7.), true),
+            (Point3::new(6., -1., 1.), Point3::new(10., 3., 6.), true),
+            (Point3::new(4., -3., -1.), Point3::new(10., 3., 6.), false),
+            (Point3::new(6., -1., 1.), Point3::new(12., 5., 8.), false),
+        ] {
+            assert_eq!(b.contains_box(&Bounds::new(min, max)), inside);
+        }
+        // Equal boxes contain each other.
+        assert!(b.contains_box(&b));
+    }
+
+    #[test]
+    fn split() {
+        for (min, max, left_max, right_min) in [
+            // A perfect cube splits along x.
+            (
+                Point3::new(-1., -4., -5.),
+                Point3::new(9., 6., 5.),
+                Point3::new(4., 6., 5.),
+                Point3::new(4., -4., -5.),
+            ),
+            (
+                Point3::new(-1., -2., -3.),
+                Point3::new(9., 5.5, 3.),
+                Point3::new(4., 5.5, 3.),
+                Point3::new(4., -2., -3.),
+            ),
+            (
+                Point3::new(-1., -2., -3.),
+                Point3::new(5., 8., 3.),
+                Point3::new(5., 3., 3.),
+                Point3::new(-1., 3., -3.),
+            ),
+            (
+                Point3::new(-1., -2., -3.),
+                Point3::new(5., 3., 7.),
+                Point3::new(5., 3., 2.),
+                Point3::new(-1., -2., 2.),
+            ),
+        ] {
+            let (left, right) = Bounds::<f32>::new(min, max).split();
+            assert_eq!(left, Bounds::new(min, left_max));
+            assert_eq!(right, Bounds::new(right_min, max));
+        }
+    }
 }
@@ -1,8 +1,71 @@
-use crate::{canvas::Canvas, ray::Ray, world::World};
-use cgmath::{BaseFloat, EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix};
+use crate::{canvas::Canvas, intersection::hit, ray::Ray, world::World};
+use cgmath::{
+    BaseFloat, EuclideanSpace, InnerSpace, Matrix4, Point2, Point3, SquareMatrix, Vector2,
+};
 use rgb::RGB;
+use std::f64::consts::PI;
 
-#[derive(derive_more::Constructor)]
+// The shape of the lens aperture, which shows in out-of-focus highlights.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BokehShape {
+    Disk,
+    // A regular polygon with the given number of sides.
+    Polygon(usize),
+}
+
+impl BokehShape {
+    // Map (u, v) in the unit square uniformly onto the aperture of radius one.
+    pub fn sample<T: BaseFloat>(&self, u: T, v: T) -> Point2<T> {
+        let two_pi = T::from(2. * PI).unwrap();
+        let r = u.sqrt();
+        match *self {
+            BokehShape::Disk => {
+                let theta = two_pi * v;
+                Point2::new(r * theta.cos(), r * theta.sin())
+            }
+            BokehShape::Polygon(sides) => {
+                // Pick a triangle of the fan around the center, then a point along its outer edge.
+                let n = T::from(sides).unwrap();
+                let k = (v * n).floor().min(n - T::one());
+                let s = v * n - k;
+                let vertex = |i: T| {
+                    let angle = two_pi * i / n;
+                    Vector2::new(angle.cos(), angle.sin())
+                };
+                Point2::from_vec((vertex(k) * (T::one() - s) + vertex(k + T::one()) * s) * r)
+            }
+        }
+    }
+}
+
+// Bundled render settings for render_preset, from quick previews to final images.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quality {
+    Draft,
+    Medium,
+    High,
+}
+
+impl Quality {
+    // Samples along each side of the pixel grid, then the combined, reflection and refraction depths.
+    fn settings(self) -> (usize, u8, u8, u8) {
+        match self {
+            Quality::Draft => (1, 1, 1, 1),
+            Quality::Medium => (2, 3, 3, 3),
+            Quality::High => (4, 8, 5, 8),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RenderStats {
+    pub primary_rays: usize,
+    // Samples whose NaN or infinite channels the world replaced with 0.
+    pub invalid_colors: usize,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Camera<T> {
     pub hsize: usize,
     pub vsize: usize,
@@ -11,6 +74,13 @@ pub struct Camera<T> {
     pub half_width: T,
     pub half_height: T,
     pub pixel_size: T,
+    // The radius of the lens; zero makes a pinhole camera with everything in focus.
+    pub aperture: T,
+    pub focal_distance: T,
+    pub bokeh: BokehShape,
+    pub aperture_samples: usize,
+    // How strongly the image darkens towards the edges with cos^4 falloff; zero turns it off.
+    pub vignetting: T,
 }
 
 impl<T: BaseFloat + Default + std::fmt::Display> Camera<T> {
@@ -33,44 +103,251 @@ impl<T: BaseFloat + Default + std::fmt::Display> Camera<T> {
             half_width,
             half_height,
             pixel_size: half_width * two / h,
+            aperture: T::zero(),
+            focal_distance: T::one(),
+            bokeh: BokehShape::Disk,
+            aperture_samples: 16,
+            vignetting: T::zero(),
         }
     }
 
-    pub fn ray_for_pixel(&self, px: usize, py: usize) -> Ray<T> {
+    // The center of the pixel on the image plane at z = -1 in camera space.
+    fn pixel_point(&self, px: usize, py: usize) -> Point3<T> {
         let half = T::from(0.5).unwrap();
-        let xoffset = (T::from(px).unwrap() + half) * self.pixel_size;
-        let yoffset = (T::from(py).unwrap() + half) * self.pixel_size;
-        let world_x = self.half_width - xoffset;
-        let world_y = self.half_height - yoffset;
-        let inverse = self.transform.invert().unwrap();
-        let negone = T::from(-1).unwrap();
-        let pixel = Point3::from_homogeneous(
-            inverse * Point3::new(world_x, world_y, negone).to_homogeneous(),
-        );
-        let origin = Point3::from_homogeneous(inverse * Point3::origin().to_homogeneous());
-        Ray::new(
-            Point3::origin() + origin.to_vec(),
-            (pixel - origin).normalize(),
+        self.image_point(T::from(px).unwrap() + half, T::from(py).unwrap() + half)
+    }
+
+    // The point at (x, y), measured in pixels from the top left corner of the image.
+    fn image_point(&self, x: T, y: T) -> Point3<T> {
+        let xoffset = x * self.pixel_size;
+        let yoffset = y * self.pixel_size;
+        Point3::new(
+            self.half_width - xoffset,
+            self.half_height - yoffset,
+            -T::one(),
         )
     }
 
-    pub fn render(&self, mut w: World<T>) -> Canvas<RGB<T>> {
+    // The share of light left at a point on the image plane after vignetting.
+    fn vignette(&self, point: Point3<T>) -> T {
+        let cos_theta = T::one() / point.to_vec().magnitude();
+        T::one() - self.vignetting * (T::one() - cos_theta.powi(4))
+    }
+
+    // None if the camera transform is singular.
+    fn ray_through(&self, from: Point3<T>, to: Point3<T>) -> Option<Ray<T>> {
+        let inverse = self.transform.invert()?;
+        let to = Point3::from_homogeneous(inverse * to.to_homogeneous());
+        let origin = Point3::from_homogeneous(inverse * from.to_homogeneous());
+        Some(Ray::new(
+            Point3::origin() + origin.to_vec(),
+            (to - origin).normalize(),
+        ))
+    }
+
+    pub fn ray_for_pixel(&self, px: usize, py: usize) -> Option<Ray<T>> {
+        self.ray_through(Point3::origin(), self.pixel_point(px, py))
+    }
+
+    // Rays from points spread over the aperture, all converging on the focal plane.
+    // Empty if the camera transform is singular.
+    pub fn rays_for_pixel(&self, px: usize, py: usize) -> Vec<Ray<T>> {
+        if self.aperture == T::zero() {
+            return self.ray_for_pixel(px, py).into_iter().collect();
+        }
+        let focus = Point3::from_vec(self.pixel_point(px, py).to_vec() * self.focal_distance);
+        let n = T::from(self.aperture_samples).unwrap();
+        let golden_ratio = T::from(0.5 * (5.0_f64.sqrt() - 1.)).unwrap();
+        (0..self.aperture_samples)
+            .filter_map(|i| {
+                let i = T::from(i).unwrap();
+                let u = (i + T::from(0.5).unwrap()) / n;
+                let v = (i * golden_ratio).fract();
+                let lens = self.bokeh.sample(u, v) * self.aperture;
+                self.ray_through(Point3::new(lens.x, lens.y, T::zero()), focus)
+            })
+            .collect()
+    }
+
+    /// Render the world as seen through the camera, one pixel per ray (or aperture sample).
+    ///
+    /// ```
+    /// use cgmath::{EuclideanSpace, Matrix4, Point3, Vector3};
+    /// use ray_tracer_challenge::{camera::Camera, world::World};
+    /// use std::f32::consts::FRAC_PI_2;
+    ///
+    /// let w = World::default();
+    /// let mut c = Camera::from(11, 11, FRAC_PI_2);
+    /// c.transform = Matrix4::look_at_rh(
+    ///     Point3::new(0., 0., -5.),
+    ///     Point3::origin(),
+    ///     Vector3::unit_y(),
+    /// );
+    /// let image = c.render(&w);
+    /// assert_eq!((image.width, image.height), (11, 11));
+    /// ```
+    pub fn render(&self, w: &World<T>) -> Canvas<RGB<T>> {
+        self.render_with_progress(w, |_, _| {})
+    }
+
+    // Like render, calling on_row(completed_rows, vsize) after each scanline.
+    pub fn render_with_progress(
+        &self,
+        w: &World<T>,
+        mut on_row: impl FnMut(usize, usize),
+    ) -> Canvas<RGB<T>> {
         let mut image = Canvas::new(self.hsize, self.vsize);
+        self.render_rows(w, &mut image, &mut on_row);
+        image
+    }
+
+    // Like render, but reuse a canvas of the camera's size instead of allocating one.
+    pub fn render_into(&self, w: &World<T>, image: &mut Canvas<RGB<T>>) {
+        assert_eq!(
+            (image.width, image.height),
+            (self.hsize, self.vsize),
+            "The canvas must match the camera's size."
+        );
+        self.render_rows(w, image, &mut |_, _| {});
+    }
+
+    fn render_rows(
+        &self,
+        w: &World<T>,
+        image: &mut Canvas<RGB<T>>,
+        on_row: &mut impl FnMut(usize, usize),
+    ) {
         for y in 0..self.vsize {
             for x in 0..self.hsize {
-                let ray = self.ray_for_pixel(x, y);
-                image.pixels[y][x] = w.color_at(ray);
+                image.pixels[y][x] = self.color_for_pixel(w, x, y);
+            }
+            on_row(y + 1, self.vsize);
+        }
+    }
+
+    // Render only the pixels in [x0, x1) x [y0, y1), leaving the rest of the full-size canvas black.
+    pub fn render_region(
+        &self,
+        w: &World<T>,
+        x0: usize,
+        y0: usize,
+        x1: usize,
+        y1: usize,
+    ) -> Canvas<RGB<T>> {
+        let mut image = Canvas::new(self.hsize, self.vsize);
+        for y in y0..y1.min(self.vsize) {
+            for x in x0..x1.min(self.hsize) {
+                image.pixels[y][x] = self.color_for_pixel(w, x, y);
             }
         }
         image
     }
+
+    // Render with the antialiasing and recursion depths of the preset, restoring the world's
+    // depths afterwards. The canvas comes back gamma encoded for 2.2, ready for to_ppm.
+    pub fn render_preset(
+        &self,
+        w: &mut World<T>,
+        quality: Quality,
+    ) -> (Canvas<RGB<T>>, RenderStats) {
+        let (samples, depth, reflection_depth, refraction_depth) = quality.settings();
+        let depths = (w.max_depth, w.max_reflection_depth, w.max_refraction_depth);
+        w.max_depth = depth;
+        w.max_reflection_depth = reflection_depth;
+        w.max_refraction_depth = refraction_depth;
+        let mut image = Canvas::new(self.hsize, self.vsize);
+        let mut stats = RenderStats::default();
+        let invalid_colors = w.invalid_colors();
+        let n = T::from(samples).unwrap();
+        let half = T::from(0.5).unwrap();
+        let encode = |c: T| c.max(T::zero()).powf(T::one() / T::from(2.2).unwrap());
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                let mut color = RGB::default();
+                for sy in 0..samples {
+                    for sx in 0..samples {
+                        let point = self.image_point(
+                            T::from(x).unwrap() + (T::from(sx).unwrap() + half) / n,
+                            T::from(y).unwrap() + (T::from(sy).unwrap() + half) / n,
+                        );
+                        if let Some(ray) = self.ray_through(Point3::origin(), point) {
+                            color += w.color_at(ray);
+                            stats.primary_rays += 1;
+                        }
+                    }
+                }
+                let color = color / (n * n) * self.vignette(self.pixel_point(x, y));
+                image.pixels[y][x] = RGB::new(encode(color.r), encode(color.g), encode(color.b));
+            }
+        }
+        (w.max_depth, w.max_reflection_depth, w.max_refraction_depth) = depths;
+        stats.invalid_colors = w.invalid_colors() - invalid_colors;
+        (image, stats)
+    }
+
+    fn color_for_pixel(&self, w: &World<T>, x: usize, y: usize) -> RGB<T> {
+        let rays = self.rays_for_pixel(x, y);
+        if rays.is_empty() {
+            return RGB::default();
+        }
+        let count = T::from(rays.len()).unwrap();
+        let mut color = RGB::default();
+        for ray in rays {
+            color += w.color_at(ray);
+        }
+        color / count * self.vignette(self.pixel_point(x, y))
+    }
+
+    // 1 where the primary hit lies within tolerance of the focal plane and 0 elsewhere.
+    pub fn focal_plane_mask(&self, w: &World<T>, tolerance: T) -> Canvas<T> {
+        let mut mask = Canvas::new(self.hsize, self.vsize);
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                let ray = self.ray_for_pixel(x, y);
+                if let Some(i) = ray.and_then(|ray| hit(&w.intersect(ray))) {
+                    // The pixel lies at depth one, so this converts the distance along the ray to depth.
+                    let depth = i.t / self.pixel_point(x, y).to_vec().magnitude();
+                    if (depth - self.focal_distance).abs() <= tolerance {
+                        mask.pixels[y][x] = T::one();
+                    }
+                }
+            }
+        }
+        mask
+    }
+
+    // 1 where a ray hits anything and 0 elsewhere, averaged over samples x samples rays per pixel.
+    pub fn render_mask(&self, w: &World<T>, samples: usize) -> Canvas<T> {
+        let mut mask = Canvas::new(self.hsize, self.vsize);
+        let n = T::from(samples).unwrap();
+        let half = T::from(0.5).unwrap();
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                let mut coverage = T::zero();
+                for sy in 0..samples {
+                    for sx in 0..samples {
+                        let point = self.image_point(
+                            T::from(x).unwrap() + (T::from(sx).unwrap() + half) / n,
+                            T::from(y).unwrap() + (T::from(sy).unwrap() + half) / n,
+                        );
+                        let ray = self.ray_through(Point3::origin(), point);
+                        if ray.is_some_and(|ray| hit(&w.intersect(ray)).is_some()) {
+                            coverage += T::one();
+                        }
+                    }
+                }
+                mask.pixels[y][x] = coverage / (n * n);
+            }
+        }
+        mask
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use cgmath::{assert_abs_diff_eq, assert_relative_eq, Quaternion, Rad, Rotation3, Vector3};
-    use std::f32::consts::{FRAC_1_SQRT_2, FRAC_PI_2, FRAC_PI_4};
+    use std::f32::consts::{FRAC_1_SQRT_2, FRAC_PI_2, FRAC_PI_3, FRAC_PI_4, FRAC_PI_6};
 
     #[test]
     fn new() {
@@ -82,11 +359,11 @@ mod tests {
     fn ray_for_pixel() {
         let mut c = Camera::from(201, 101, FRAC_PI_2);
         assert_relative_eq!(
-            c.ray_for_pixel(100, 50),
+            c.ray_for_pixel(100, 50).unwrap(),
             Ray::new(Point3::origin(), -Vector3::unit_z())
         );
         assert_relative_eq!(
-            c.ray_for_pixel(0, 0),
+            c.ray_for_pixel(0, 0).unwrap(),
             Ray::new(Point3::origin(), Vector3::new(0.66519, 0.33259, -0.66851)),
             max_relative = 0.00001
         );
@@ -95,7 +372,7 @@ mod tests {
             c.transform = Matrix4::from(Quaternion::from_angle_y(Rad(FRAC_PI_4)))
                 * Matrix4::from_translation(-point.to_vec());
             assert_abs_diff_eq!(
-                c.ray_for_pixel(100, 50),
+                c.ray_for_pixel(100, 50).unwrap(),
                 Ray::new(point, Vector3::new(FRAC_1_SQRT_2, 0., -FRAC_1_SQRT_2)),
                 epsilon = 0.000001
             );
@@ -111,11 +388,203 @@ mod tests {
             Point3::origin(),
             Vector3::unit_y(),
         );
-        let image = c.render(w);
+        let image = c.render(&w);
         approx::assert_relative_eq!(
             image.pixels[5][5],
             RGB::new(0.38066, 0.47583, 0.2855),
             max_relative = 0.0001
         );
     }
+
+    #[test]
+    fn render_into() {
+        let w = World::default();
+        let mut c = Camera::from(11, 11, FRAC_PI_2);
+        c.transform = Matrix4::look_at_rh(
+            Point3::new(0., 0., -5.),
+            Point3::origin(),
+            Vector3::unit_y(),
+        );
+        let expected = c.render(&w);
+        let mut image = Canvas::new(11, 11);
+        image.pixels[0][0] = RGB::new(9., 9., 9.);
+        for _ in 0..2 {
+            c.render_into(&w, &mut image);
+            assert_eq!(image, expected);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn render_into_mismatch() {
+        Camera::<f32>::from(11, 11, FRAC_PI_2)
+            .render_into(&World::default(), &mut Canvas::new(10, 11));
+    }
+
+    #[test]
+    fn rays_for_pixel() {
+        let mut c = Camera::from(201, 101, FRAC_PI_2);
+        assert_eq!(c.rays_for_pixel(0, 0), vec![c.ray_for_pixel(0, 0).unwrap()]);
+        c.aperture = 0.1;
+        c.focal_distance = 5.;
+        let focus = Point3::origin() + c.ray_for_pixel(0, 0).unwrap().direction * 5. / 0.66851;
+        let rays = c.rays_for_pixel(0, 0);
+        assert_eq!(rays.len(), c.aperture_samples);
+        for ray in rays {
+            let t = (-5. - ray.origin.z) / ray.direction.z;
+            assert_relative_eq!(ray.position(t), focus, max_relative = 0.0001);
+        }
+    }
+
+    #[test]
+    fn bokeh_shape() {
+        let apothem = FRAC_PI_6.cos();
+        // The distance of a point past the nearest hexagon edge.
+        let outside = |p: Point2<f32>| {
+            (0..6)
+                .map(|k| {
+                    let angle = (k as f32 + 0.5) * FRAC_PI_3;
+                    p.x * angle.cos() + p.y * angle.sin() - apothem
+                })
+                .fold(f32::MIN, f32::max)
+        };
+        let grid = (0..20).flat_map(|i| (0..20).map(move |j| (i as f32 / 19., j as f32 / 19.)));
+        for (u, v) in grid.clone() {
+            assert!(outside(BokehShape::Polygon(6).sample(u, v)) <= 0.00001);
+        }
+        assert!(grid
+            .map(|(u, v)| outside(BokehShape::Disk.sample(u, v)))
+            .any(|d| d > 0.01));
+    }
+
+    #[test]
+    fn render_mask() {
+        let w = World::default();
+        let mut c = Camera::from(11, 11, FRAC_PI_2);
+        c.transform = Matrix4::look_at_rh(
+            Point3::new(0., 0., -5.),
+            Point3::origin(),
+            Vector3::unit_y(),
+        );
+        for samples in [1, 3] {
+            let mask = c.render_mask(&w, samples);
+            assert_eq!(mask.pixels[5][5], 1.);
+            for (x, y) in [(0, 0), (10, 0), (0, 10), (10, 10)] {
+                assert_eq!(mask.pixels[y][x], 0.);
+            }
+        }
+        // Edge pixels are partially covered with antialiasing.
+        assert!(c
+            .render_mask(&w, 4)
+            .pixels
+            .iter()
+            .flatten()
+            .any(|&m| m > 0. && m < 1.));
+    }
+
+    #[test]
+    fn focal_plane_mask() {
+        let w = World::default();
+        let mut c = Camera::from(11, 11, FRAC_PI_2);
+        c.transform = Matrix4::look_at_rh(
+            Point3::new(0., 0., -5.),
+            Point3::origin(),
+            Vector3::unit_y(),
+        );
+        // The front of the outer sphere is 4 units away.
+        c.focal_distance = 4.;
+        let mask = c.focal_plane_mask(&w, 0.01);
+        assert_eq!(mask.pixels[5][5], 1.);
+        assert_eq!(mask.pixels[0][0], 0.);
+        c.focal_distance = 6.;
+        assert_eq!(c.focal_plane_mask(&w, 0.01).pixels[5][5], 0.);
+    }
+
+    #[test]
+    fn render_with_progress() {
+        let w = World::default();
+        let c = Camera::from(4, 3, FRAC_PI_2);
+        let mut progress = Vec::new();
+        let image = c.render_with_progress(&w, |done, total| progress.push((done, total)));
+        assert_eq!(progress, vec![(1, 3), (2, 3), (3, 3)]);
+        assert_eq!(image, c.render(&w));
+    }
+
+    #[test]
+    fn render_region() {
+        let w = World::default();
+        let mut c = Camera::from(11, 11, FRAC_PI_2);
+        c.transform = Matrix4::look_at_rh(
+            Point3::new(0., 0., -5.),
+            Point3::origin(),
+            Vector3::unit_y(),
+        );
+        let mut image = Canvas::new(11, 11);
+        for (x0, y0, x1, y1) in [(0, 0, 5, 5), (5, 0, 20, 5), (0, 5, 5, 11), (5, 5, 11, 20)] {
+            let tile = c.render_region(&w, x0, y0, x1, y1);
+            assert_eq!((tile.width, tile.height), (11, 11));
+            for (row, tile_row) in image.pixels.iter_mut().zip(tile.pixels) {
+                for (pixel, tile_pixel) in row.iter_mut().zip(tile_row) {
+                    *pixel += tile_pixel;
+                }
+            }
+        }
+        assert_eq!(image, c.render(&w));
+    }
+
+    #[test]
+    fn render_preset() {
+        let mut w = World::default();
+        let mut c = Camera::from(11, 11, FRAC_PI_2);
+        c.transform = Matrix4::look_at_rh(
+            Point3::new(0., 0., -5.),
+            Point3::origin(),
+            Vector3::unit_y(),
+        );
+        let (draft, draft_stats) = c.render_preset(&mut w, Quality::Draft);
+        let (high, high_stats) = c.render_preset(&mut w, Quality::High);
+        assert_eq!(draft_stats.primary_rays, 11 * 11);
+        assert_eq!(high_stats.primary_rays, 11 * 11 * 16);
+        assert_eq!((high.width, high.height), (draft.width, draft.height));
+        // The center pixel is the linear shade_hit color, gamma encoded.
+        approx::assert_relative_eq!(
+            draft.pixels[5][5].g,
+            0.47583_f32.powf(1. / 2.2),
+            max_relative = 0.0001
+        );
+        assert_eq!(
+            w.max_reflection_depth,
+            World::<f32>::default().max_reflection_depth
+        );
+    }
+
+    #[test]
+    fn vignetting() {
+        let mut w = World::<f32>::default();
+        w.objects.clear();
+        w.background = RGB::new(1., 1., 1.);
+        let mut c = Camera::from(9, 9, FRAC_PI_2);
+        let image = c.render(&w);
+        assert!(image.pixels.iter().flatten().all(|&p| p == w.background));
+        c.vignetting = 1.;
+        let image = c.render(&w);
+        let center = image.pixels[4][4];
+        assert_relative_eq!(center.r, 1., max_relative = 0.01);
+        for (x, y) in [(0, 0), (8, 0), (0, 8), (8, 8)] {
+            assert!(image.pixels[y][x].r < center.r);
+        }
+        // A pixel at 45 degrees off the axis gets cos^4 of the light.
+        assert_relative_eq!(c.vignette(Point3::new(1., 0., -1.)), 0.25);
+    }
+
+    #[test]
+    fn singular_transform() {
+        let w = World::default();
+        let mut c = Camera::from(3, 3, FRAC_PI_2);
+        c.transform = Matrix4::from_scale(0.);
+        assert_eq!(c.ray_for_pixel(1, 1), None);
+        assert_eq!(c.rays_for_pixel(1, 1), vec![]);
+        let image = c.render(&w);
+        assert!(image.pixels.iter().flatten().all(|&p| p == RGB::default()));
+    }
 }
@@ -1,49 +1,307 @@
 use cgmath::BaseFloat;
 use rgb::RGB;
 
+// How texture coordinates outside [0, 1] are brought back onto the canvas.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl WrapMode {
+    pub fn wrap<F: BaseFloat>(&self, t: F) -> F {
+        match self {
+            WrapMode::Repeat => t - t.floor(),
+            WrapMode::Clamp => t.max(F::zero()).min(F::one()),
+            WrapMode::Mirror => {
+                let two = F::from(2).unwrap();
+                let t = t - (t / two).floor() * two;
+                if t > F::one() {
+                    two - t
+                } else {
+                    t
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseError {
+    // The text does not start with the P3 magic number.
+    NotP3,
+    InvalidNumber(String),
+    // The maximum channel value is 0, so channels cannot be scaled by it.
+    ZeroMaximum,
+    // The text ends before the header or all the pixels are read.
+    MissingData,
+}
+
+// Where get and set put pixel (0, 0).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Origin {
+    #[default]
+    TopLeft,
+    // Math-style coordinates with y growing upwards.
+    BottomLeft,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Canvas<T> {
     pub width: usize,
     pub height: usize,
-    pub pixels: Vec<Vec<T>>, // column-major
+    // Stored top row first whatever the origin, so pixel (x, y) from the top left is pixels[y][x].
+    pub pixels: Vec<Vec<T>>,
+    pub origin: Origin,
+}
+
+impl<T: Clone + Default> Canvas<T> {
+    pub fn new(width: usize, height: usize) -> Canvas<T> {
+        Canvas::<T> {
+            width,
+            height,
+            pixels: vec![vec!(T::default(); width); height],
+            origin: Origin::TopLeft,
+        }
+    }
+}
+
+impl<T> Canvas<T> {
+    // The storage row of y under the canvas origin, if it is on the canvas.
+    fn row(&self, y: usize) -> Option<usize> {
+        match self.origin {
+            Origin::TopLeft => Some(y),
+            Origin::BottomLeft => self.height.checked_sub(y + 1),
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<&T> {
+        let row = self.row(y)?;
+        self.pixels.get(row).and_then(|row| row.get(x))
+    }
+
+    #[allow(clippy::result_unit_err)]
+    pub fn set(&mut self, x: usize, y: usize, c: T) -> Result<(), ()> {
+        let row = self.row(y).ok_or(())?;
+        let pixel = self
+            .pixels
+            .get_mut(row)
+            .and_then(|row| row.get_mut(x))
+            .ok_or(())?;
+        *pixel = c;
+        Ok(())
+    }
+}
+
+impl<T: Clone> Canvas<T> {
+    // The nearest pixel to (u, v), where (0, 0) is the bottom left and (1, 1) the top right.
+    // An empty canvas has no pixel to sample.
+    pub fn sample_wrapped<F: BaseFloat>(&self, u: F, v: F, mode: WrapMode) -> Option<T> {
+        if self.width == 0 || self.height == 0 {
+            return None;
+        }
+        let last = |n: usize| F::from(n - 1).unwrap();
+        let x = (mode.wrap(u) * last(self.width)).round();
+        let y = ((F::one() - mode.wrap(v)) * last(self.height)).round();
+        Some(self.pixels[y.to_usize().unwrap()][x.to_usize().unwrap()].clone())
+    }
+}
+
+fn to_byte<T: BaseFloat>(c: T) -> u8 {
+    // T.clamp(T::zero(), T::one()) is not available
+    (c.max(T::zero()).min(T::one()) * num_traits::cast(u8::MAX).unwrap())
+        .round()
+        .to_u8()
+        .unwrap()
 }
 
-impl<T: BaseFloat + Default> Canvas<RGB<T>> {
-    pub fn new(width: usize, height: usize) -> Canvas<RGB<T>> {
-        Canvas::<RGB<T>> {
+impl<T: BaseFloat> Canvas<RGB<T>> {
+    // Rec. 709 luminance of each pixel.
+    pub fn to_grayscale(&self) -> Canvas<T> {
+        let (r, g, b) = (
+            T::from(0.2126).unwrap(),
+            T::from(0.7152).unwrap(),
+            T::from(0.0722).unwrap(),
+        );
+        Canvas {
+            width: self.width,
+            height: self.height,
+            origin: self.origin,
+            pixels: self
+                .pixels
+                .iter()
+                .map(|row| row.iter().map(|c| c.r * r + c.g * g + c.b * b).collect())
+                .collect(),
+        }
+    }
+
+    pub fn to_ppm_binary(&self) -> Vec<u8> {
+        let mut ppm = format!("P6\n{} {}\n{}\n", self.width, self.height, u8::MAX).into_bytes();
+        for c in self.pixels.iter().flatten() {
+            ppm.extend([to_byte(c.r), to_byte(c.g), to_byte(c.b)]);
+        }
+        ppm
+    }
+
+    // Parse a plain (P3) PPM, scaling channels by the maximum value into [0, 1].
+    pub fn from_ppm(text: &str) -> Result<Canvas<RGB<T>>, ParseError> {
+        let mut tokens = text
+            .lines()
+            .flat_map(|l| l.split('#').next().unwrap_or_default().split_whitespace());
+        if tokens.next() != Some("P3") {
+            return Err(ParseError::NotP3);
+        }
+        let mut next = || {
+            let token = tokens.next().ok_or(ParseError::MissingData)?;
+            token
+                .parse::<usize>()
+                .map_err(|_| ParseError::InvalidNumber(token.to_string()))
+        };
+        let width = next()?;
+        let height = next()?;
+        let max = match next()? {
+            0 => return Err(ParseError::ZeroMaximum),
+            max => T::from(max).unwrap(),
+        };
+        let mut channel = || next().map(|n| T::from(n).unwrap() / max);
+        let mut canvas = Canvas {
             width,
             height,
-            pixels: vec![vec!(RGB::default(); width); height],
+            // The header is not trusted to size the storage; rows are added as they are read.
+            pixels: Vec::new(),
+            origin: Origin::TopLeft,
+        };
+        for _ in 0..height {
+            let row = (0..width)
+                .map(|_| Ok(RGB::new(channel()?, channel()?, channel()?)))
+                .collect::<Result<Vec<_>, _>>()?;
+            canvas.pixels.push(row);
+        }
+        Ok(canvas)
+    }
+
+    // Paint the 4-connected region around (x, y) whose channels are all within tolerance of
+    // the color at (x, y), returning how many pixels were painted.
+    pub fn flood_fill(&mut self, x: usize, y: usize, new_color: RGB<T>, tolerance: T) -> usize {
+        let seed = match self.get(x, y) {
+            Some(&c) => c,
+            None => return 0,
+        };
+        let similar = |c: RGB<T>| {
+            (c.r - seed.r).abs() <= tolerance
+                && (c.g - seed.g).abs() <= tolerance
+                && (c.b - seed.b).abs() <= tolerance
+        };
+        // Work in storage rows, where the neighbours are the same whatever the origin.
+        let start = (x, self.row(y).unwrap());
+        let mut visited = vec![vec![false; self.width]; self.height];
+        visited[start.1][start.0] = true;
+        let mut stack = vec![start];
+        let mut count = 0;
+        while let Some((x, y)) = stack.pop() {
+            self.pixels[y][x] = new_color;
+            count += 1;
+            let neighbours = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+            for (nx, ny) in neighbours {
+                if nx < self.width
+                    && ny < self.height
+                    && !visited[ny][nx]
+                    && similar(self.pixels[ny][nx])
+                {
+                    visited[ny][nx] = true;
+                    stack.push((nx, ny));
+                }
+            }
+        }
+        count
+    }
+
+    // The pixels whose Sobel gradient magnitude of luminance exceeds threshold, as (x, y).
+    // Rendering these again at more samples per pixel antialiases edges cheaply.
+    pub fn edge_mask(&self, threshold: T) -> Vec<(usize, usize)> {
+        let gray = self.to_grayscale();
+        // Past the border, repeat the nearest pixel.
+        let at = |x: usize, y: usize, dx: isize, dy: isize| {
+            let x = x.saturating_add_signed(dx).min(self.width - 1);
+            let y = y.saturating_add_signed(dy).min(self.height - 1);
+            *gray.get(x, y).unwrap()
+        };
+        let two = T::from(2).unwrap();
+        let mut mask = Vec::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let p = |dx, dy| at(x, y, dx, dy);
+                let gx = p(1, -1) + p(1, 0) * two + p(1, 1) - p(-1, -1) - p(-1, 0) * two - p(-1, 1);
+                let gy = p(-1, 1) + p(0, 1) * two + p(1, 1) - p(-1, -1) - p(0, -1) * two - p(1, -1);
+                if (gx * gx + gy * gy).sqrt() > threshold {
+                    mask.push((x, y));
+                }
+            }
+        }
+        mask
+    }
+
+    pub fn from_grayscale(gray: &Canvas<T>) -> Canvas<RGB<T>> {
+        Canvas {
+            width: gray.width,
+            height: gray.height,
+            origin: gray.origin,
+            pixels: gray
+                .pixels
+                .iter()
+                .map(|row| row.iter().map(|&l| RGB::new(l, l, l)).collect())
+                .collect(),
         }
     }
 }
 
 impl<T: BaseFloat + std::fmt::Display> Canvas<RGB<T>> {
     pub fn to_ppm(&self) -> String {
-        let mut ppm: String = format!("P3\n{} {}\n{}\n", self.width, self.height, u8::MAX);
+        self.to_ppm_gamma(T::one())
+    }
+
+    pub fn to_ppm_gamma(&self, gamma: T) -> String {
+        let mut ppm = Vec::new();
+        self.write_ppm_gamma(&mut ppm, gamma)
+            .expect("Writing to a Vec cannot fail");
+        String::from_utf8(ppm).unwrap()
+    }
+
+    pub fn write_ppm<W: std::io::Write>(&self, w: &mut W) -> std::io::Result<()> {
+        self.write_ppm_gamma(w, T::one())
+    }
+
+    fn write_ppm_gamma<W: std::io::Write>(&self, w: &mut W, gamma: T) -> std::io::Result<()> {
         const LINE_LEN_LIMIT: usize = 70;
+        let exponent = T::one() / gamma;
+        write!(w, "P3\n{} {}\n{}\n", self.width, self.height, u8::MAX)?;
         for r in &self.pixels {
             let mut line_len = 0;
             for c in r {
                 for cc in [c.r, c.g, c.b] {
-                    // T.clamp(T::zero(), T::one()) is not available
-                    let str = (cc.max(T::zero()).min(T::one())
-                        * num_traits::cast(u8::MAX).unwrap())
-                    .round()
-                    .to_string();
-                    if line_len + str.len() > LINE_LEN_LIMIT {
-                        ppm.pop();
-                        ppm.push('\n');
+                    let str = to_byte(cc.max(T::zero()).powf(exponent)).to_string();
+                    if line_len + 1 + str.len() > LINE_LEN_LIMIT {
+                        w.write_all(b"\n")?;
                         line_len = 0;
+                    } else if line_len > 0 {
+                        w.write_all(b" ")?;
+                        line_len += 1;
                     }
-                    ppm += str.as_str();
-                    ppm.push(' ');
-                    line_len += str.len() + 1;
+                    w.write_all(str.as_bytes())?;
+                    line_len += str.len();
                 }
             }
-            ppm.pop();
-            ppm.push('\n');
+            w.write_all(b"\n")?;
         }
-        ppm
+        Ok(())
     }
 }
 
@@ -96,4 +354,190 @@ mod tests {
 "
         );
     }
+
+    #[test]
+    fn from_ppm() {
+        let mut canvas = Canvas::new(10, 2);
+        canvas.pixels.fill(vec![RGB::new(1., 0.8, 0.6); 10]);
+        canvas.pixels[1][3] = RGB::new(0., 0.2, 0.4);
+        let ppm = canvas.to_ppm();
+        let parsed = Canvas::<RGB<f32>>::from_ppm(&ppm).unwrap();
+        assert_eq!(parsed, canvas);
+        assert_eq!(parsed.to_ppm(), ppm);
+
+        let parsed = Canvas::<RGB<f32>>::from_ppm(
+            "P3\n# made by hand\n2 1\n  10\n10 5 0 # first\n\n0   0\t10\n",
+        )
+        .unwrap();
+        assert_eq!(
+            parsed.pixels,
+            vec![vec![RGB::new(1., 0.5, 0.), RGB::new(0., 0., 1.)]]
+        );
+
+        assert_eq!(
+            Canvas::<RGB<f32>>::from_ppm("P6\n1 1\n255\n"),
+            Err(ParseError::NotP3)
+        );
+        assert_eq!(
+            Canvas::<RGB<f32>>::from_ppm("P3\n1 1\n255\n1 2"),
+            Err(ParseError::MissingData)
+        );
+        assert_eq!(
+            Canvas::<RGB<f32>>::from_ppm("P3\n1 x\n255\n"),
+            Err(ParseError::InvalidNumber("x".to_string()))
+        );
+        assert_eq!(
+            Canvas::<RGB<f32>>::from_ppm("P3\n1 1\n0\n0 0 0\n"),
+            Err(ParseError::ZeroMaximum)
+        );
+        // A huge header with no pixels fails without reserving room for them.
+        assert_eq!(
+            Canvas::<RGB<f32>>::from_ppm(&format!("P3\n1 {}\n255\n", usize::MAX)),
+            Err(ParseError::MissingData)
+        );
+    }
+
+    #[test]
+    fn write_ppm() {
+        let mut canvas = Canvas::new(10, 2);
+        canvas.pixels.fill(vec![RGB::new(1., 0.8, 0.6); 10]);
+        let mut ppm = Vec::new();
+        canvas.write_ppm(&mut ppm).unwrap();
+        assert_eq!(String::from_utf8(ppm).unwrap(), canvas.to_ppm());
+    }
+
+    #[test]
+    fn to_ppm_gamma() {
+        let mut canvas = Canvas::new(1, 1);
+        canvas.pixels[0][0] = RGB::new(0.5, 0., 1.);
+        assert_eq!(canvas.to_ppm_gamma(1.), canvas.to_ppm());
+        // 0.5^(1/2.2) * 255 = 186.08
+        assert_eq!(canvas.to_ppm_gamma(2.2), "P3\n1 1\n255\n186 0 255\n");
+    }
+
+    #[test]
+    fn get_set() {
+        let mut canvas = Canvas::new(3, 2);
+        let r = RGB::new(1., 0., 0.);
+        assert_eq!(canvas.set(2, 1, r), Ok(()));
+        assert_eq!(canvas.pixels[1][2], r);
+        assert_eq!(canvas.get(2, 1), Some(&r));
+        assert_eq!(canvas.get(3, 1), None);
+        assert_eq!(canvas.get(2, 2), None);
+        assert_eq!(canvas.set(1, 2, r), Err(()));
+    }
+
+    #[test]
+    fn origin() {
+        let ppm = |origin| {
+            let mut canvas = Canvas::new(2, 3);
+            canvas.origin = origin;
+            canvas.set(1, 0, RGB::new(1., 1., 1.)).unwrap();
+            assert_eq!(canvas.get(1, 0), Some(&RGB::new(1., 1., 1.)));
+            canvas.to_ppm()
+        };
+        assert_eq!(
+            ppm(Origin::TopLeft),
+            "P3\n2 3\n255\n0 0 0 255 255 255\n0 0 0 0 0 0\n0 0 0 0 0 0\n"
+        );
+        assert_eq!(
+            ppm(Origin::BottomLeft),
+            "P3\n2 3\n255\n0 0 0 0 0 0\n0 0 0 0 0 0\n0 0 0 255 255 255\n"
+        );
+        let mut canvas = Canvas::<f32>::new(2, 3);
+        canvas.origin = Origin::BottomLeft;
+        assert_eq!(canvas.set(0, 3, 1.), Err(()));
+    }
+
+    #[test]
+    fn sample_wrapped() {
+        let mut canvas = Canvas::new(3, 1);
+        canvas.pixels[0] = vec![0., 0.5, 1.];
+        assert_eq!(canvas.sample_wrapped(1.5, 0.5, WrapMode::Repeat), Some(0.5));
+        assert_eq!(canvas.sample_wrapped(1.5, 0.5, WrapMode::Clamp), Some(1.));
+        assert_eq!(canvas.sample_wrapped(1.5, 0.5, WrapMode::Mirror), Some(0.5));
+        assert_eq!(
+            canvas.sample_wrapped(-0.25, 0.5, WrapMode::Repeat),
+            Some(1.)
+        );
+        assert_eq!(canvas.sample_wrapped(-0.25, 0.5, WrapMode::Clamp), Some(0.));
+        assert_eq!(canvas.sample_wrapped(1.9, 0.5, WrapMode::Mirror), Some(0.));
+        let empty = Canvas::<f32>::new(0, 0);
+        assert_eq!(empty.sample_wrapped(0.5, 0.5, WrapMode::Repeat), None);
+    }
+
+    #[test]
+    fn grayscale() {
+        let mut canvas = Canvas::new(2, 1);
+        canvas.pixels[0][0] = RGB::new(1., 0., 0.);
+        canvas.pixels[0][1] = RGB::new(1., 1., 1.);
+        let gray = canvas.to_grayscale();
+        assert_eq!(gray.pixels[0][0], 0.2126);
+        assert_eq!(gray.pixels[0][1], 1.);
+        assert_eq!(
+            Canvas::from_grayscale(&gray).pixels[0][0],
+            RGB::new(0.2126, 0.2126, 0.2126)
+        );
+    }
+
+    #[test]
+    fn to_ppm_binary() {
+        let mut canvas = Canvas::new(2, 2);
+        canvas.pixels[0][0] = RGB::new(1.5, 0., 0.);
+        canvas.pixels[0][1] = RGB::new(0., 0.5, 0.);
+        canvas.pixels[1][1] = RGB::new(-0.5, 0., 1.);
+        let ppm = canvas.to_ppm_binary();
+        let header = b"P6\n2 2\n255\n";
+        assert_eq!(&ppm[..header.len()], header);
+        let pixels = ppm[header.len()..]
+            .chunks(3)
+            .map(|c| RGB::new(c[0], c[1], c[2]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pixels,
+            vec![
+                RGB::new(255, 0, 0),
+                RGB::new(0, 128, 0),
+                RGB::new(0, 0, 0),
+                RGB::new(0, 0, 255)
+            ]
+        );
+    }
+
+    #[test]
+    fn flood_fill() {
+        let white = RGB::new(1., 1., 1.);
+        let red = RGB::new(1., 0., 0.);
+        let mut c = Canvas::<RGB<f32>>::new(5, 4);
+        // A vertical white line splits the canvas at x = 2.
+        for y in 0..4 {
+            c.pixels[y][2] = white;
+        }
+        c.pixels[1][0] = RGB::new(0.05, 0.05, 0.05);
+        assert_eq!(c.flood_fill(0, 0, red, 0.1), 8);
+        for y in 0..4 {
+            assert_eq!(c.pixels[y][..2], [red, red]);
+            assert_eq!(c.pixels[y][2], white);
+            assert_eq!(c.pixels[y][3..], [RGB::default(), RGB::default()]);
+        }
+        // The region on the other side of the line is filled separately.
+        assert_eq!(c.flood_fill(4, 3, red, 0.), 8);
+        assert_eq!(c.flood_fill(9, 9, red, 0.), 0);
+    }
+
+    #[test]
+    fn edge_mask() {
+        let white = RGB::new(1., 1., 1.);
+        let mut c = Canvas::<RGB<f32>>::new(6, 3);
+        for row in &mut c.pixels {
+            row[3..].fill(white);
+        }
+        // Only the columns on either side of the boundary are marked.
+        let expected = (0..3).flat_map(|y| [(2, y), (3, y)]).collect::<Vec<_>>();
+        assert_eq!(c.edge_mask(0.5), expected);
+        assert_eq!(Canvas::<RGB<f32>>::new(4, 4).edge_mask(0.), vec![]);
+        let mut flat = Canvas::<RGB<f32>>::new(4, 4);
+        flat.pixels.fill(vec![white; 4]);
+        assert_eq!(flat.edge_mask(0.), vec![]);
+    }
 }
@@ -1,10 +1,46 @@
-use crate::shape::Shape;
-use cgmath::{BaseFloat, InnerSpace, Point3, Vector3};
+use crate::{
+    intersection::surface_epsilon,
+    shape::{reflect, ShapeRc},
+};
+use cgmath::{BaseFloat, InnerSpace, Point3, Vector3, Zero};
 
-#[derive(Clone, derive_more::Constructor, Debug, PartialEq)]
+// The angles and Fresnel reflectance at the hit, worked out once in Computation::new since
+// shading a glass surface needs them for both the reflected and the refracted rays.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Optics<T> {
+    cos_i: T,
+    sin2_t: T,
+    cos_t: T,
+    reflectance: T,
+}
+
+impl<T: BaseFloat> Optics<T> {
+    fn new(eyev: Vector3<T>, normalv: Vector3<T>, n1: T, n2: T) -> Optics<T> {
+        let one = T::one();
+        let cos_i = eyev.dot(normalv);
+        let sin2_t = (n1 / n2).powi(2) * (one - cos_i.powi(2));
+        let cos_t = (one - sin2_t).sqrt();
+        // Schlick's approximation, with everything reflected past the critical angle.
+        let reflectance = if sin2_t > one {
+            one
+        } else {
+            let cos = if n1 > n2 { cos_t } else { cos_i };
+            let r0 = ((n1 - n2) / (n1 + n2)).powi(2);
+            r0 + (one - r0) * (one - cos).powi(5)
+        };
+        Optics {
+            cos_i,
+            sin2_t,
+            cos_t,
+            reflectance,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Computation<T> {
     pub t: T,
-    pub object: Shape<T>,
+    pub object: ShapeRc<T>,
     pub point: Point3<T>,
     pub eyev: Vector3<T>,
     pub normalv: Vector3<T>,
@@ -12,15 +48,88 @@ pub struct Computation<T> {
     pub reflectv: Vector3<T>,
     pub n1: T,
     pub n2: T,
+    // Where the ray next meets the same object, i.e. leaves it when entering a solid.
+    pub exit_t: Option<T>,
+    // How far the instanced copy that was hit lies from the object, in world space.
+    pub offset: Vector3<T>,
+    // Where the hit falls in the texture coordinates of the mesh, if it has any.
+    pub texture_uv: Option<(T, T)>,
+    optics: Optics<T>,
 }
 
 impl<T: BaseFloat> Computation<T> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        t: T,
+        object: impl Into<ShapeRc<T>>,
+        point: Point3<T>,
+        eyev: Vector3<T>,
+        normalv: Vector3<T>,
+        inside: bool,
+        reflectv: Vector3<T>,
+        n1: T,
+        n2: T,
+        exit_t: Option<T>,
+    ) -> Computation<T> {
+        Computation {
+            t,
+            object: object.into(),
+            point,
+            eyev,
+            normalv,
+            inside,
+            reflectv,
+            n1,
+            n2,
+            exit_t,
+            offset: Vector3::zero(),
+            texture_uv: None,
+            optics: Optics::new(eyev, normalv, n1, n2),
+        }
+    }
+
+    // The same hit shaded with another normal, as given by the shape before facing the eye.
+    pub fn with_normal(self, normalv: Vector3<T>) -> Computation<T> {
+        let normalv = if self.inside { -normalv } else { normalv };
+        let (offset, texture_uv) = (self.offset, self.texture_uv);
+        Computation::new(
+            self.t,
+            self.object,
+            self.point,
+            self.eyev,
+            normalv,
+            self.inside,
+            reflect(-self.eyev, normalv),
+            self.n1,
+            self.n2,
+            self.exit_t,
+        )
+        .with_offset(offset)
+        .with_texture_uv(texture_uv)
+    }
+
+    pub fn with_offset(self, offset: Vector3<T>) -> Computation<T> {
+        Computation { offset, ..self }
+    }
+
+    pub fn with_texture_uv(self, texture_uv: Option<(T, T)>) -> Computation<T> {
+        Computation { texture_uv, ..self }
+    }
+
+    // How far to move the point off the surface to escape it. The rounding error of the point
+    // grows with its coordinates and with how far the ray came, so the offset does too.
+    pub fn surface_offset(&self) -> T {
+        let p = self.point;
+        let scale = p.x.abs().max(p.y.abs()).max(p.z.abs()).max(self.t.abs());
+        surface_epsilon::<T>() * scale.max(T::one())
+    }
+
     pub fn over_point(&self) -> Point3<T> {
-        self.point + self.normalv * T::epsilon()
+        self.point + self.normalv * self.surface_offset()
     }
 
     pub fn under_point(&self) -> Point3<T> {
-        self.point - self.normalv * T::epsilon()
+        self.point - self.normalv * self.surface_offset()
     }
 
     pub fn n_ratio(&self) -> T {
@@ -28,32 +137,41 @@ impl<T: BaseFloat> Computation<T> {
     }
 
     pub fn cos_i(&self) -> T {
-        self.eyev.dot(self.normalv)
+        self.optics.cos_i
     }
 
     pub fn sin2_t(&self) -> T {
-        self.n_ratio().powi(2) * (T::one() - self.cos_i().powi(2))
+        self.optics.sin2_t
     }
 
     pub fn cos_t(&self) -> T {
-        (T::one() - self.sin2_t()).sqrt()
+        self.optics.cos_t
     }
 
-    pub fn schlick(&self) -> T {
-        let one = T::one();
-        if self.n1 > self.n2 && self.sin2_t() > one {
-            one
+    // Past the critical angle no light is refracted and all of it is reflected.
+    pub fn is_total_internal_reflection(&self) -> bool {
+        self.sin2_t() > T::one()
+    }
+
+    // None under total internal reflection.
+    pub fn refractv(&self) -> Option<Vector3<T>> {
+        if self.is_total_internal_reflection() {
+            None
         } else {
-            let cos = if self.n1 > self.n2 {
-                self.cos_t()
-            } else {
-                self.cos_i()
-            };
-            let r0_t = (self.n1 - self.n2) / (self.n1 + self.n2);
-            let r0 = r0_t.powi(2);
-            r0 + (one - r0) * (one - cos).powi(5)
+            Some(
+                self.normalv * (self.n_ratio() * self.cos_i() - self.cos_t())
+                    - self.eyev * self.n_ratio(),
+            )
         }
     }
+
+    pub fn exit_distance(&self) -> Option<T> {
+        self.exit_t.map(|exit| exit - self.t)
+    }
+
+    pub fn schlick(&self) -> T {
+        self.optics.reflectance
+    }
 }
 
 mod tests {
@@ -68,12 +186,30 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn over_point() {
+        // Far from the origin the offset still moves the point by more than its rounding.
+        let point = Point3::new(1000., 2., 0.);
+        let comps = Computation::new(
+            1.,
+            Shape::Sphere(Sphere::<f64>::default()),
+            point,
+            Vector3::unit_y(),
+            Vector3::unit_y(),
+            false,
+            Vector3::unit_y(),
+            1.,
+            1.,
+            None,
+        );
+        assert!(comps.over_point().y > point.y);
+        assert!(comps.under_point().y < point.y);
+        assert!(comps.over_point().x == point.x);
+    }
+
     #[test]
     fn schlick() {
-        let mut sphere = Sphere::default();
-        sphere.material.transparency = 1.0;
-        sphere.material.refractive_index = 1.5;
-        let shape = Shape::Sphere(sphere);
+        let shape = Shape::Sphere(Sphere::glass());
         {
             let r = Ray::new(Point3::new(0., 0., FRAC_1_SQRT_2), Vector3::unit_y());
             let xs = vec![
@@ -99,4 +235,44 @@ mod tests {
             assert_relative_eq!(comps.schlick(), 0.48873, max_relative = 0.00001);
         }
     }
+
+    #[test]
+    fn is_total_internal_reflection() {
+        // Leaving glass for air, the critical angle from the normal is asin(1 / 1.5).
+        let critical = (1.0_f64 / 1.5).asin();
+        let leaving = |angle: f64| {
+            let eyev = Vector3::new(angle.sin(), angle.cos(), 0.);
+            Computation::new(
+                0.,
+                Shape::Sphere(Sphere::glass()),
+                Point3::origin(),
+                eyev,
+                Vector3::unit_y(),
+                true,
+                Vector3::new(-eyev.x, eyev.y, 0.),
+                1.5,
+                1.,
+                None,
+            )
+        };
+        assert!(!leaving(critical - 0.001).is_total_internal_reflection());
+        assert!(leaving(critical + 0.001).is_total_internal_reflection());
+        assert_eq!(leaving(critical + 0.001).refractv(), None);
+        assert_eq!(leaving(critical + 0.001).schlick(), 1.);
+        // Entering the glass never reflects everything, even at grazing angles.
+        let grazing = leaving(1.5);
+        let entering = Computation::new(
+            grazing.t,
+            grazing.object,
+            grazing.point,
+            grazing.eyev,
+            grazing.normalv,
+            false,
+            grazing.reflectv,
+            1.,
+            1.5,
+            None,
+        );
+        assert!(!entering.is_total_internal_reflection());
+    }
 }
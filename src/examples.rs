@@ -0,0 +1,79 @@
+use crate::{
+    material::Material,
+    shape::{
+        cylinder::Cylinder,
+        get_rc,
+        group::{push, push_link, Group},
+        sphere::Sphere,
+        Shape, ShapeRc,
+    },
+};
+use cgmath::{BaseFloat, Matrix4, Rad, Vector3};
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_3, FRAC_PI_6};
+
+fn hexagon_corner<T: BaseFloat + Default>() -> Shape<T> {
+    Shape::Sphere(Sphere::new(
+        Matrix4::from_translation(-Vector3::unit_z()) * Matrix4::from_scale(T::from(0.25).unwrap()),
+        Material::default(),
+        None,
+    ))
+}
+
+fn hexagon_edge<T: BaseFloat + Default>() -> Shape<T> {
+    let quarter = T::from(0.25).unwrap();
+    Shape::Cylinder(Cylinder {
+        minimum: T::zero(),
+        maximum: T::one(),
+        transform: Matrix4::from_translation(-Vector3::unit_z())
+            * Matrix4::from_angle_y(Rad(T::from(-FRAC_PI_6).unwrap()))
+            * Matrix4::from_angle_z(Rad(T::from(-FRAC_PI_2).unwrap()))
+            * Matrix4::from_nonuniform_scale(quarter, T::one(), quarter),
+        ..Cylinder::default()
+    })
+}
+
+fn hexagon_side<T: BaseFloat + Default>(transform: Matrix4<T>) -> ShapeRc<T> {
+    let side = get_rc(Shape::Group(Group {
+        transform,
+        ..Group::default()
+    }));
+    push(&side, hexagon_corner());
+    push(&side, hexagon_edge());
+    side
+}
+
+// The hexagon from the groups chapter: six sides, each a corner sphere and an edge cylinder.
+pub fn hexagon<T: BaseFloat + Default>() -> ShapeRc<T> {
+    let hex = get_rc(Shape::Group(Group::default()));
+    for n in 0..6 {
+        let angle = T::from(n).unwrap() * T::from(FRAC_PI_3).unwrap();
+        push_link(&hex, hexagon_side(Matrix4::from_angle_y(Rad(angle))));
+    }
+    hex
+}
+
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+    use cgmath::Point3;
+
+    #[test]
+    fn hexagon() {
+        let hex = super::hexagon::<f32>();
+        let sides = hex.borrow().as_group().unwrap().children.clone();
+        assert_eq!(sides.len(), 6);
+        for side in sides {
+            let side = side.borrow();
+            let children = &side.as_group().unwrap().children;
+            assert_eq!(children.len(), 2);
+            assert!(children[0].borrow().as_sphere().is_some());
+            assert!(children[1].borrow().as_cylinder().is_some());
+            assert!(side.parent().unwrap().upgrade().is_some());
+        }
+        // A ray down the -z axis passes through the corner sphere at (0, 0, -1).
+        let xs = hex
+            .borrow()
+            .intersect(Ray::new(Point3::new(0., 0., -5.), Vector3::unit_z()));
+        assert!(!xs.is_empty());
+    }
+}
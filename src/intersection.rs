@@ -1,43 +1,102 @@
 use crate::{
     computation::Computation,
+    material::MaterialLibrary,
     ray::Ray,
-    shape::{reflect, Shape},
+    shape::{reflect, ShapeRc},
 };
-use cgmath::{dot, BaseFloat};
+use cgmath::{dot, BaseFloat, BaseNum, Vector3, Zero};
 
-#[derive(Clone, derive_more::Constructor, Debug, PartialEq)]
+// The object is the Rc the shape is kept in, so that neither hitting nor copying and sorting
+// intersections copies the shape. Equality still compares the shapes themselves.
+#[derive(Clone, Debug, PartialEq)]
 pub struct Intersection<T> {
     pub t: T,
-    pub object: Shape<T>,
+    pub object: ShapeRc<T>,
     pub uv: Option<(T, T)>,
+    // How far the instanced copy that was hit lies from the object itself, in the space of the
+    // shape that was intersected. Zero unless the object is repeated by Shape::Instanced.
+    pub offset: Vector3<T>,
+}
+
+impl<T: BaseNum> Intersection<T> {
+    // Shapes that hit a ray more than once pass one Rc to every intersection.
+    pub fn new(t: T, object: impl Into<ShapeRc<T>>, uv: Option<(T, T)>) -> Intersection<T> {
+        Intersection {
+            t,
+            object: object.into(),
+            uv,
+            offset: Vector3::zero(),
+        }
+    }
+
+    // Whether both hit the same copy of the same object.
+    pub fn is_same_surface(&self, other: &Intersection<T>) -> bool {
+        self.object == other.object && self.offset == other.offset
+    }
+}
+
+// An intersection at each t, all sharing the object, which is only asked for if there is a hit
+// at all.
+pub fn intersections<T: BaseNum>(
+    ts: impl IntoIterator<Item = T>,
+    object: impl FnOnce() -> ShapeRc<T>,
+) -> Vec<Intersection<T>> {
+    let mut ts = ts.into_iter().peekable();
+    if ts.peek().is_none() {
+        return vec![];
+    }
+    let object = object();
+    ts.map(|t| Intersection::new(t, object.clone(), None))
+        .collect()
 }
 
 impl<T: BaseFloat> Intersection<T> {
     pub fn precompute(&self, ray: Ray<T>, xs: &[Intersection<T>]) -> Option<Computation<T>> {
+        self.precompute_with(ray, xs, &MaterialLibrary::new())
+    }
+
+    // Like precompute, looking up materials that refer to the library.
+    pub fn precompute_with(
+        &self,
+        ray: Ray<T>,
+        xs: &[Intersection<T>],
+        library: &MaterialLibrary<T>,
+    ) -> Option<Computation<T>> {
         let point = ray.position(self.t);
         let eyev = -ray.direction;
-        self.object.normal_at(point, self.uv).map(|t_normalv| {
+        let object = self.object.borrow();
+        let object_point = point - self.offset;
+        object.normal_at(object_point, self.uv).map(|t_normalv| {
+            let t_normalv = object.material().map_or(t_normalv, |m| {
+                library
+                    .resolve(m)
+                    .perturb_normal(t_normalv, self.uv, || object.uv_derivatives(object_point))
+            });
             let inside = dot(t_normalv, eyev) < T::zero();
             let normalv = if inside { -t_normalv } else { t_normalv };
             let reflectv = reflect(ray.direction, normalv);
             let mut n1 = None;
             let mut n2 = None;
-            let mut containers = Vec::<Shape<T>>::new();
+            let mut containers = Vec::<&Intersection<T>>::new();
             for i in xs {
                 if self == i {
-                    n1 = containers
-                        .last()
-                        .map(|i| i.material().unwrap().refractive_index);
+                    n1 = containers.last().map(|i| {
+                        library
+                            .resolve(i.object.borrow().material().unwrap())
+                            .refractive_index
+                    });
                 }
-                if let Some(index) = containers.iter().position(|x| *x == i.object) {
+                if let Some(index) = containers.iter().position(|x| x.is_same_surface(i)) {
                     containers.remove(index);
                 } else {
-                    containers.push(i.object.clone());
+                    containers.push(i);
                 }
                 if self == i {
-                    n2 = containers
-                        .last()
-                        .map(|i| i.material().unwrap().refractive_index);
+                    n2 = containers.last().map(|i| {
+                        library
+                            .resolve(i.object.borrow().material().unwrap())
+                            .refractive_index
+                    });
                     break;
                 }
             }
@@ -51,14 +110,28 @@ impl<T: BaseFloat> Intersection<T> {
                 reflectv,
                 n1.unwrap_or_else(T::one),
                 n2.unwrap_or_else(T::one),
+                xs.iter()
+                    .skip_while(|i| *i != self)
+                    .skip(1)
+                    .find(|i| i.is_same_surface(self))
+                    .map(|i| i.t),
             )
+            .with_offset(self.offset)
+            .with_texture_uv(object.texture_uv(self.uv))
         })
     }
 }
 
+// How close a hit may be before it is taken to be the surface the ray started from, and, scaled
+// by Computation::surface_offset, how far points are moved off a surface. A few machine epsilons
+// of T, so that f32 and f64 behave alike.
+pub fn surface_epsilon<T: BaseFloat>() -> T {
+    T::epsilon() * T::from(8).unwrap()
+}
+
 pub fn hit<T: BaseFloat>(v: &[Intersection<T>]) -> Option<Intersection<T>> {
     v.iter()
-        .filter(|i| i.t >= T::from(f32::EPSILON).unwrap()) // -0.0 >= T::zero()
+        .filter(|i| i.t >= surface_epsilon()) // -0.0 >= T::zero()
         .min_by(|a, b| a.t.partial_cmp(&b.t).unwrap_or(std::cmp::Ordering::Less))
         .cloned()
 }
@@ -66,11 +139,32 @@ pub fn hit<T: BaseFloat>(v: &[Intersection<T>]) -> Option<Intersection<T>> {
 mod tests {
     use super::*;
     use crate::{
+        canvas::Canvas,
         material::Material,
-        shape::{plane::Plane, sphere::Sphere},
+        shape::{plane::Plane, sphere::Sphere, triangle::Triangle, Shape},
     };
-    use cgmath::{EuclideanSpace, Matrix4, Point3, SquareMatrix, Vector3};
+    use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Vector3};
+    use rgb::RGB;
     use std::f32::{consts::FRAC_1_SQRT_2, EPSILON};
+    use std::rc::Rc;
+
+    #[test]
+    fn intersections() {
+        let sphere = Sphere::default();
+        let ray = Ray::new(Point3::new(0., 0., -5.), Vector3::unit_z());
+        let xs = sphere.local_intersect(ray);
+        // Both hits share one copy of the sphere, which still compares equal to a fresh one.
+        assert!(Rc::ptr_eq(&xs[0].object, &xs[1].object));
+        assert_eq!(*xs[0].object.borrow(), Shape::Sphere(sphere.clone()));
+        assert_eq!(
+            xs[0],
+            Intersection::new(4., Shape::Sphere(sphere), xs[0].uv)
+        );
+        assert_eq!(
+            super::intersections(Vec::<f32>::new(), || unreachable!()),
+            vec![]
+        );
+    }
 
     #[test]
     fn hit() {
@@ -98,10 +192,15 @@ mod tests {
         assert_eq!(
             super::hit(&vec![
                 Intersection::new(-2., sphere.clone(), None),
-                Intersection::new(-1., sphere, None)
+                Intersection::new(-1., sphere.clone(), None)
             ]),
             None
         );
+        // Tiny positive hits count in both f32 and f64.
+        let near = Intersection::new(1e-6, sphere, None);
+        assert_eq!(super::hit(std::slice::from_ref(&near)), Some(near));
+        let near = Intersection::new(1e-6, Shape::Sphere(Sphere::<f32>::default()), None);
+        assert_eq!(super::hit(std::slice::from_ref(&near)), Some(near));
     }
 
     #[test]
@@ -130,7 +229,7 @@ mod tests {
             let xs = vec![i.clone()];
             assert_eq!(
                 i.precompute(Ray::new(Point3::origin(), vz), &xs).unwrap(),
-                Computation::new(1., object, point, -vz, -vz, true, -vz, 1., 1.)
+                Computation::new(1., object, point, -vz, -vz, true, -vz, 1., 1., None)
             );
         }
         {
@@ -149,14 +248,12 @@ mod tests {
         }
         {
             let vz = Vector3::unit_z();
-            let mut material = Material::default();
-            material.transparency = 1.;
-            material.refractive_index = 1.5;
-            let a = Shape::Sphere(Sphere::new(Matrix4::from_scale(2.), material, None));
+            let mut material = Material::glass();
+            let a = Shape::Sphere(Sphere::new(Matrix4::from_scale(2.), material.clone(), None));
             material.refractive_index = 2.;
             let b = Shape::Sphere(Sphere::new(
                 Matrix4::from_translation(vz * -0.25),
-                material,
+                material.clone(),
                 None,
             ));
             material.refractive_index = 2.5;
@@ -184,4 +281,41 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn normal_map() {
+        let mut map = Canvas::new(1, 1);
+        map.pixels[0][0] = RGB::new(1., 0.5, 1.);
+        let material = Material::default().with_normal_map(map);
+        // Two faces turned differently, each leaning towards its own p1 to p2 edge.
+        let faces = [
+            (
+                [
+                    Point3::origin(),
+                    Point3::new(1., 0., 0.),
+                    Point3::new(0., 1., 0.),
+                ],
+                Ray::new(Point3::new(0.25, 0.25, -5.), Vector3::unit_z()),
+                Vector3::new(1., 0., -1.),
+            ),
+            (
+                [
+                    Point3::origin(),
+                    Point3::new(0., 0., 1.),
+                    Point3::new(0., 1., 0.),
+                ],
+                Ray::new(Point3::new(5., 0.25, 0.25), -Vector3::unit_x()),
+                Vector3::new(1., 0., 1.),
+            ),
+        ];
+        for ([p1, p2, p3], ray, expected) in faces {
+            let shape = Shape::Triangle(Triangle {
+                material: material.clone(),
+                ..Triangle::from(p1, p2, p3)
+            });
+            let xs = shape.intersect(ray);
+            let comps = xs[0].precompute(ray, &xs).unwrap();
+            cgmath::assert_relative_eq!(comps.normalv, expected.normalize(), epsilon = 0.00001);
+        }
+    }
 }
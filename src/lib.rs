@@ -2,11 +2,16 @@ pub mod bounds;
 pub mod camera;
 pub mod canvas;
 pub mod computation;
+pub mod examples;
 pub mod intersection;
 pub mod light;
 pub mod material;
+pub mod mesh;
 pub mod pattern;
+pub mod random;
 pub mod ray;
+pub mod scalar;
+pub mod scene;
 pub mod shape;
 pub mod world;
 
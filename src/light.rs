@@ -1,5 +1,179 @@
-#[derive(Clone, derive_more::Constructor, Copy, Debug)]
-pub struct Light<T> {
-    pub position: cgmath::Point3<T>,
-    pub intensity: rgb::RGB<T>,
+use crate::{
+    canvas::{Canvas, WrapMode},
+    shape::sphere::spherical_uv,
+    world::cone_samples,
+};
+use cgmath::{BaseFloat, EuclideanSpace, InnerSpace, Point3, Vector3};
+use rgb::RGB;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Light<T> {
+    Point {
+        position: Point3<T>,
+        intensity: RGB<T>,
+        // Divide the intensity by the squared distance to the lit point.
+        attenuation: bool,
+    },
+    // Full intensity within inner radians of the direction, fading to nothing at outer.
+    Spot {
+        position: Point3<T>,
+        direction: Vector3<T>,
+        intensity: RGB<T>,
+        inner: T,
+        outer: T,
+    },
+}
+
+impl<T: Copy> Light<T> {
+    pub fn new(position: Point3<T>, intensity: RGB<T>) -> Light<T> {
+        Light::Point {
+            position,
+            intensity,
+            attenuation: false,
+        }
+    }
+
+    pub fn position(&self) -> Point3<T> {
+        match *self {
+            Light::Point { position, .. } | Light::Spot { position, .. } => position,
+        }
+    }
+
+    pub fn intensity(&self) -> RGB<T> {
+        match *self {
+            Light::Point { intensity, .. } | Light::Spot { intensity, .. } => intensity,
+        }
+    }
+}
+
+impl<T: BaseFloat> Light<T> {
+    // The fraction of the intensity that reaches the point.
+    pub fn falloff(&self, point: Point3<T>) -> T {
+        match *self {
+            Light::Point {
+                position,
+                attenuation,
+                ..
+            } => {
+                if attenuation {
+                    T::one() / (position - point).magnitude2()
+                } else {
+                    T::one()
+                }
+            }
+            Light::Spot {
+                position,
+                direction,
+                inner,
+                outer,
+                ..
+            } => {
+                let cos = (point - position).normalize().dot(direction.normalize());
+                let angle = cos.max(-T::one()).min(T::one()).acos();
+                if angle <= inner {
+                    T::one()
+                } else if angle >= outer {
+                    T::zero()
+                } else {
+                    // Smoothstep from the outer edge in to the inner one.
+                    let t = (outer - angle) / (outer - inner);
+                    t * t * (T::from(3).unwrap() - T::from(2).unwrap() * t)
+                }
+            }
+        }
+    }
+}
+
+// Light arriving from every direction, looked up in an equirectangular image.
+#[derive(Clone, derive_more::Constructor, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EnvironmentLight<T> {
+    pub map: Canvas<RGB<T>>,
+    pub samples: usize,
+}
+
+impl<T: BaseFloat> EnvironmentLight<T> {
+    pub fn from(map: Canvas<RGB<T>>) -> EnvironmentLight<T> {
+        EnvironmentLight { map, samples: 64 }
+    }
+
+    pub fn radiance(&self, direction: Vector3<T>) -> RGB<T> {
+        let (u, v) = spherical_uv(Point3::from_vec(direction.normalize()));
+        self.map
+            .sample_wrapped(u, v, WrapMode::Repeat)
+            .unwrap_or(RGB::new(T::zero(), T::zero(), T::zero()))
+    }
+
+    // The cosine-weighted average of the radiance over the hemisphere around the normal, or
+    // black without samples.
+    pub fn irradiance(&self, normal: Vector3<T>) -> RGB<T> {
+        let normal = normal.normalize();
+        let half_pi = T::from(std::f64::consts::FRAC_PI_2).unwrap();
+        let mut total = RGB::new(T::zero(), T::zero(), T::zero());
+        let mut weight = T::zero();
+        for direction in cone_samples(normal, half_pi, self.samples) {
+            let cos = direction.dot(normal).max(T::zero());
+            total += self.radiance(direction) * cos;
+            weight += cos;
+        }
+        if weight > T::zero() {
+            total / weight
+        } else {
+            total
+        }
+    }
+}
+
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn falloff() {
+        let spot = Light::Spot {
+            position: Point3::new(0., 10., 0.),
+            direction: -Vector3::unit_y(),
+            intensity: RGB::new(1., 1., 1.),
+            inner: 0.2,
+            outer: 0.4,
+        };
+        assert_eq!(spot.falloff(Point3::new(0., 0., 0.)), 1.);
+        assert_eq!(spot.falloff(Point3::new(10., 0., 0.)), 0.);
+        // tan(0.3) * 10 puts the point halfway between the cones.
+        assert_relative_eq!(
+            spot.falloff(Point3::new(0.3_f32.tan() * 10., 0., 0.)),
+            0.5,
+            max_relative = 0.001
+        );
+        let point = Light::new(Point3::new(0., 10., 0.), RGB::new(1., 1., 1.));
+        assert_eq!(point.falloff(Point3::new(10., 0., 0.)), 1.);
+        let attenuated = Light::Point {
+            position: Point3::new(0., 10., 0.),
+            intensity: RGB::new(1., 1., 1.),
+            attenuation: true,
+        };
+        assert_eq!(attenuated.falloff(Point3::new(0., 5., 0.)), 0.04);
+    }
+
+    #[test]
+    fn irradiance() {
+        let mut map = Canvas::new(8, 4);
+        map.pixels.fill(vec![RGB::new(0.2, 0.4, 0.6); 8]);
+        let env = EnvironmentLight::from(map);
+        for normal in [
+            Vector3::unit_x(),
+            -Vector3::unit_y(),
+            Vector3::new(1., 2., 3.),
+        ] {
+            assert_relative_eq!(
+                env.irradiance(normal),
+                RGB::new(0.2, 0.4, 0.6),
+                max_relative = 0.0001
+            );
+        }
+        // Without samples the environment gives no light rather than NaN.
+        let none = EnvironmentLight { samples: 0, ..env };
+        assert_eq!(none.irradiance(Vector3::unit_y()), RGB::new(0., 0., 0.));
+    }
 }
@@ -1,17 +1,112 @@
-use crate::{light::Light, pattern::Pattern, shape::reflect};
+use crate::{
+    canvas::{Canvas, WrapMode},
+    light::{EnvironmentLight, Light},
+    pattern::Pattern,
+    shape::reflect,
+};
 use cgmath::{BaseFloat, InnerSpace, Point3, Vector3};
 use rgb::RGB;
+use std::{collections::HashMap, rc::Rc};
 
-#[derive(Clone, derive_more::Constructor, Copy, Debug, PartialEq)]
+// Refers to a material defined in a MaterialLibrary.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MaterialHandle(usize);
+
+// Named materials that many shapes can share, so editing one changes them all.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MaterialLibrary<T> {
+    handles: HashMap<String, MaterialHandle>,
+    materials: Vec<Material<T>>,
+}
+
+impl<T> MaterialLibrary<T> {
+    pub fn new() -> MaterialLibrary<T> {
+        MaterialLibrary {
+            handles: HashMap::new(),
+            materials: Vec::new(),
+        }
+    }
+
+    // Defining a name again replaces its material, keeping the handle.
+    pub fn define(&mut self, name: &str, material: Material<T>) -> MaterialHandle {
+        match self.handles.get(name) {
+            Some(&handle) => {
+                self.materials[handle.0] = material;
+                handle
+            }
+            None => {
+                let handle = MaterialHandle(self.materials.len());
+                self.materials.push(material);
+                self.handles.insert(name.to_string(), handle);
+                handle
+            }
+        }
+    }
+
+    pub fn handle(&self, name: &str) -> Option<MaterialHandle> {
+        self.handles.get(name).copied()
+    }
+
+    pub fn get(&self, handle: MaterialHandle) -> Option<&Material<T>> {
+        self.materials.get(handle.0)
+    }
+
+    pub fn get_mut(&mut self, handle: MaterialHandle) -> Option<&mut Material<T>> {
+        self.materials.get_mut(handle.0)
+    }
+
+    // The library material the given one refers to, or the material itself.
+    pub fn resolve<'a>(&'a self, material: &'a Material<T>) -> &'a Material<T> {
+        material
+            .handle
+            .and_then(|handle| self.get(handle))
+            .unwrap_or(material)
+    }
+}
+
+impl<T> Default for MaterialLibrary<T> {
+    fn default() -> MaterialLibrary<T> {
+        MaterialLibrary::new()
+    }
+}
+
+#[derive(Clone, derive_more::Constructor, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Material<T> {
     pub pattern: Pattern<T>,
     pub ambient: T,
     pub diffuse: T,
     pub specular: T,
     pub shininess: T,
+    // The minimum angular width (in radians) at which the highlight falls to half intensity.
+    // Zero keeps the highlight as sharp as the shininess makes it.
+    pub highlight_width: T,
     pub reflective: T,
+    // The half-angle (in radians) of the cone the reflected rays are spread over.
+    // Zero gives a perfect mirror.
+    pub reflection_roughness: T,
     pub transparency: T,
     pub refractive_index: T,
+    // How much of each channel is absorbed per unit of distance travelled inside (Beer's law).
+    pub absorption: RGB<T>,
+    // Light given off by the surface; emissive triangles also light the rest of the world.
+    pub emission: RGB<T>,
+    // The strength of a glossy clear layer over the base, reflecting by Fresnel like varnish.
+    pub clearcoat: T,
+    // The half-angle (in radians) over which the clear layer spreads its reflection.
+    pub clearcoat_roughness: T,
+    // How far diffuse light wraps around past the terminator, like light scattered under wax or
+    // skin. Zero is plain Lambertian diffuse.
+    pub subsurface: T,
+    // Whether the surface blocks the light for what lies behind it, off for panes and fill cards.
+    pub casts_shadow: bool,
+    // Tangent-space normals encoded as RGB in [0, 1], looked up by the surface uv.
+    // Shared so that copying the material does not copy the image.
+    pub normal_map: Option<Rc<Canvas<RGB<T>>>>,
+    // Use this library material when shading instead of the fields above.
+    pub handle: Option<MaterialHandle>,
 }
 
 impl<T: BaseFloat> Default for Material<T> {
@@ -23,14 +118,252 @@ impl<T: BaseFloat> Default for Material<T> {
             diffuse: T::from(0.9).unwrap(),
             specular: T::from(0.9).unwrap(),
             shininess: T::from(200).unwrap(),
+            highlight_width: T::zero(),
             reflective: T::zero(),
+            reflection_roughness: T::zero(),
             transparency: T::zero(),
             refractive_index: one,
+            absorption: RGB::new(T::zero(), T::zero(), T::zero()),
+            emission: RGB::new(T::zero(), T::zero(), T::zero()),
+            clearcoat: T::zero(),
+            clearcoat_roughness: T::zero(),
+            subsurface: T::zero(),
+            casts_shadow: true,
+            normal_map: None,
+            handle: None,
+        }
+    }
+}
+
+impl<T: BaseFloat> Material<T> {
+    // Tilt the unit normal by the normal map texel at uv, if there is both a map and a uv. The
+    // red and green channels lean it along the directions the surface moves in as u and v grow,
+    // as given by derivatives, so the map reads the same on every face.
+    pub fn perturb_normal(
+        &self,
+        normal: Vector3<T>,
+        uv: Option<(T, T)>,
+        derivatives: impl FnOnce() -> Option<(Vector3<T>, Vector3<T>)>,
+    ) -> Vector3<T> {
+        let map = self.normal_map.as_ref();
+        match uv.and_then(|(u, v)| map?.sample_wrapped(u, v, WrapMode::Clamp)) {
+            Some(texel) => {
+                let two = T::from(2).unwrap();
+                let frame = derivatives().and_then(|(dpdu, dpdv)| {
+                    let tangent = dpdu - normal * normal.dot(dpdu);
+                    (tangent.magnitude2() > T::epsilon()).then(|| {
+                        let tangent = tangent.normalize();
+                        let bitangent = normal.cross(tangent);
+                        if bitangent.dot(dpdv) < T::zero() {
+                            (tangent, -bitangent)
+                        } else {
+                            (tangent, bitangent)
+                        }
+                    })
+                });
+                // Where the uv has no direction, as at the poles of a sphere, any tangent will do.
+                let (tangent, bitangent) = frame.unwrap_or_else(|| {
+                    let helper = if normal.y.abs() < T::from(0.9).unwrap() {
+                        Vector3::unit_y()
+                    } else {
+                        Vector3::unit_x()
+                    };
+                    let tangent = helper.cross(normal).normalize();
+                    (tangent, normal.cross(tangent))
+                });
+                (tangent * (texel.r * two - T::one())
+                    + bitangent * (texel.g * two - T::one())
+                    + normal * (texel.b * two - T::one()))
+                .normalize()
+            }
+            _ => normal,
+        }
+    }
+}
+
+/// Chainable setters for building a material from the default one.
+///
+/// ```
+/// use ray_tracer_challenge::material::Material;
+/// use rgb::RGB;
+///
+/// let glass = Material::default()
+///     .with_color(RGB::new(0.1, 0.1, 0.1))
+///     .with_diffuse(0.1)
+///     .with_reflective(0.9)
+///     .with_transparency(0.9)
+///     .with_refractive_index(1.5);
+/// assert_eq!(glass.transparency, 0.9);
+/// assert_eq!(glass.refractive_index, 1.5);
+/// ```
+impl<T> Material<T> {
+    pub fn with_pattern(mut self, pattern: Pattern<T>) -> Material<T> {
+        self.pattern = pattern;
+        self
+    }
+
+    // A solid color, the usual case of with_pattern.
+    pub fn with_color(self, color: RGB<T>) -> Material<T> {
+        self.with_pattern(Pattern::Solid(color))
+    }
+
+    pub fn with_ambient(mut self, ambient: T) -> Material<T> {
+        self.ambient = ambient;
+        self
+    }
+
+    pub fn with_diffuse(mut self, diffuse: T) -> Material<T> {
+        self.diffuse = diffuse;
+        self
+    }
+
+    pub fn with_specular(mut self, specular: T) -> Material<T> {
+        self.specular = specular;
+        self
+    }
+
+    pub fn with_shininess(mut self, shininess: T) -> Material<T> {
+        self.shininess = shininess;
+        self
+    }
+
+    pub fn with_highlight_width(mut self, highlight_width: T) -> Material<T> {
+        self.highlight_width = highlight_width;
+        self
+    }
+
+    pub fn with_reflective(mut self, reflective: T) -> Material<T> {
+        self.reflective = reflective;
+        self
+    }
+
+    pub fn with_reflection_roughness(mut self, reflection_roughness: T) -> Material<T> {
+        self.reflection_roughness = reflection_roughness;
+        self
+    }
+
+    pub fn with_transparency(mut self, transparency: T) -> Material<T> {
+        self.transparency = transparency;
+        self
+    }
+
+    pub fn with_refractive_index(mut self, refractive_index: T) -> Material<T> {
+        self.refractive_index = refractive_index;
+        self
+    }
+
+    pub fn with_absorption(mut self, absorption: RGB<T>) -> Material<T> {
+        self.absorption = absorption;
+        self
+    }
+
+    pub fn with_emission(mut self, emission: RGB<T>) -> Material<T> {
+        self.emission = emission;
+        self
+    }
+
+    pub fn with_clearcoat(mut self, clearcoat: T) -> Material<T> {
+        self.clearcoat = clearcoat;
+        self
+    }
+
+    pub fn with_clearcoat_roughness(mut self, clearcoat_roughness: T) -> Material<T> {
+        self.clearcoat_roughness = clearcoat_roughness;
+        self
+    }
+
+    pub fn with_subsurface(mut self, subsurface: T) -> Material<T> {
+        self.subsurface = subsurface;
+        self
+    }
+
+    pub fn with_casts_shadow(mut self, casts_shadow: bool) -> Material<T> {
+        self.casts_shadow = casts_shadow;
+        self
+    }
+
+    // Takes either a canvas or one already shared with other materials.
+    pub fn with_normal_map(mut self, normal_map: impl Into<Rc<Canvas<RGB<T>>>>) -> Material<T> {
+        self.normal_map = Some(normal_map.into());
+        self
+    }
+}
+
+impl<T: BaseFloat> Material<T> {
+    // The book's glass: fully transparent with the refractive index of glass, and no mirror
+    // reflection so the refraction scenes match.
+    pub fn glass() -> Material<T> {
+        Material {
+            transparency: T::one(),
+            refractive_index: T::from(1.5).unwrap(),
+            ..Material::default()
+        }
+    }
+
+    // A material that stands for the library material with the given handle.
+    pub fn from_library(handle: MaterialHandle) -> Material<T> {
+        Material {
+            handle: Some(handle),
+            ..Material::default()
         }
     }
 }
 
 impl<T: BaseFloat + Default> Material<T> {
+    // Cap the shininess so that the highlight is at least highlight_width wide.
+    fn effective_shininess(&self) -> T {
+        if self.highlight_width > T::zero() {
+            let limit = T::from(0.5).unwrap().ln() / self.highlight_width.cos().ln();
+            self.shininess.min(limit)
+        } else {
+            self.shininess
+        }
+    }
+
+    // The share of light reflected by the clear layer, by Schlick's approximation for an
+    // index of 1.5. The base underneath receives the rest.
+    pub(crate) fn clearcoat_reflectance(&self, eyev: Vector3<T>, normalv: Vector3<T>) -> T {
+        if self.clearcoat == T::zero() {
+            return T::zero();
+        }
+        let r0 = T::from(0.04).unwrap();
+        let cos = eyev.dot(normalv).max(T::zero());
+        self.clearcoat * (r0 + (T::one() - r0) * (T::one() - cos).powi(5))
+    }
+
+    // The diffuse response to an environment light; it casts no shadows.
+    pub fn environment_lighting(
+        &self,
+        environment: &EnvironmentLight<T>,
+        point: Point3<T>,
+        normalv: Vector3<T>,
+    ) -> RGB<T> {
+        self.environment_lighting_of(self.pattern.at_normal(point, normalv), environment, normalv)
+    }
+
+    // Like environment_lighting, for a surface of the given color.
+    pub(crate) fn environment_lighting_of(
+        &self,
+        color: RGB<T>,
+        environment: &EnvironmentLight<T>,
+        normalv: Vector3<T>,
+    ) -> RGB<T> {
+        color * environment.irradiance(normalv) * self.diffuse
+    }
+
+    // The color of the surface at a hit, by the texture coordinates of a mesh where the pattern
+    // is laid by them and by the point otherwise.
+    pub(crate) fn color_at(
+        &self,
+        point: Point3<T>,
+        normalv: Vector3<T>,
+        texture_uv: Option<(T, T)>,
+    ) -> RGB<T> {
+        texture_uv
+            .and_then(|uv| self.pattern.at_texture_uv(uv))
+            .unwrap_or_else(|| self.pattern.at_normal(point, normalv))
+    }
+
     pub fn lighting(
         &self,
         light: Light<T>,
@@ -39,22 +372,68 @@ impl<T: BaseFloat + Default> Material<T> {
         normalv: Vector3<T>,
         in_shadow: bool,
     ) -> RGB<T> {
-        let effective_color = self.pattern.at(point) * light.intensity;
-        let lightv = (light.position - point).normalize();
-        let ambient = effective_color * self.ambient;
+        let color = self.pattern.at_normal(point, normalv);
+        self.lighting_of(color, light, point, eyev, normalv, in_shadow)
+    }
+
+    // Like lighting, for a surface of the given color.
+    pub(crate) fn lighting_of(
+        &self,
+        color: RGB<T>,
+        light: Light<T>,
+        point: Point3<T>,
+        eyev: Vector3<T>,
+        normalv: Vector3<T>,
+        in_shadow: bool,
+    ) -> RGB<T> {
+        let ambient = color * light.intensity() * self.ambient;
+        if in_shadow {
+            ambient
+        } else {
+            ambient + self.direct_lighting(color, light, point, eyev, normalv)
+        }
+    }
+
+    // The diffuse and specular terms of lighting, for a light known to reach the point.
+    pub(crate) fn direct_lighting(
+        &self,
+        color: RGB<T>,
+        light: Light<T>,
+        point: Point3<T>,
+        eyev: Vector3<T>,
+        normalv: Vector3<T>,
+    ) -> RGB<T> {
+        let intensity = light.intensity() * light.falloff(point);
+        let effective_color = color * intensity;
+        let lightv = (light.position() - point).normalize();
         let light_dot_normal = lightv.dot(normalv);
+        let wrapped = (light_dot_normal + self.subsurface) / (T::one() + self.subsurface);
         let mut diffuse = RGB::default();
+        if wrapped > T::zero() {
+            diffuse = effective_color * self.diffuse * wrapped;
+        }
         let mut specular = RGB::default();
-        if !in_shadow && light_dot_normal >= T::zero() {
-            diffuse = effective_color * self.diffuse * light_dot_normal;
+        if light_dot_normal >= T::zero() {
             let reflectv = reflect(-lightv, normalv);
             let reflect_dot_eye = reflectv.dot(eyev);
             if reflect_dot_eye > T::zero() {
-                let factor = reflect_dot_eye.powf(self.shininess);
-                specular = light.intensity * self.specular * factor;
+                let factor = reflect_dot_eye.powf(self.effective_shininess());
+                specular = intensity * self.specular * factor;
             }
         }
-        ambient + diffuse + specular
+        let coat = self.clearcoat_reflectance(eyev, normalv);
+        if coat == T::zero() {
+            return diffuse + specular;
+        }
+        let mut highlight = RGB::default();
+        let reflect_dot_eye = reflect(-lightv, normalv).dot(eyev);
+        if light_dot_normal >= T::zero() && reflect_dot_eye > T::zero() {
+            // Even a perfectly smooth coat shows the light as a small spot.
+            let width = self.clearcoat_roughness.max(T::from(0.05).unwrap());
+            let shininess = T::from(0.5).unwrap().ln() / width.cos().ln();
+            highlight = intensity * reflect_dot_eye.powf(shininess);
+        }
+        (diffuse + specular) * (T::one() - coat) + highlight * coat
     }
 }
 
@@ -127,4 +506,189 @@ mod tests {
             RGB::new(0.1, 0.1, 0.1)
         );
     }
+
+    #[test]
+    fn highlight_width() {
+        let light = Light::new(Point3::new(0., 0., -10.), RGB::new(1., 1., 1.));
+        let mut material = Material {
+            ambient: 0.,
+            diffuse: 0.,
+            shininess: 2000.,
+            ..Material::default()
+        };
+        let lit = |m: &Material<f32>| {
+            (-100..=100)
+                .map(|i| {
+                    let angle = i as f32 * 0.002;
+                    m.lighting(
+                        light,
+                        Point3::origin(),
+                        Vector3::new(0., angle.sin(), -angle.cos()),
+                        -Vector3::unit_z(),
+                        false,
+                    )
+                })
+                .filter(|c| c.r > 0.5)
+                .count()
+        };
+        let sharp = lit(&material);
+        material.highlight_width = 0.1;
+        let soft = lit(&material);
+        assert!(soft > sharp);
+        // The peak is unchanged.
+        assert_relative_eq!(
+            material.lighting(
+                light,
+                Point3::origin(),
+                -Vector3::unit_z(),
+                -Vector3::unit_z(),
+                false
+            ),
+            RGB::new(0.9, 0.9, 0.9)
+        );
+    }
+
+    #[test]
+    fn perturb_normal() {
+        let mut map = Canvas::new(2, 2);
+        map.pixels.fill(vec![RGB::new(0.5, 0.5, 1.); 2]);
+        let material = Material::default().with_normal_map(map);
+        let normal = Vector3::new(1., 2., 3.).normalize();
+        let flat = || None;
+        cgmath::assert_relative_eq!(
+            material.perturb_normal(normal, Some((0.3, 0.7)), flat),
+            normal
+        );
+        assert_eq!(material.perturb_normal(normal, None, flat), normal);
+        let mut map = Canvas::new(1, 1);
+        map.pixels[0][0] = RGB::new(1., 0.5, 0.5);
+        let material = Material::default().with_normal_map(map);
+        // A texel full of red leans the normal all the way over to dP/du, made perpendicular.
+        let derivatives = || Some((Vector3::unit_x(), Vector3::unit_y()));
+        let tangent = (Vector3::unit_x() - normal * normal.x).normalize();
+        cgmath::assert_relative_eq!(
+            material.perturb_normal(normal, Some((0.5, 0.5)), derivatives),
+            tangent,
+            epsilon = 0.00001
+        );
+        // Without derivatives the tangent is still some direction on the surface.
+        assert_relative_eq!(
+            material
+                .perturb_normal(normal, Some((0.5, 0.5)), flat)
+                .dot(normal),
+            0.,
+            epsilon = 0.00001
+        );
+    }
+
+    #[test]
+    fn environment_lighting() {
+        let mut map = Canvas::new(4, 2);
+        map.pixels.fill(vec![RGB::new(1., 1., 1.); 4]);
+        let environment = EnvironmentLight::from(map);
+        let material = Material::default();
+        for normal in [Vector3::unit_y(), Vector3::new(-1., 0.5, 0.2).normalize()] {
+            assert_relative_eq!(
+                material.environment_lighting(&environment, Point3::origin(), normal),
+                RGB::new(0.9, 0.9, 0.9),
+                max_relative = 0.0001
+            );
+        }
+    }
+
+    #[test]
+    fn spot_lighting() {
+        let spot = Light::Spot {
+            position: Point3::new(0., 0., -10.),
+            direction: Vector3::unit_z(),
+            intensity: RGB::new(1., 1., 1.),
+            inner: 0.1,
+            outer: 0.2,
+        };
+        let material = Material::default();
+        let negz = -Vector3::unit_z();
+        // On the axis the spot matches a point light.
+        assert_relative_eq!(
+            material.lighting(spot, Point3::origin(), negz, negz, false),
+            RGB::new(1.9, 1.9, 1.9),
+            max_relative = 0.0001
+        );
+        // Well outside the outer cone only the ambient term is left.
+        assert_relative_eq!(
+            material.lighting(spot, Point3::new(5., 0., 0.), negz, negz, false),
+            RGB::new(0.1, 0.1, 0.1),
+            max_relative = 0.0001
+        );
+    }
+
+    #[test]
+    fn attenuation() {
+        let material = Material {
+            ambient: 0.,
+            specular: 0.,
+            ..Material::default()
+        };
+        let diffuse = |distance: f32| {
+            let light = Light::Point {
+                position: Point3::new(0., 0., -distance),
+                intensity: RGB::new(1., 1., 1.),
+                attenuation: true,
+            };
+            let negz = -Vector3::unit_z();
+            material
+                .lighting(light, Point3::origin(), negz, negz, false)
+                .r
+        };
+        assert_relative_eq!(diffuse(2.) / diffuse(4.), 4., max_relative = 0.0001);
+    }
+
+    #[test]
+    fn clearcoat() {
+        let light = Light::new(Point3::new(0., 10., -10.), RGB::new(1., 1., 1.));
+        let eyev = Vector3::new(0., -FRAC_1_SQRT_2, -FRAC_1_SQRT_2);
+        let normalv = -Vector3::unit_z();
+        let matte = Material {
+            specular: 0.,
+            ..Material::default()
+        };
+        let base = matte.lighting(light, Point3::origin(), eyev, normalv, false);
+        let coated = Material {
+            clearcoat: 1.,
+            ..matte.clone()
+        };
+        let color = coated.lighting(light, Point3::origin(), eyev, normalv, false);
+        assert!(color.r > base.r);
+        // Away from the mirror direction the coat only takes light away from the base.
+        let eyev = -Vector3::unit_z();
+        let base = matte.lighting(light, Point3::origin(), eyev, normalv, false);
+        let color = coated.lighting(light, Point3::origin(), eyev, normalv, false);
+        assert!(color.r < base.r);
+        assert_eq!(matte.clearcoat_reflectance(eyev, normalv), 0.);
+        assert_relative_eq!(coated.clearcoat_reflectance(eyev, normalv), 0.04);
+    }
+
+    #[test]
+    fn subsurface() {
+        let negz = -Vector3::unit_z();
+        let plain = Material::default();
+        let waxy = Material {
+            subsurface: 0.5,
+            ..Material::default()
+        };
+        // Just past the terminator only the wrapped diffuse reaches the surface.
+        let light = Light::new(Point3::new(0., 10., 1.), RGB::new(1., 1., 1.));
+        let lit = |m: &Material<f32>| m.lighting(light, Point3::origin(), negz, negz, false);
+        assert_relative_eq!(lit(&plain), RGB::new(0.1, 0.1, 0.1));
+        assert!(lit(&waxy).r > lit(&plain).r);
+        // Without wrap the diffuse term is Lambert's.
+        let light = Light::new(Point3::new(0., 10., -10.), RGB::new(1., 1., 1.));
+        let zero = Material {
+            subsurface: 0.,
+            ..Material::default()
+        };
+        assert_eq!(
+            zero.lighting(light, Point3::origin(), negz, negz, false),
+            plain.lighting(light, Point3::origin(), negz, negz, false)
+        );
+    }
 }
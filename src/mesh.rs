@@ -0,0 +1,150 @@
+use crate::shape::triangle::Triangle;
+use cgmath::{BaseFloat, InnerSpace, Point3, Vector3};
+
+fn find(parents: &mut [usize], i: usize) -> usize {
+    if parents[i] != i {
+        parents[i] = find(parents, parents[i]);
+    }
+    parents[i]
+}
+
+fn edges<T: BaseFloat>(tri: &Triangle<T>) -> [(Point3<T>, Point3<T>); 3] {
+    [(tri.p1, tri.p2), (tri.p2, tri.p3), (tri.p3, tri.p1)]
+}
+
+// Merge adjacent triangles whose normals differ by at most angle_eps (in radians) into polygons.
+// The triangles are expected to share exact vertices and have consistent winding.
+pub fn merge_coplanar<T: BaseFloat>(tris: &[Triangle<T>], angle_eps: T) -> Vec<Vec<Point3<T>>> {
+    let cos_eps = angle_eps.cos();
+    let mut parents = (0..tris.len()).collect::<Vec<_>>();
+    for i in 0..tris.len() {
+        for j in i + 1..tris.len() {
+            let adjacent = edges(&tris[i])
+                .iter()
+                .any(|&(a, b)| edges(&tris[j]).contains(&(b, a)));
+            if adjacent && tris[i].normal.dot(tris[j].normal) >= cos_eps {
+                let (ri, rj) = (find(&mut parents, i), find(&mut parents, j));
+                parents[ri] = rj;
+            }
+        }
+    }
+    let mut polygons = Vec::new();
+    for root in 0..tris.len() {
+        if find(&mut parents, root) != root {
+            continue;
+        }
+        let cluster = (0..tris.len())
+            .filter(|&i| find(&mut parents, i) == root)
+            .flat_map(|i| edges(&tris[i]))
+            .collect::<Vec<_>>();
+        // Interior edges appear once in each direction.
+        let mut boundary = cluster
+            .iter()
+            .filter(|&&(a, b)| !cluster.contains(&(b, a)))
+            .copied()
+            .collect::<Vec<_>>();
+        while let Some((start, mut end)) = boundary.pop() {
+            let mut polygon = vec![start];
+            while end != start {
+                match boundary.iter().position(|&(a, _)| a == end) {
+                    Some(index) => {
+                        polygon.push(end);
+                        end = boundary.swap_remove(index).1;
+                    }
+                    None => break,
+                }
+            }
+            polygons.push(remove_collinear(polygon));
+        }
+    }
+    polygons
+}
+
+// Edges shared by a triangle facing the viewer and one facing away, as seen along view_dir.
+// Each edge is returned with the winding of its front-facing triangle.
+pub fn silhouette_edges<T: BaseFloat>(
+    tris: &[Triangle<T>],
+    view_dir: Vector3<T>,
+) -> Vec<(Point3<T>, Point3<T>)> {
+    let front = |tri: &Triangle<T>| tri.normal.dot(view_dir) < T::zero();
+    let mut silhouette = Vec::new();
+    for tri in tris.iter().filter(|&tri| front(tri)) {
+        for (a, b) in edges(tri) {
+            if tris
+                .iter()
+                .any(|other| !front(other) && edges(other).contains(&(b, a)))
+            {
+                silhouette.push((a, b));
+            }
+        }
+    }
+    silhouette
+}
+
+fn remove_collinear<T: BaseFloat>(polygon: Vec<Point3<T>>) -> Vec<Point3<T>> {
+    let n = polygon.len();
+    (0..n)
+        .filter(|&i| {
+            let (prev, cur, next) = (polygon[(i + n - 1) % n], polygon[i], polygon[(i + 1) % n]);
+            (cur - prev).cross(next - cur).magnitude2() > T::epsilon()
+        })
+        .map(|i| polygon[i])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_coplanar() {
+        let a = Point3::new(0., 0., 0.);
+        let b = Point3::new(1., 0., 0.);
+        let c = Point3::new(1., 1., 0.);
+        let d = Point3::new(0., 1., 0.);
+        let square = [Triangle::from(a, b, c), Triangle::from(a, c, d)];
+        let polygons = super::merge_coplanar(&square, 0.01);
+        assert_eq!(polygons.len(), 1);
+        assert_eq!(polygons[0].len(), 4);
+        for p in [a, b, c, d] {
+            assert!(polygons[0].contains(&p));
+        }
+        // A folded pair stays as two triangles.
+        let folded = [
+            Triangle::from(a, b, c),
+            Triangle::from(a, c, Point3::new(0., 1., 1.)),
+        ];
+        assert_eq!(
+            super::merge_coplanar(&folded, 0.01)
+                .iter()
+                .map(|p| p.len())
+                .collect::<Vec<_>>(),
+            vec![3, 3]
+        );
+    }
+
+    #[test]
+    fn silhouette_edges() {
+        let a = Point3::new(0., 0., 0.);
+        let b = Point3::new(1., 0., 0.);
+        let c = Point3::new(0., 1., 0.);
+        let d = Point3::new(0., 0., 1.);
+        let tetrahedron = [
+            Triangle::from(a, b, c),
+            Triangle::from(a, d, b),
+            Triangle::from(a, c, d),
+            Triangle::from(b, d, c),
+        ];
+        // Only the slanted face is visible, so its outline is the silhouette.
+        let edges = super::silhouette_edges(&tetrahedron, Vector3::new(-1., -2., -3.));
+        assert_eq!(edges.len(), 3);
+        for edge in [(b, d), (d, c), (c, b)] {
+            assert!(edges.contains(&edge));
+        }
+        // With two faces towards the viewer and two away, the outline has four edges.
+        assert_eq!(
+            super::silhouette_edges(&tetrahedron, Vector3::new(-1., -1., 1.)).len(),
+            4
+        );
+    }
+}
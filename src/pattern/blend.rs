@@ -0,0 +1,60 @@
+use crate::pattern::{Pattern, TraitPattern};
+use cgmath::{BaseFloat, Matrix4, Point3};
+use rgb::RGB;
+
+// The channel-wise average of two patterns, each sampled in its own pattern space,
+// so two stripes at different angles make a plaid.
+#[derive(Clone, derive_more::Constructor, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Blend<T> {
+    pub a: Box<Pattern<T>>,
+    pub b: Box<Pattern<T>>,
+    pub transform: Matrix4<T>,
+}
+
+impl<T: BaseFloat> TraitPattern<T> for Blend<T> {
+    fn transform(&self) -> Matrix4<T> {
+        self.transform
+    }
+
+    fn at(&self, point: Point3<T>) -> RGB<T> {
+        let half = T::from(0.5).unwrap();
+        (self.a.at_local(point) + self.b.at_local(point)) * half
+    }
+
+    fn alpha_at(&self, point: Point3<T>) -> T {
+        let half = T::from(0.5).unwrap();
+        (self.a.alpha_at_local(point) + self.b.alpha_at_local(point)) * half
+    }
+}
+
+mod tests {
+    use super::*;
+    use crate::pattern::stripe::Stripe;
+    use cgmath::{Deg, EuclideanSpace, SquareMatrix};
+
+    #[test]
+    fn at() {
+        let white = RGB::new(1., 1., 1.);
+        let black = RGB::new(0., 0., 0.);
+        let gray = RGB::new(0.5, 0.5, 0.5);
+        let across = Pattern::Stripe(Stripe::new(white, black, Matrix4::identity()));
+        let along = Pattern::Stripe(Stripe::new(white, black, Matrix4::from_angle_y(Deg(90.))));
+        let plaid = Blend::new(
+            Box::new(across.clone()),
+            Box::new(along),
+            Matrix4::identity(),
+        );
+        // Where the stripes agree the color is kept, and where they cross it is gray.
+        assert_eq!(plaid.at(Point3::origin()), white);
+        assert_eq!(plaid.at(Point3::new(0.5, 0., 0.5)), gray);
+        assert_eq!(plaid.at(Point3::new(1.5, 0., -0.5)), gray);
+        assert_eq!(plaid.at(Point3::new(1.5, 0., 0.5)), black);
+        let same = Blend::new(
+            Box::new(across.clone()),
+            Box::new(across),
+            Matrix4::identity(),
+        );
+        assert_eq!(same.at(Point3::new(1.5, 0., 0.)), black);
+    }
+}
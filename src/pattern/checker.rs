@@ -3,6 +3,7 @@ use cgmath::{BaseFloat, Matrix4, Point3};
 use rgb::RGB;
 
 #[derive(Clone, derive_more::Constructor, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Checker<T> {
     a: RGB<T>,
     b: RGB<T>,
@@ -16,7 +17,7 @@ impl<T: BaseFloat> TraitPattern<T> for Checker<T> {
 
     fn at(&self, point: Point3<T>) -> RGB<T> {
         let i: i32 = num_traits::cast(point.x.floor() + point.y.floor() + point.z.floor()).unwrap();
-        if i % 2 == 0 {
+        if i.rem_euclid(2) == 0 {
             self.a
         } else {
             self.b
@@ -41,4 +42,16 @@ mod tests {
         assert_eq!(checker.at(Point3::new(0., 0., 0.99)), white);
         assert_eq!(checker.at(Point3::new(0., 0., 1.01)), black);
     }
+
+    #[test]
+    fn negative() {
+        let white = RGB::new(1., 1., 1.);
+        let black = RGB::new(0., 0., 0.);
+        let checker = Checker::new(white, black, Matrix4::identity());
+        assert_eq!(checker.at(Point3::new(-0.5, 0., 0.)), black);
+        assert_eq!(checker.at(Point3::new(-1.5, 0., 0.)), white);
+        assert_eq!(checker.at(Point3::new(-2.5, 0., 0.)), black);
+        assert_eq!(checker.at(Point3::new(-0.5, -0.5, 0.)), white);
+        assert_eq!(checker.at(Point3::new(-1.5, -0.5, -0.5)), white);
+    }
 }
@@ -0,0 +1,100 @@
+use crate::{
+    canvas::{Canvas, WrapMode},
+    pattern::{uv::UvMapping, TraitPattern},
+};
+use cgmath::{BaseFloat, Matrix4, Point3};
+use rgb::RGB;
+
+// An image laid over the shape by a UV mapping, sampled at the nearest pixel.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ImageTexture<T> {
+    pub canvas: Canvas<RGB<T>>,
+    pub mapping: UvMapping,
+    pub transform: Matrix4<T>,
+    // The coverage of each pixel, from 0 where the surface is cut out to 1; opaque if None.
+    #[cfg_attr(feature = "serde", serde(default = "Option::default"))]
+    pub alpha: Option<Canvas<T>>,
+}
+
+impl<T> ImageTexture<T> {
+    pub fn new(canvas: Canvas<RGB<T>>, mapping: UvMapping, transform: Matrix4<T>) -> Self {
+        ImageTexture {
+            canvas,
+            mapping,
+            transform,
+            alpha: None,
+        }
+    }
+
+    // Cut the texture out where the grayscale alpha image is below 1.
+    pub fn with_alpha(mut self, alpha: Canvas<T>) -> Self {
+        self.alpha = Some(alpha);
+        self
+    }
+}
+
+impl<T: BaseFloat> ImageTexture<T> {
+    // The pixel at (u, v), where (0, 0) is the bottom left of the image and (1, 1) the top right.
+    // An empty image is black.
+    pub fn at_uv(&self, u: T, v: T) -> RGB<T> {
+        self.canvas
+            .sample_wrapped(u, v, WrapMode::Clamp)
+            .unwrap_or(RGB::new(T::zero(), T::zero(), T::zero()))
+    }
+
+    // The coverage at (u, v), opaque without an alpha image.
+    pub fn alpha_at_uv(&self, u: T, v: T) -> T {
+        self.alpha
+            .as_ref()
+            .and_then(|alpha| alpha.sample_wrapped(u, v, WrapMode::Clamp))
+            .unwrap_or(T::one())
+    }
+}
+
+impl<T: BaseFloat> TraitPattern<T> for ImageTexture<T> {
+    fn transform(&self) -> Matrix4<T> {
+        self.transform
+    }
+
+    fn at(&self, point: Point3<T>) -> RGB<T> {
+        let (u, v) = self.mapping.uv(point);
+        self.at_uv(u, v)
+    }
+
+    fn alpha_at(&self, point: Point3<T>) -> T {
+        let (u, v) = self.mapping.uv(point);
+        self.alpha_at_uv(u, v)
+    }
+}
+
+mod tests {
+    use super::*;
+    use cgmath::SquareMatrix;
+
+    #[test]
+    fn at() {
+        let (red, green, blue, white) = (
+            RGB::new(1., 0., 0.),
+            RGB::new(0., 1., 0.),
+            RGB::new(0., 0., 1.),
+            RGB::new(1., 1., 1.),
+        );
+        let mut canvas = Canvas::new(2, 2);
+        canvas.pixels = vec![vec![red, green], vec![blue, white]];
+        let texture = ImageTexture::new(canvas.clone(), UvMapping::Spherical, Matrix4::identity());
+        assert_eq!(texture.at_uv(0., 1.), red);
+        assert_eq!(texture.at_uv(1., 1.), green);
+        assert_eq!(texture.at_uv(0., 0.), blue);
+        assert_eq!(texture.at_uv(1., 0.), white);
+        // Above and below the equator, facing -z and -x.
+        assert_eq!(texture.at(Point3::new(0., 0.5, -0.8)), red);
+        assert_eq!(texture.at(Point3::new(0., -0.5, -0.8)), blue);
+        assert_eq!(texture.at(Point3::new(-0.8, 0.5, 0.)), green);
+        assert_eq!(texture.at(Point3::new(-0.8, -0.5, 0.)), white);
+        // On the cube each face shows the whole image, here the back and right faces.
+        let texture = ImageTexture::new(canvas, UvMapping::Cube, Matrix4::identity());
+        assert_eq!(texture.at(Point3::new(0.9, 0.9, -1.)), red);
+        assert_eq!(texture.at(Point3::new(1., -0.9, -0.9)), white);
+    }
+}
@@ -1,35 +1,186 @@
+pub mod blend;
 pub mod checker;
 pub mod gradient;
+pub mod image_texture;
+pub mod noise;
 pub mod ring;
 pub mod stripe;
 pub mod test;
+pub mod two_pattern;
+pub mod uv;
 
 use crate::{
-    pattern::{checker::Checker, gradient::Gradient, ring::Ring, stripe::Stripe, test::Test},
+    pattern::{
+        blend::Blend,
+        checker::Checker,
+        gradient::Gradient,
+        image_texture::ImageTexture,
+        noise::{Noise, Perturbed},
+        ring::Ring,
+        stripe::Stripe,
+        test::Test,
+        two_pattern::TwoPattern,
+        uv::UvMapping,
+    },
     shape::Shape,
 };
-use cgmath::{BaseFloat, Matrix4, Point3, SquareMatrix};
-use rgb::RGB;
+use cgmath::{BaseFloat, Matrix4, Point3, SquareMatrix, Vector3};
+use rgb::{RGB, RGBA};
+use std::ops::{Add, Mul};
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Pattern<T> {
     Solid(RGB<T>),
+    // A solid color with coverage; where alpha is below one, what lies behind shows through.
+    SolidAlpha(RGBA<T>),
     Stripe(Stripe<T>),
     Gradient(Gradient<T>),
     Ring(Ring<T>),
     Checker(Checker<T>),
     Test(Test<T>),
+    Noise(Noise<T>),
+    // The inner pattern sampled at noisily displaced points, to break up regular patterns.
+    Perturbed(Perturbed<T>),
+    Blend(Blend<T>),
+    TwoPattern(TwoPattern<T>),
+    ImageTexture(ImageTexture<T>),
+    // Box mapping: the pattern projected along each axis, blended by the surface normal.
+    // Each projection lays the plane's two coordinates onto the pattern's x and y.
+    Triplanar(Box<Pattern<T>>),
 }
 
 impl<T: BaseFloat> Pattern<T> {
     pub fn at(&self, point: Point3<T>) -> RGB<T> {
         match self {
             Pattern::Solid(s) => s.at(point),
+            Pattern::SolidAlpha(s) => s.at(point),
             Pattern::Stripe(s) => s.at(point),
             Pattern::Gradient(s) => s.at(point),
             Pattern::Ring(s) => s.at(point),
             Pattern::Checker(s) => s.at(point),
             Pattern::Test(s) => s.at(point),
+            Pattern::Noise(s) => s.at(point),
+            Pattern::Perturbed(s) => s.at(point),
+            Pattern::Blend(s) => s.at(point),
+            Pattern::TwoPattern(s) => s.at(point),
+            Pattern::ImageTexture(s) => s.at(point),
+            // Without a normal, weigh the three projections equally.
+            Pattern::Triplanar(p) => {
+                Pattern::blend(point, Vector3::new(T::one(), T::one(), T::one()), |p2| {
+                    p.at(p2)
+                })
+            }
+        }
+    }
+
+    // The color at a point on a surface with the given normal.
+    pub fn at_normal(&self, point: Point3<T>, normal: Vector3<T>) -> RGB<T> {
+        match self {
+            Pattern::Triplanar(p) => Pattern::blend(
+                point,
+                Vector3::new(normal.x.abs(), normal.y.abs(), normal.z.abs()),
+                |p2| p.at(p2),
+            ),
+            _ => self.at(point),
+        }
+    }
+
+    // Mix what sample gives at the YZ, XZ and XY projections of the point by the x, y and z
+    // weights.
+    fn blend<C: Add<Output = C> + Mul<T, Output = C>>(
+        point: Point3<T>,
+        weights: Vector3<T>,
+        sample: impl Fn(Point3<T>) -> C,
+    ) -> C {
+        let total = weights.x + weights.y + weights.z;
+        let yz = sample(Point3::new(point.z, point.y, T::zero()));
+        let xz = sample(Point3::new(point.x, point.z, T::zero()));
+        let xy = sample(Point3::new(point.x, point.y, T::zero()));
+        yz * (weights.x / total) + xz * (weights.y / total) + xy * (weights.z / total)
+    }
+
+    pub fn transform(&self) -> Matrix4<T> {
+        match self {
+            Pattern::Solid(s) => s.transform(),
+            Pattern::SolidAlpha(s) => s.transform(),
+            Pattern::Stripe(s) => s.transform(),
+            Pattern::Gradient(s) => s.transform(),
+            Pattern::Ring(s) => s.transform(),
+            Pattern::Checker(s) => s.transform(),
+            Pattern::Test(s) => s.transform(),
+            Pattern::Noise(s) => s.transform(),
+            Pattern::Perturbed(s) => s.transform(),
+            Pattern::Blend(s) => s.transform(),
+            Pattern::TwoPattern(s) => s.transform(),
+            Pattern::ImageTexture(s) => s.transform(),
+            Pattern::Triplanar(_) => Matrix4::identity(),
+        }
+    }
+
+    // The color at a point given in the space containing the pattern, as when nested.
+    pub fn at_local(&self, point: Point3<T>) -> RGB<T> {
+        let inverse = self.transform().invert().unwrap();
+        self.at(Point3::from_homogeneous(inverse * point.to_homogeneous()))
+    }
+
+    // The coverage at a point given in the space containing the pattern, as when nested.
+    pub fn alpha_at_local(&self, point: Point3<T>) -> T {
+        let inverse = self.transform().invert().unwrap();
+        self.alpha_at(Point3::from_homogeneous(inverse * point.to_homogeneous()))
+    }
+
+    // The color at the texture coordinates of a mesh hit, for an image texture laid by them;
+    // None if the pattern is found by point instead.
+    pub fn at_texture_uv(&self, (u, v): (T, T)) -> Option<RGB<T>> {
+        match self {
+            Pattern::ImageTexture(t) if t.mapping == UvMapping::Mesh => Some(t.at_uv(u, v)),
+            _ => None,
+        }
+    }
+
+    // The coverage at the texture coordinates of a mesh hit, like at_texture_uv.
+    pub fn alpha_at_texture_uv(&self, (u, v): (T, T)) -> Option<T> {
+        match self {
+            Pattern::ImageTexture(t) if t.mapping == UvMapping::Mesh => Some(t.alpha_at_uv(u, v)),
+            _ => None,
+        }
+    }
+
+    // The coverage at a point on the object, found in pattern space like the color of at_object.
+    // A point that cannot be brought into object space counts as covered.
+    pub fn alpha_at_object(&self, object: &Shape<T>, world_point: Point3<T>) -> T {
+        object
+            .world_to_object(world_point)
+            .and_then(|object_point| {
+                let inverse = self.transform().invert()?;
+                Some(self.alpha_at(Point3::from_homogeneous(
+                    inverse * object_point.to_homogeneous(),
+                )))
+            })
+            .unwrap_or(T::one())
+    }
+
+    pub fn alpha_at(&self, point: Point3<T>) -> T {
+        match self {
+            Pattern::Solid(s) => s.alpha_at(point),
+            Pattern::SolidAlpha(s) => s.alpha_at(point),
+            Pattern::Stripe(s) => s.alpha_at(point),
+            Pattern::Gradient(s) => s.alpha_at(point),
+            Pattern::Ring(s) => s.alpha_at(point),
+            Pattern::Checker(s) => s.alpha_at(point),
+            Pattern::Test(s) => s.alpha_at(point),
+            Pattern::Noise(s) => s.alpha_at(point),
+            Pattern::Perturbed(s) => s.alpha_at(point),
+            Pattern::Blend(s) => s.alpha_at(point),
+            Pattern::TwoPattern(s) => s.alpha_at(point),
+            Pattern::ImageTexture(s) => s.alpha_at(point),
+            // Projected like the color.
+            Pattern::Triplanar(p) => {
+                Pattern::blend(point, Vector3::new(T::one(), T::one(), T::one()), |p2| {
+                    p.alpha_at(p2)
+                })
+            }
         }
     }
 }
@@ -39,6 +190,11 @@ pub trait TraitPattern<T: BaseFloat> {
 
     fn at(&self, point: Point3<T>) -> RGB<T>;
 
+    // Opaque unless the pattern says otherwise.
+    fn alpha_at(&self, _point: Point3<T>) -> T {
+        T::one()
+    }
+
     fn at_shape(&self, object: Shape<T>, world_point: Point3<T>) -> RGB<T> {
         let object_point = object.transform().invert().unwrap() * world_point.to_homogeneous();
         let pattern_point =
@@ -46,7 +202,8 @@ pub trait TraitPattern<T: BaseFloat> {
         self.at(pattern_point)
     }
 
-    fn at_shape_wrapper(&self, object: &Shape<T>, world_point: Point3<T>) -> Option<RGB<T>> {
+    // Like at_shape, but also undoing the transforms of the groups the object is nested in.
+    fn at_object(&self, object: &Shape<T>, world_point: Point3<T>) -> Option<RGB<T>> {
         object.world_to_object(world_point).map(|object_point| {
             let pattern_point = Point3::from_homogeneous(
                 self.transform().invert().unwrap() * object_point.to_homogeneous(),
@@ -54,6 +211,11 @@ pub trait TraitPattern<T: BaseFloat> {
             self.at(pattern_point)
         })
     }
+
+    #[deprecated(note = "renamed to at_object")]
+    fn at_shape_wrapper(&self, object: &Shape<T>, world_point: Point3<T>) -> Option<RGB<T>> {
+        self.at_object(object, world_point)
+    }
 }
 
 impl<T: BaseFloat> TraitPattern<T> for RGB<T> {
@@ -66,10 +228,34 @@ impl<T: BaseFloat> TraitPattern<T> for RGB<T> {
     }
 }
 
+impl<T: BaseFloat> TraitPattern<T> for RGBA<T> {
+    fn transform(&self) -> Matrix4<T> {
+        Matrix4::identity()
+    }
+
+    fn at(&self, _point: Point3<T>) -> RGB<T> {
+        self.rgb()
+    }
+
+    fn alpha_at(&self, _point: Point3<T>) -> T {
+        self.a
+    }
+}
+
 mod tests {
     use super::*;
-    use crate::{material::Material, shape::sphere::Sphere};
-    use cgmath::{EuclideanSpace, Vector3};
+    use crate::{
+        canvas::Canvas,
+        material::Material,
+        pattern::two_pattern::TwoPatternKind,
+        shape::{
+            get_rc, get_rc_with_parent,
+            group::{push, push_link, Group},
+            sphere::Sphere,
+        },
+    };
+    use cgmath::{assert_relative_eq, EuclideanSpace, Rad, Vector3};
+    use std::f32::consts::FRAC_PI_2;
 
     #[test]
     fn at() {
@@ -82,6 +268,55 @@ mod tests {
         }
     }
 
+    #[test]
+    fn triplanar() {
+        let box_mapped =
+            Pattern::Triplanar(Box::new(Pattern::Test(Test::new(Matrix4::identity()))));
+        let point = Point3::new(1., 2., 3.);
+        // Facing +x, only the YZ projection shows: z lands on the pattern's x and y stays y.
+        assert_eq!(
+            box_mapped.at_normal(point, Vector3::unit_x()),
+            RGB::new(3., 2., 0.)
+        );
+        assert_eq!(
+            box_mapped.at_normal(point, -Vector3::unit_y()),
+            RGB::new(1., 3., 0.)
+        );
+        let color = box_mapped.at_normal(point, Vector3::new(0.9, 0.1, 0.1));
+        assert!(color.r > 2.5);
+    }
+
+    #[test]
+    fn alpha() {
+        let clear = Pattern::SolidAlpha(RGBA::new(1., 1., 1., 0.));
+        let opaque = Pattern::Solid(RGB::new(0., 0., 0.));
+        // A cutout nested in a checker covers only its own cells, and a blend halves it.
+        let checker = Pattern::TwoPattern(TwoPattern::new(
+            Box::new(clear),
+            Box::new(opaque.clone()),
+            Matrix4::identity(),
+            TwoPatternKind::Checker,
+        ));
+        assert_eq!(checker.alpha_at(Point3::new(0.5, 0.5, 0.5)), 0.);
+        assert_eq!(checker.alpha_at(Point3::new(1.5, 0.5, 0.5)), 1.);
+        let blend = Pattern::Blend(Blend::new(
+            Box::new(checker.clone()),
+            Box::new(opaque),
+            Matrix4::identity(),
+        ));
+        assert_eq!(blend.alpha_at(Point3::new(0.5, 0.5, 0.5)), 0.5);
+        // Of the three projections of the point, only the YZ one lands in a clear cell.
+        let box_mapped = Pattern::Triplanar(Box::new(checker));
+        assert_relative_eq!(box_mapped.alpha_at(Point3::new(1.5, 0.5, 0.5)), 2. / 3.);
+        // A texture is cut out by its alpha image.
+        let texture = ImageTexture::new(Canvas::new(1, 1), UvMapping::Planar, Matrix4::identity());
+        let mut alpha = Canvas::new(1, 1);
+        alpha.pixels[0][0] = 0.25;
+        let point = Point3::new(0.5, 0., 0.5);
+        assert_eq!(texture.alpha_at(point), 1.);
+        assert_eq!(texture.with_alpha(alpha).alpha_at(point), 0.25);
+    }
+
     #[test]
     fn at_shape() {
         let white = RGB::new(1., 1., 1.);
@@ -138,4 +373,44 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn at_object() {
+        let white = RGB::new(1., 1., 1.);
+        let black = RGB::new(0., 0., 0.);
+        let outer = get_rc(Shape::Group(Group::new(
+            Matrix4::from_angle_y(Rad(FRAC_PI_2)),
+            Vec::new(),
+            false,
+            None,
+        )));
+        let inner = get_rc_with_parent(
+            Shape::Group(Group::new(Matrix4::from_scale(2.), Vec::new(), false, None)),
+            &outer,
+        );
+        push_link(&outer, inner.clone());
+        push(
+            &inner,
+            Shape::Sphere(Sphere::new(
+                Matrix4::from_translation(Vector3::unit_x() * 5.),
+                Material::default(),
+                None,
+            )),
+        );
+        let sphere = inner.borrow().as_group().unwrap().children[0].clone();
+        let sphere = sphere.borrow();
+        let pattern = Stripe::new(white, black, Matrix4::identity());
+        // The sphere's local x runs along world -z, and a local unit is two world units.
+        let world_point = |x: f32| Point3::new(0., 0., -2. * (5. + x));
+        assert_eq!(pattern.at_object(&sphere, world_point(0.5)), Some(white));
+        assert_eq!(pattern.at_object(&sphere, world_point(-0.5)), Some(black));
+        #[allow(deprecated)]
+        let renamed = pattern.at_shape_wrapper(&sphere, world_point(-0.5));
+        assert_eq!(renamed, Some(black));
+        // Without the groups the stripes come out wrong.
+        assert_eq!(
+            pattern.at_shape(sphere.clone(), world_point(0.5)),
+            pattern.at(Point3::new(-5., 0., -11.))
+        );
+    }
 }
@@ -0,0 +1,139 @@
+use crate::{
+    pattern::{Pattern, TraitPattern},
+    random::Rng,
+};
+use cgmath::{BaseFloat, Matrix4, Point3, SquareMatrix, Vector3};
+use rgb::RGB;
+
+// A value in [0, 1) fixed for each integer lattice point.
+fn lattice<T: BaseFloat>(x: i64, y: i64, z: i64) -> T {
+    T::from(Rng::stream(0, &[x as u64, y as u64, z as u64]).next_f64()).unwrap()
+}
+
+// Value noise: the lattice values around the point blended with a smoothstep, in [0, 1).
+pub fn value_noise<T: BaseFloat>(point: Point3<T>) -> T {
+    let cell = |c: T| -> (i64, T) {
+        let floor = c.floor();
+        let f = c - floor;
+        let three = T::from(3).unwrap();
+        let two = T::from(2).unwrap();
+        (num_traits::cast(floor).unwrap(), f * f * (three - two * f))
+    };
+    let (x, fx) = cell(point.x);
+    let (y, fy) = cell(point.y);
+    let (z, fz) = cell(point.z);
+    let lerp = |a: T, b: T, t: T| a + (b - a) * t;
+    let side = |dz: i64| {
+        lerp(
+            lerp(lattice(x, y, z + dz), lattice(x + 1, y, z + dz), fx),
+            lerp(lattice(x, y + 1, z + dz), lattice(x + 1, y + 1, z + dz), fx),
+            fy,
+        )
+    };
+    lerp(side(0), side(1), fz)
+}
+
+// Grey noise whose features are about 1 / scale apart.
+#[derive(Clone, derive_more::Constructor, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Noise<T> {
+    pub scale: T,
+    pub transform: Matrix4<T>,
+}
+
+impl<T: BaseFloat> TraitPattern<T> for Noise<T> {
+    fn transform(&self) -> Matrix4<T> {
+        self.transform
+    }
+
+    fn at(&self, point: Point3<T>) -> RGB<T> {
+        let n = value_noise(point * self.scale);
+        RGB::new(n, n, n)
+    }
+}
+
+// The inner pattern sampled at a point jittered by up to scale along each axis.
+#[derive(Clone, derive_more::Constructor, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Perturbed<T> {
+    pub inner: Box<Pattern<T>>,
+    pub scale: T,
+}
+
+impl<T: BaseFloat> Perturbed<T> {
+    fn jitter(&self, point: Point3<T>) -> Point3<T> {
+        // Offset the samples so the three axes get unrelated noise.
+        let offset = |o: f64| {
+            let o = T::from(o).unwrap();
+            value_noise(point + Vector3::new(o, o, o)) * T::from(2).unwrap() - T::one()
+        };
+        point + Vector3::new(offset(0.), offset(31.7), offset(67.3)) * self.scale
+    }
+}
+
+impl<T: BaseFloat> TraitPattern<T> for Perturbed<T> {
+    fn transform(&self) -> Matrix4<T> {
+        Matrix4::identity()
+    }
+
+    fn at(&self, point: Point3<T>) -> RGB<T> {
+        self.inner.at(self.jitter(point))
+    }
+
+    fn alpha_at(&self, point: Point3<T>) -> T {
+        self.inner.alpha_at(self.jitter(point))
+    }
+}
+
+mod tests {
+    use super::*;
+    use crate::pattern::stripe::Stripe;
+
+    #[test]
+    fn value_noise() {
+        let points = [
+            Point3::new(0.3, -1.2, 4.5),
+            Point3::new(-7.1, 0.25, 2.),
+            Point3::new(10.5, 3.3, -0.7),
+        ];
+        for point in points {
+            let n = super::value_noise::<f64>(point);
+            assert_eq!(n, super::value_noise(point));
+            assert!((0. ..1.).contains(&n));
+        }
+        // Lattice points take their own value, and the noise is continuous between them.
+        assert_eq!(
+            super::value_noise::<f64>(Point3::new(2., 3., 4.)),
+            lattice::<f64>(2, 3, 4)
+        );
+        let a = super::value_noise::<f64>(Point3::new(0.5, 0.5, 0.5));
+        let b = super::value_noise::<f64>(Point3::new(0.5001, 0.5, 0.5));
+        assert!((a - b).abs() < 0.001);
+    }
+
+    #[test]
+    fn noise() {
+        let noise = Noise::new(2., Matrix4::identity());
+        let color = noise.at(Point3::new(0.3, 0.2, 0.1));
+        assert_eq!(color.r, color.g);
+        assert_eq!(color.r, super::value_noise(Point3::new(0.6, 0.4, 0.2)));
+    }
+
+    #[test]
+    fn perturbed() {
+        let white = RGB::new(1., 1., 1.);
+        let black = RGB::new(0., 0., 0.);
+        let stripe = Pattern::Stripe(Stripe::new(white, black, Matrix4::identity()));
+        let still = Perturbed::new(Box::new(stripe.clone()), 0.);
+        let shaken = Perturbed::new(Box::new(stripe.clone()), 0.5);
+        let mut moved = 0;
+        for i in 0..100 {
+            let point = Point3::new(i as f64 * 0.173 - 8., 0.37 * i as f64, 0.);
+            assert_eq!(still.at(point), stripe.at(point));
+            if shaken.at(point) != stripe.at(point) {
+                moved += 1;
+            }
+        }
+        assert!(moved > 0);
+    }
+}
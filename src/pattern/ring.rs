@@ -3,6 +3,7 @@ use cgmath::{BaseFloat, Matrix4, Point3};
 use rgb::RGB;
 
 #[derive(Clone, derive_more::Constructor, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Ring<T> {
     a: RGB<T>,
     b: RGB<T>,
@@ -16,7 +17,7 @@ impl<T: BaseFloat> TraitPattern<T> for Ring<T> {
 
     fn at(&self, point: Point3<T>) -> RGB<T> {
         let i: i32 = num_traits::cast((point.x.powi(2) + point.z.powi(2)).sqrt().floor()).unwrap();
-        if i % 2 == 0 {
+        if i.rem_euclid(2) == 0 {
             self.a
         } else {
             self.b
@@ -38,4 +39,14 @@ mod tests {
         assert_eq!(ring.at(Point3::from_vec(Vector3::unit_z())), black);
         assert_eq!(ring.at(Point3::new(0.708, 0., 0.708)), black);
     }
+
+    #[test]
+    fn negative() {
+        let white = RGB::new(1., 1., 1.);
+        let black = RGB::new(0., 0., 0.);
+        let ring = Ring::new(white, black, Matrix4::identity());
+        assert_eq!(ring.at(Point3::new(-0.5, 0., 0.)), white);
+        assert_eq!(ring.at(Point3::new(-1.5, 0., 0.)), black);
+        assert_eq!(ring.at(Point3::new(0., 0., -2.5)), white);
+    }
 }
@@ -3,6 +3,7 @@ use cgmath::{BaseFloat, Matrix4, Point3};
 use rgb::RGB;
 
 #[derive(Clone, derive_more::Constructor, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Stripe<T> {
     a: RGB<T>,
     b: RGB<T>,
@@ -16,7 +17,7 @@ impl<T: BaseFloat> TraitPattern<T> for Stripe<T> {
 
     fn at(&self, point: Point3<T>) -> RGB<T> {
         let i: i32 = num_traits::cast(point.x.floor()).unwrap();
-        if i % 2 == 0 {
+        if i.rem_euclid(2) == 0 {
             self.a
         } else {
             self.b
@@ -44,4 +45,14 @@ mod tests {
         assert_eq!(stripe.at(Point3::from_vec(-Vector3::unit_x())), black);
         assert_eq!(stripe.at(Point3::from_vec(Vector3::unit_x() * -1.1)), white);
     }
+
+    #[test]
+    fn negative() {
+        let white = RGB::new(1., 1., 1.);
+        let black = RGB::new(0., 0., 0.);
+        let stripe = Stripe::new(white, black, Matrix4::identity());
+        assert_eq!(stripe.at(Point3::new(-0.5, 0., 0.)), black);
+        assert_eq!(stripe.at(Point3::new(-1.5, 0., 0.)), white);
+        assert_eq!(stripe.at(Point3::new(-2.5, 0., 0.)), black);
+    }
 }
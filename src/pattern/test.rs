@@ -3,6 +3,7 @@ use cgmath::{BaseFloat, Matrix4, Point3};
 use rgb::RGB;
 
 #[derive(Clone, derive_more::Constructor, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Test<T> {
     transform: Matrix4<T>,
 }
@@ -0,0 +1,121 @@
+use crate::pattern::{Pattern, TraitPattern};
+use cgmath::{BaseFloat, Matrix4, Point3};
+use rgb::RGB;
+use std::ops::{Add, Mul};
+
+// How a TwoPattern lays out its sub-patterns, following the flat pattern of the same name.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TwoPatternKind {
+    Stripe,
+    Gradient,
+    Ring,
+    Checker,
+}
+
+impl TwoPatternKind {
+    // How much of the second pattern shows at the point, from 0 to 1.
+    fn weight<T: BaseFloat>(&self, point: Point3<T>) -> T {
+        let parity = |c: T| {
+            let i: i32 = num_traits::cast(c.floor()).unwrap();
+            T::from(i.rem_euclid(2)).unwrap()
+        };
+        match self {
+            TwoPatternKind::Stripe => parity(point.x),
+            TwoPatternKind::Gradient => point.x - point.x.floor(),
+            TwoPatternKind::Ring => parity((point.x.powi(2) + point.z.powi(2)).sqrt()),
+            TwoPatternKind::Checker => parity(point.x.floor() + point.y.floor() + point.z.floor()),
+        }
+    }
+}
+
+// A stripe, gradient, ring or checker alternating between two patterns instead of two colors.
+// Each sub-pattern is evaluated in its own space, nested inside this one.
+#[derive(Clone, derive_more::Constructor, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TwoPattern<T> {
+    pub a: Box<Pattern<T>>,
+    pub b: Box<Pattern<T>>,
+    pub transform: Matrix4<T>,
+    pub kind: TwoPatternKind,
+}
+
+impl<T: BaseFloat> TwoPattern<T> {
+    // Mix what sample gives for the sub-patterns by how much of each shows at the point.
+    fn mix<C: Add<Output = C> + Mul<T, Output = C>>(
+        &self,
+        point: Point3<T>,
+        sample: impl Fn(&Pattern<T>) -> C,
+    ) -> C {
+        let weight = self.kind.weight(point);
+        if weight == T::zero() {
+            sample(&self.a)
+        } else if weight == T::one() {
+            sample(&self.b)
+        } else {
+            sample(&self.a) * (T::one() - weight) + sample(&self.b) * weight
+        }
+    }
+}
+
+impl<T: BaseFloat> TraitPattern<T> for TwoPattern<T> {
+    fn transform(&self) -> Matrix4<T> {
+        self.transform
+    }
+
+    fn at(&self, point: Point3<T>) -> RGB<T> {
+        self.mix(point, |p| p.at_local(point))
+    }
+
+    fn alpha_at(&self, point: Point3<T>) -> T {
+        self.mix(point, |p| p.alpha_at_local(point))
+    }
+}
+
+mod tests {
+    use super::*;
+    use crate::pattern::stripe::Stripe;
+    use cgmath::{Deg, EuclideanSpace, SquareMatrix};
+
+    #[test]
+    fn at() {
+        let white = RGB::new(1., 1., 1.);
+        let black = RGB::new(0., 0., 0.);
+        let red = RGB::new(1., 0., 0.);
+        let green = RGB::new(0., 1., 0.);
+        // Thin stripes along x in one cell, along z in the other.
+        let across = Pattern::Stripe(Stripe::new(white, black, Matrix4::from_scale(0.25)));
+        let along = Pattern::Stripe(Stripe::new(
+            red,
+            green,
+            Matrix4::from_angle_y(Deg(90.)) * Matrix4::from_scale(0.25),
+        ));
+        let checker = TwoPattern::new(
+            Box::new(across),
+            Box::new(along),
+            Matrix4::identity(),
+            TwoPatternKind::Checker,
+        );
+        // In the first cell the stripes change every quarter along x.
+        assert_eq!(checker.at(Point3::new(0.1, 0.5, 0.5)), white);
+        assert_eq!(checker.at(Point3::new(0.3, 0.5, 0.5)), black);
+        assert_eq!(checker.at(Point3::new(0.6, 0.5, 0.1)), white);
+        // In the neighbouring cell they change every quarter along z instead.
+        assert_eq!(checker.at(Point3::new(1.1, 0.5, 0.1)), green);
+        assert_eq!(checker.at(Point3::new(1.1, 0.5, 0.3)), red);
+        assert_eq!(checker.at(Point3::new(1.6, 0.5, 0.3)), red);
+        assert_eq!(checker.at(Point3::new(1.6, 0.5, 0.6)), green);
+        // A nested gradient blends the sub-patterns.
+        let gradient = TwoPattern::new(
+            Box::new(Pattern::Solid(white)),
+            Box::new(Pattern::Solid(black)),
+            Matrix4::identity(),
+            TwoPatternKind::Gradient,
+        );
+        assert_eq!(
+            gradient.at(Point3::new(0.25, 0., 0.)),
+            RGB::new(0.75, 0.75, 0.75)
+        );
+        assert_eq!(gradient.at(Point3::origin()), white);
+    }
+}
@@ -0,0 +1,169 @@
+use cgmath::{BaseFloat, EuclideanSpace, InnerSpace, Point3};
+
+// How a pattern lays a flat image over a shape's local space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UvMapping {
+    Spherical,
+    Planar,
+    Cylindrical,
+    // Each face of the unit cube shows the whole image.
+    Cube,
+    // The texture coordinates that came with a mesh, blended across each triangle. A point
+    // alone carries none, so sampling by point falls back to planar.
+    Mesh,
+}
+
+impl UvMapping {
+    pub fn uv<T: BaseFloat>(&self, point: Point3<T>) -> (T, T) {
+        match self {
+            UvMapping::Spherical => spherical_uv(point),
+            UvMapping::Planar | UvMapping::Mesh => planar_uv(point),
+            UvMapping::Cylindrical => cylindrical_uv(point),
+            UvMapping::Cube => {
+                let (_, u, v) = cube_uv(point);
+                (u, v)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CubeFace {
+    Left,
+    Right,
+    Front,
+    Back,
+    Up,
+    Down,
+}
+
+// The fraction of one turn around the y axis, 0 facing -z and growing clockwise seen from above.
+fn azimuth<T: BaseFloat>(point: Point3<T>) -> T {
+    let two_pi = T::from(std::f64::consts::TAU).unwrap();
+    let raw_u = point.x.atan2(point.z) / two_pi;
+    T::one() - (raw_u + T::from(0.5).unwrap())
+}
+
+// The (u, v) of a point on the unit sphere, where u goes once around the y axis and
+// v runs from the south pole at 0 to the north pole at 1.
+pub fn spherical_uv<T: BaseFloat>(point: Point3<T>) -> (T, T) {
+    let pi = T::from(std::f64::consts::PI).unwrap();
+    let phi = (point.y / point.to_vec().magnitude()).acos();
+    (azimuth(point), T::one() - phi / pi)
+}
+
+// The xz plane tiled with the image once per unit square.
+pub fn planar_uv<T: BaseFloat>(point: Point3<T>) -> (T, T) {
+    (point.x - point.x.floor(), point.z - point.z.floor())
+}
+
+// Once around the y axis, repeating every unit of height.
+pub fn cylindrical_uv<T: BaseFloat>(point: Point3<T>) -> (T, T) {
+    (azimuth(point), point.y - point.y.floor())
+}
+
+// The face of the unit cube the point lies on, picked by its largest coordinate like
+// Cube::local_normal_at, and the (u, v) on that face seen from outside with up along +y
+// (or along -z and +z for the top and bottom).
+pub fn cube_uv<T: BaseFloat>(point: Point3<T>) -> (CubeFace, T, T) {
+    let two = T::from(2).unwrap();
+    let face_coordinate = |c: T| {
+        let c = c + T::one();
+        (c - (c / two).floor() * two) / two
+    };
+    let (x, y, z) = (point.x, point.y, point.z);
+    let maxc = x.abs().max(y.abs()).max(z.abs());
+    if maxc == x.abs() {
+        if x > T::zero() {
+            (CubeFace::Right, face_coordinate(-z), face_coordinate(y))
+        } else {
+            (CubeFace::Left, face_coordinate(z), face_coordinate(y))
+        }
+    } else if maxc == y.abs() {
+        if y > T::zero() {
+            (CubeFace::Up, face_coordinate(x), face_coordinate(-z))
+        } else {
+            (CubeFace::Down, face_coordinate(x), face_coordinate(z))
+        }
+    } else if z > T::zero() {
+        (CubeFace::Front, face_coordinate(x), face_coordinate(y))
+    } else {
+        (CubeFace::Back, face_coordinate(-x), face_coordinate(y))
+    }
+}
+
+mod tests {
+    use super::*;
+    use cgmath::assert_relative_eq;
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    #[test]
+    fn spherical_uv() {
+        for (point, uv) in [
+            (Point3::new(0., 0., -1.), (0., 0.5)),
+            (Point3::new(1., 0., 0.), (0.25, 0.5)),
+            (Point3::new(0., 0., 1.), (0.5, 0.5)),
+            (Point3::new(-1., 0., 0.), (0.75, 0.5)),
+            (Point3::new(0., 1., 0.), (0.5, 1.)),
+            (Point3::new(0., -1., 0.), (0.5, 0.)),
+            (Point3::new(FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0.), (0.25, 0.75)),
+        ] {
+            let (u, v) = super::spherical_uv(point);
+            assert_relative_eq!(u, uv.0);
+            assert_relative_eq!(v, uv.1);
+        }
+    }
+
+    #[test]
+    fn planar_uv() {
+        for (point, uv) in [
+            (Point3::new(0.25, 0., 0.5), (0.25, 0.5)),
+            (Point3::new(0.25, 0., -0.25), (0.25, 0.75)),
+            (Point3::new(0.25, 0.5, -0.25), (0.25, 0.75)),
+            (Point3::new(1.25, 0., 0.5), (0.25, 0.5)),
+            (Point3::new(-0.25, 0., -1.75), (0.75, 0.25)),
+        ] {
+            assert_eq!(super::planar_uv(point), uv);
+        }
+    }
+
+    #[test]
+    fn cylindrical_uv() {
+        for (point, uv) in [
+            (Point3::new(0., 0., -1.), (0., 0.)),
+            (Point3::new(0., 0.5, -1.), (0., 0.5)),
+            (Point3::new(0., 1., -1.), (0., 0.)),
+            (
+                Point3::new(FRAC_1_SQRT_2, 0.5, -FRAC_1_SQRT_2),
+                (0.125, 0.5),
+            ),
+            (Point3::new(1., 0.5, 0.), (0.25, 0.5)),
+            (Point3::new(0., -0.25, 1.), (0.5, 0.75)),
+            (Point3::new(-1., 1.25, 0.), (0.75, 0.25)),
+        ] {
+            let (u, v) = super::cylindrical_uv(point);
+            assert_relative_eq!(u, uv.0);
+            assert_relative_eq!(v, uv.1);
+        }
+    }
+
+    #[test]
+    fn cube_uv() {
+        for (point, face, u, v) in [
+            (Point3::new(1., 0.5, -0.8), CubeFace::Right, 0.9, 0.75),
+            (Point3::new(1., -0.2, 0.9), CubeFace::Right, 0.05, 0.4),
+            (Point3::new(-1., 0.5, -0.8), CubeFace::Left, 0.1, 0.75),
+            (Point3::new(0.5, 1., -0.9), CubeFace::Up, 0.75, 0.95),
+            (Point3::new(-0.5, -1., 0.9), CubeFace::Down, 0.25, 0.95),
+            (Point3::new(-0.5, 0.5, 1.), CubeFace::Front, 0.25, 0.75),
+            (Point3::new(0.5, -0.5, -1.), CubeFace::Back, 0.25, 0.25),
+        ] {
+            let (f, fu, fv) = super::cube_uv(point);
+            assert_eq!(f, face);
+            assert_relative_eq!(fu, u);
+            assert_relative_eq!(fv, v);
+            assert!((0. ..=1.).contains(&fu) && (0. ..=1.).contains(&fv));
+        }
+    }
+}
@@ -0,0 +1,50 @@
+// SplitMix64: small, fast and fully determined by its seed, so noisy renders can be reproduced.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    // A generator for one of many independent streams drawn from the same seed.
+    pub fn stream(seed: u64, keys: &[u64]) -> Rng {
+        let mut rng = Rng::new(seed);
+        for &key in keys {
+            rng.state ^= key;
+            rng.next_u64();
+        }
+        rng
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rng() {
+        let sample = |seed| {
+            let mut rng = Rng::new(seed);
+            (0..8).map(|_| rng.next_f64()).collect::<Vec<_>>()
+        };
+        assert_eq!(sample(1), sample(1));
+        assert_ne!(sample(1), sample(2));
+        assert!(sample(3).iter().all(|&x| (0. ..1.).contains(&x)));
+        assert_ne!(Rng::stream(1, &[1, 2]), Rng::stream(1, &[2, 1]));
+    }
+}
@@ -0,0 +1,47 @@
+use cgmath::BaseFloat;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+// The arithmetic the core intersection code needs, so a SIMD packet holding several rays'
+// worth of values can stand in for a float. Methods work lane by lane and never branch.
+pub trait Scalar:
+    Copy
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    // The same value in every lane.
+    fn splat(value: f64) -> Self;
+    fn simd_min(self, other: Self) -> Self;
+    fn simd_max(self, other: Self) -> Self;
+    fn simd_sqrt(self) -> Self;
+}
+
+// Covers f32 and f64; a packet type defined in this crate can implement Scalar directly.
+impl<T: BaseFloat> Scalar for T {
+    fn splat(value: f64) -> T {
+        T::from(value).unwrap()
+    }
+
+    // Written as comparisons rather than Float::min so NaNs propagate as in the scalar code.
+    fn simd_min(self, other: T) -> T {
+        if self < other {
+            self
+        } else {
+            other
+        }
+    }
+
+    fn simd_max(self, other: T) -> T {
+        if self < other {
+            other
+        } else {
+            self
+        }
+    }
+
+    fn simd_sqrt(self) -> T {
+        self.sqrt()
+    }
+}
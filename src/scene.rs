@@ -0,0 +1,318 @@
+use crate::{
+    camera::Camera,
+    light::Light,
+    material::Material,
+    pattern::Pattern,
+    shape::{cube::Cube, plane::Plane, sphere::Sphere, Shape},
+    world::World,
+};
+use cgmath::{BaseFloat, EuclideanSpace, Matrix4, Point3, Rad, SquareMatrix, Vector3};
+use rgb::RGB;
+use std::{collections::HashMap, fmt::Display};
+use yaml_rust::{yaml::Hash, Yaml, YamlLoader};
+
+fn number<T: BaseFloat>(y: &Yaml) -> Option<T> {
+    match y {
+        Yaml::Integer(i) => T::from(*i),
+        Yaml::Real(_) => y.as_f64().and_then(T::from),
+        _ => None,
+    }
+}
+
+fn triple<T: BaseFloat>(y: &Yaml) -> Option<(T, T, T)> {
+    match y.as_vec()?.as_slice() {
+        [a, b, c] => Some((number(a)?, number(b)?, number(c)?)),
+        _ => None,
+    }
+}
+
+fn point<T: BaseFloat>(y: &Yaml) -> Option<Point3<T>> {
+    triple(y).map(|(x, y, z)| Point3::new(x, y, z))
+}
+
+fn color<T: BaseFloat>(y: &Yaml) -> Option<RGB<T>> {
+    triple(y).map(|(r, g, b)| RGB::new(r, g, b))
+}
+
+// A named material refers to its definition; unknown keys are ignored.
+fn material<T: BaseFloat>(y: &Yaml, defines: &HashMap<String, Yaml>) -> Material<T> {
+    let mut m = Material::default();
+    let y = match y {
+        Yaml::String(name) => defines.get(name).unwrap_or(&Yaml::BadValue),
+        _ => y,
+    };
+    for (key, value) in y.as_hash().into_iter().flatten() {
+        let field = match key.as_str() {
+            Some("color") => {
+                if let Some(c) = color(value) {
+                    m.pattern = Pattern::Solid(c);
+                }
+                continue;
+            }
+            Some("ambient") => &mut m.ambient,
+            Some("diffuse") => &mut m.diffuse,
+            Some("specular") => &mut m.specular,
+            Some("shininess") => &mut m.shininess,
+            Some("reflective") => &mut m.reflective,
+            Some("transparency") => &mut m.transparency,
+            Some("refractive-index") => &mut m.refractive_index,
+            _ => continue,
+        };
+        if let Some(v) = number(value) {
+            *field = v;
+        }
+    }
+    m
+}
+
+// The steps of a transform list apply in order, so the first one listed is applied first.
+// A step is either `[op, args...]` or the name of a defined transform list.
+fn transform<T: BaseFloat>(y: &Yaml, defines: &HashMap<String, Yaml>) -> Matrix4<T> {
+    transform_steps(y, defines, &mut Vec::new())
+}
+
+// A name that is already being expanded is skipped, so a list that refers to itself ends.
+fn transform_steps<'a, T: BaseFloat>(
+    y: &'a Yaml,
+    defines: &'a HashMap<String, Yaml>,
+    expanding: &mut Vec<&'a str>,
+) -> Matrix4<T> {
+    let mut m = Matrix4::identity();
+    for step in y.as_vec().into_iter().flatten() {
+        let step_matrix = match step {
+            Yaml::String(name) if !expanding.contains(&name.as_str()) => match defines.get(name) {
+                Some(d) => {
+                    expanding.push(name);
+                    let steps = transform_steps(d, defines, expanding);
+                    expanding.pop();
+                    steps
+                }
+                None => Matrix4::identity(),
+            },
+            Yaml::Array(a) => match a.split_first() {
+                Some((op, args)) => {
+                    let args = args.iter().filter_map(number).collect::<Vec<T>>();
+                    match (op.as_str(), args.as_slice()) {
+                        (Some("translate"), &[x, y, z]) => {
+                            Matrix4::from_translation(Vector3::new(x, y, z))
+                        }
+                        (Some("scale"), &[x, y, z]) => Matrix4::from_nonuniform_scale(x, y, z),
+                        (Some("rotate-x"), &[r]) => Matrix4::from_angle_x(Rad(r)),
+                        (Some("rotate-y"), &[r]) => Matrix4::from_angle_y(Rad(r)),
+                        (Some("rotate-z"), &[r]) => Matrix4::from_angle_z(Rad(r)),
+                        _ => Matrix4::identity(),
+                    }
+                }
+                None => Matrix4::identity(),
+            },
+            _ => Matrix4::identity(),
+        };
+        m = step_matrix * m;
+    }
+    m
+}
+
+// Load a scene in the YAML dialect of the book's example scenes: `add` cameras, lights,
+// spheres, planes and cubes, and `define` materials or transform lists, optionally extending
+// an earlier material. The world holds one light, so only the last one added is kept.
+// Panics if the text is not YAML or the scene has no camera or light.
+pub fn load_scene<T: BaseFloat + Default + Display>(yaml: &str) -> (Camera<T>, World<T>) {
+    let docs = YamlLoader::load_from_str(yaml).expect("the scene is not valid YAML");
+    let mut defines = HashMap::new();
+    let mut camera = None;
+    let mut light = None;
+    let mut objects = Vec::new();
+    for item in docs.first().and_then(Yaml::as_vec).into_iter().flatten() {
+        if let Some(name) = item["define"].as_str() {
+            // Extending a material starts from a copy of its keys.
+            let value = match (
+                defines.get(item["extend"].as_str().unwrap_or_default()),
+                &item["value"],
+            ) {
+                (Some(Yaml::Hash(base)), Yaml::Hash(h)) => {
+                    let mut merged: Hash = base.clone();
+                    merged.extend(h.iter().map(|(k, v)| (k.clone(), v.clone())));
+                    Yaml::Hash(merged)
+                }
+                (_, value) => value.clone(),
+            };
+            defines.insert(name.to_string(), value);
+            continue;
+        }
+        let shape_material = || material(&item["material"], &defines);
+        let shape_transform = || transform(&item["transform"], &defines);
+        match item["add"].as_str() {
+            Some("camera") => {
+                let size = |key: &str| item[key].as_i64().unwrap_or_default() as usize;
+                let mut c = Camera::from(
+                    size("width"),
+                    size("height"),
+                    number(&item["field-of-view"]).unwrap_or_default(),
+                );
+                c.transform = Matrix4::look_at_rh(
+                    point(&item["from"]).unwrap_or_else(Point3::origin),
+                    point(&item["to"]).unwrap_or_else(Point3::origin),
+                    point::<T>(&item["up"]).map_or(Vector3::unit_y(), |p| p - Point3::origin()),
+                );
+                camera = Some(c);
+            }
+            Some("light") => {
+                light = Some(Light::new(
+                    point(&item["at"]).unwrap_or_else(Point3::origin),
+                    color(&item["intensity"]).unwrap_or(RGB::new(T::one(), T::one(), T::one())),
+                ));
+            }
+            Some("sphere") => objects.push(Shape::Sphere(Sphere {
+                transform: shape_transform(),
+                material: shape_material(),
+                ..Sphere::default()
+            })),
+            Some("plane") => objects.push(Shape::Plane(Plane {
+                transform: shape_transform(),
+                material: shape_material(),
+                ..Plane::default()
+            })),
+            Some("cube") => objects.push(Shape::Cube(Cube {
+                transform: shape_transform(),
+                material: shape_material(),
+                ..Cube::default()
+            })),
+            _ => {}
+        }
+    }
+    (
+        camera.expect("the scene has no camera"),
+        World::new(light.expect("the scene has no light"), objects),
+    )
+}
+
+mod tests {
+    use super::*;
+    use crate::shape::get_rc;
+    use std::f64::consts::FRAC_PI_2;
+
+    #[test]
+    fn load_scene() {
+        let (camera, world) = super::load_scene::<f64>(
+            r#"
+- add: camera
+  width: 100
+  height: 50
+  field-of-view: 0.785
+  from: [0, 1.5, -5]
+  to: [0, 1, 0]
+  up: [0, 1, 0]
+
+- add: light
+  at: [-10, 10, -10]
+  intensity: [1, 1, 1]
+
+- define: white-material
+  value:
+    color: [1, 1, 1]
+    diffuse: 0.7
+    specular: 0
+
+- define: blue-material
+  extend: white-material
+  value:
+    color: [0.5, 0.5, 1]
+    reflective: 0.1
+
+- define: standard-transform
+  value:
+    - [translate, 1, -1, 1]
+    - [scale, 0.5, 0.5, 0.5]
+
+- add: plane
+  material:
+    color: [1, 0.9, 0.9]
+
+- add: sphere
+  material: blue-material
+  transform:
+    - standard-transform
+    - [rotate-y, 1.5707963267948966]
+
+- add: cube
+  material: white-material
+  transform:
+    - [scale, 2, 1, 1]
+    - [translate, 0, 1, 0]
+"#,
+        );
+        assert_eq!((camera.hsize, camera.vsize), (100, 50));
+        assert_eq!(camera.field_of_view, 0.785);
+        assert_eq!(
+            camera.transform,
+            Matrix4::look_at_rh(
+                Point3::new(0., 1.5, -5.),
+                Point3::new(0., 1., 0.),
+                Vector3::unit_y()
+            )
+        );
+        assert_eq!(
+            world.light,
+            Light::new(Point3::new(-10., 10., -10.), RGB::new(1., 1., 1.))
+        );
+        let white = Material {
+            pattern: Pattern::Solid(RGB::new(1., 1., 1.)),
+            diffuse: 0.7,
+            specular: 0.,
+            ..Material::default()
+        };
+        let blue = Material {
+            pattern: Pattern::Solid(RGB::new(0.5, 0.5, 1.)),
+            reflective: 0.1,
+            ..white.clone()
+        };
+        assert_eq!(
+            world.objects,
+            [
+                Shape::Plane(Plane {
+                    material: Material {
+                        pattern: Pattern::Solid(RGB::new(1., 0.9, 0.9)),
+                        ..Material::default()
+                    },
+                    ..Plane::default()
+                }),
+                Shape::Sphere(Sphere {
+                    transform: Matrix4::from_angle_y(Rad(FRAC_PI_2))
+                        * Matrix4::from_scale(0.5)
+                        * Matrix4::from_translation(Vector3::new(1., -1., 1.)),
+                    material: blue,
+                    ..Sphere::default()
+                }),
+                Shape::Cube(Cube {
+                    transform: Matrix4::from_translation(Vector3::unit_y())
+                        * Matrix4::from_nonuniform_scale(2., 1., 1.),
+                    material: white,
+                    ..Cube::default()
+                }),
+            ]
+            .map(get_rc)
+        );
+    }
+
+    #[test]
+    fn transform() {
+        let docs = YamlLoader::load_from_str(
+            r#"
+loop: [loop, [translate, 1, 0, 0]]
+steps: [[], loop, [scale, 2, 2, 2]]
+"#,
+        )
+        .unwrap();
+        let defines = docs[0]
+            .as_hash()
+            .unwrap()
+            .iter()
+            .map(|(k, v)| (k.as_str().unwrap().to_string(), v.clone()))
+            .collect::<HashMap<_, _>>();
+        // The empty step is skipped and `loop` expands once.
+        assert_eq!(
+            super::transform::<f64>(&defines["steps"], &defines),
+            Matrix4::from_scale(2.) * Matrix4::from_translation(Vector3::unit_x())
+        );
+    }
+}
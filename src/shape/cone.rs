@@ -1,14 +1,15 @@
 use crate::{
     bounds::Bounds,
-    intersection::Intersection,
+    intersection::{intersections, Intersection},
     material::Material,
     ray::Ray,
-    shape::{Shape, ShapeWeak},
+    shape::{get_rc, Shape, ShapeRc, ShapeWeak},
 };
 use cgmath::{abs_diff_eq, abs_diff_ne, BaseFloat, Matrix4, Point3, SquareMatrix, Vector3};
 use std::{cmp::Ordering::Less, fmt::Debug};
 
 #[derive(Clone, derive_more::Constructor, Debug, derivative::Derivative)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derivative(PartialEq)]
 pub struct Cone<T> {
     pub transform: Matrix4<T>,
@@ -16,6 +17,7 @@ pub struct Cone<T> {
     pub minimum: T,
     pub maximum: T,
     pub closed: bool,
+    #[cfg_attr(feature = "serde", serde(skip, default = "Option::default"))]
     #[derivative(PartialEq = "ignore")]
     pub parent: Option<ShapeWeak<T>>,
 }
@@ -40,58 +42,49 @@ fn check_cap<T: BaseFloat>(ray: Ray<T>, t: T, radius: T) -> bool {
 }
 
 impl<T: BaseFloat> Cone<T> {
-    fn intersect_caps(&self, ray: Ray<T>) -> Vec<Intersection<T>> {
-        let mut xs = Vec::new();
+    fn intersect_caps(&self, ray: Ray<T>) -> Vec<T> {
+        let mut ts = Vec::new();
         if self.closed && abs_diff_ne!(ray.direction.y, T::zero()) {
             for m in [self.minimum, self.maximum] {
                 let t = (m - ray.origin.y) / ray.direction.y;
                 if check_cap(ray, t, m) {
-                    xs.push(Intersection::new(t, Shape::Cone(self.clone()), None));
+                    ts.push(t);
                 }
             }
         }
-        xs
+        ts
     }
 }
 
 impl<T: BaseFloat + Debug> Cone<T> {
+    // An infinite cone is given huge but finite bounds, small enough that transforming them
+    // cannot overflow into infinities, which would turn into NaN in later transforms.
     pub fn bounds(&self) -> Bounds<T> {
-        let one = T::one();
-        let max = Point3::new(
-            one,
-            if self.closed {
-                self.maximum
-            } else {
-                T::max_value()
-            },
-            one,
-        );
-        let min = Point3::new(
-            -one,
-            if self.closed {
-                self.minimum
-            } else {
-                T::min_value()
-            },
-            -one,
-        );
-        Bounds::new(min, max)
+        let limit = T::max_value().sqrt();
+        let ymin = self.minimum.max(-limit);
+        let ymax = self.maximum.min(limit);
+        let r = ymin.abs().max(ymax.abs());
+        Bounds::new(Point3::new(-r, ymin, -r), Point3::new(r, ymax, r))
     }
 
     pub fn local_intersect(&self, ray: Ray<T>) -> Vec<Intersection<T>> {
+        self.local_intersect_with(ray, || get_rc(Shape::Cone(self.clone())))
+    }
+
+    pub fn local_intersect_with(
+        &self,
+        ray: Ray<T>,
+        object: impl FnOnce() -> ShapeRc<T>,
+    ) -> Vec<Intersection<T>> {
         let two = T::from(2).unwrap();
         let a = ray.direction.x.powi(2) - ray.direction.y.powi(2) + ray.direction.z.powi(2);
         let b = two
             * (ray.origin.x * ray.direction.x - ray.origin.y * ray.direction.y
                 + ray.origin.z * ray.direction.z);
         let c = ray.origin.x.powi(2) - ray.origin.y.powi(2) + ray.origin.z.powi(2);
-        let mut xs = Vec::new();
+        let mut ts = Vec::new();
         if abs_diff_eq!(a, T::zero()) && !abs_diff_eq!(b, T::zero()) {
-            xs.push(Intersection::new(
-                -c / (two * b),
-                Shape::Cone(self.clone()),
-                None,
-            ));
+            ts.push(-c / (two * b));
         } else {
             let disc = b.powi(2) - T::from(4).unwrap() * a * c;
             if disc < T::zero() {
@@ -101,16 +94,16 @@ impl<T: BaseFloat + Debug> Cone<T> {
             let t1 = (-b + disc.sqrt()) / (two * a);
             let y0 = ray.origin.y + t0 * ray.direction.y;
             if self.minimum < y0 && y0 < self.maximum {
-                xs.push(Intersection::new(t0, Shape::Cone(self.clone()), None));
+                ts.push(t0);
             }
             let y1 = ray.origin.y + t1 * ray.direction.y;
             if self.minimum < y1 && y1 < self.maximum {
-                xs.push(Intersection::new(t1, Shape::Cone(self.clone()), None));
+                ts.push(t1);
             }
         }
-        xs.append(&mut self.intersect_caps(ray));
-        xs.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap_or(Less));
-        xs
+        ts.append(&mut self.intersect_caps(ray));
+        ts.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Less));
+        intersections(ts, object)
     }
 
     pub fn local_normal_at(&self, point: Point3<T>) -> Vector3<T> {
@@ -127,6 +120,10 @@ impl<T: BaseFloat + Debug> Cone<T> {
 
 mod tests {
     use super::*;
+    use crate::shape::{
+        get_rc,
+        group::{push, push_link, Group},
+    };
     use cgmath::{assert_relative_eq, EuclideanSpace, InnerSpace, Rad, Zero};
     use std::f32::consts::{PI, SQRT_2};
 
@@ -225,4 +222,44 @@ mod tests {
             Vector3::new(-1., 1., 0.)
         );
     }
+
+    #[test]
+    fn bounds() {
+        let closed = Cone::<f32> {
+            minimum: -1.,
+            maximum: 2.,
+            closed: true,
+            ..Cone::default()
+        };
+        assert_eq!(
+            closed.bounds(),
+            Bounds::new(Point3::new(-2., -1., -2.), Point3::new(2., 2., 2.))
+        );
+        // A default cone is infinite, yet its bounds survive nested rotated groups.
+        let outer = get_rc(Shape::Group(Group::new(
+            Matrix4::from_angle_x(Rad(PI / 3.)),
+            Vec::new(),
+            false,
+            None,
+        )));
+        let inner = get_rc(Shape::Group(Group::new(
+            Matrix4::from_angle_z(Rad(PI / 4.)),
+            Vec::new(),
+            false,
+            None,
+        )));
+        push(&inner, Shape::Cone(Cone::default()));
+        push_link(&outer, inner);
+        let b = outer.borrow().bounds().unwrap();
+        for p in b.all_points() {
+            assert!(p.x.is_finite() && p.y.is_finite() && p.z.is_finite());
+        }
+        let ray = Ray::new(Point3::new(0.5, 0.2, -5.), Vector3::unit_z());
+        let direct = Shape::Cone(Cone::default())
+            .rotate_z(PI / 4.)
+            .rotate_x(PI / 3.)
+            .intersect(ray);
+        assert_eq!(direct.len(), 2);
+        assert_eq!(outer.borrow().intersect(ray).len(), 2);
+    }
 }
@@ -2,11 +2,13 @@ use crate::{
     bounds::Bounds,
     intersection::Intersection,
     ray::Ray,
-    shape::{ShapeRc, ShapeWeak},
+    shape::{intersect_shared, ShapeRc, ShapeWeak},
 };
 use cgmath::{BaseFloat, Matrix4};
+use std::cell::Cell;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Operation {
     Union,
     Intersect,
@@ -14,12 +16,14 @@ pub enum Operation {
 }
 
 #[derive(Clone, derive_more::Constructor, Debug, derivative::Derivative)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derivative(PartialEq)]
 pub struct ConstructiveSolidGeometry<T> {
     pub transform: Matrix4<T>,
     pub op: Operation,
     pub left: ShapeRc<T>,
     pub right: ShapeRc<T>,
+    #[cfg_attr(feature = "serde", serde(skip, default = "Option::default"))]
     #[derivative(PartialEq = "ignore")]
     pub parent: Option<ShapeWeak<T>>,
 }
@@ -42,7 +46,7 @@ impl<T: BaseFloat> ConstructiveSolidGeometry<T> {
         let mut inr = false;
         let mut result = Vec::new();
         for i in xs {
-            let lhit = self.left.borrow().include(&i.object);
+            let lhit = self.left.borrow().include(&i.object.borrow());
             if intersection_allowed(self.op, lhit, inl, inr) {
                 result.push(i.clone());
             }
@@ -56,8 +60,16 @@ impl<T: BaseFloat> ConstructiveSolidGeometry<T> {
     }
 
     pub fn local_intersect(&self, ray: Ray<T>) -> Vec<Intersection<T>> {
-        let mut v = self.left.borrow().intersect(ray);
-        v.append(&mut self.right.borrow().intersect(ray));
+        self.local_intersect_counting(ray, &Cell::new(0))
+    }
+
+    pub fn local_intersect_counting(
+        &self,
+        ray: Ray<T>,
+        counter: &Cell<u64>,
+    ) -> Vec<Intersection<T>> {
+        let mut v = intersect_shared(&self.left, ray, counter);
+        v.append(&mut intersect_shared(&self.right, ray, counter));
         v.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap_or(std::cmp::Ordering::Less));
         self.filter_intersections(&v)
     }
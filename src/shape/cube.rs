@@ -1,17 +1,19 @@
 use crate::{
     bounds::Bounds,
-    intersection::Intersection,
+    intersection::{intersections, Intersection},
     material::Material,
     ray::Ray,
-    shape::{Shape, ShapeWeak},
+    shape::{get_rc, Shape, ShapeRc, ShapeWeak},
 };
 use cgmath::{BaseFloat, Matrix4, Point3, SquareMatrix, Vector3};
 
 #[derive(Clone, derive_more::Constructor, Debug, derivative::Derivative)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derivative(PartialEq)]
 pub struct Cube<T> {
     pub transform: Matrix4<T>,
     pub material: Material<T>,
+    #[cfg_attr(feature = "serde", serde(skip, default = "Option::default"))]
     #[derivative(PartialEq = "ignore")]
     pub parent: Option<ShapeWeak<T>>,
 }
@@ -35,12 +37,17 @@ impl<T: BaseFloat> Cube<T> {
     }
 
     pub fn local_intersect(&self, ray: Ray<T>) -> Vec<Intersection<T>> {
+        self.local_intersect_with(ray, || get_rc(Shape::Cube(self.clone())))
+    }
+
+    pub fn local_intersect_with(
+        &self,
+        ray: Ray<T>,
+        object: impl FnOnce() -> ShapeRc<T>,
+    ) -> Vec<Intersection<T>> {
         let (tmin, tmax) = self.bounds().check_axes(ray).minmax();
         if tmin <= tmax {
-            vec![
-                Intersection::new(tmin, Shape::Cube(self.clone()), None),
-                Intersection::new(tmax, Shape::Cube(self.clone()), None),
-            ]
+            intersections([tmin, tmax], object)
         } else {
             vec![]
         }
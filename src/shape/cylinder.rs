@@ -1,13 +1,14 @@
 use crate::{
     bounds::Bounds,
-    intersection::Intersection,
+    intersection::{intersections, Intersection},
     material::Material,
     ray::Ray,
-    shape::{Shape, ShapeWeak},
+    shape::{get_rc, Shape, ShapeRc, ShapeWeak},
 };
 use cgmath::{abs_diff_eq, abs_diff_ne, BaseFloat, Matrix4, Point3, SquareMatrix, Vector3};
 
 #[derive(Clone, derive_more::Constructor, Debug, derivative::Derivative)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derivative(PartialEq)]
 pub struct Cylinder<T> {
     pub transform: Matrix4<T>,
@@ -15,6 +16,7 @@ pub struct Cylinder<T> {
     pub minimum: T,
     pub maximum: T,
     pub closed: bool,
+    #[cfg_attr(feature = "serde", serde(skip, default = "Option::default"))]
     #[derivative(PartialEq = "ignore")]
     pub parent: Option<ShapeWeak<T>>,
 }
@@ -39,17 +41,17 @@ fn check_cap<T: BaseFloat>(ray: Ray<T>, t: T) -> bool {
 }
 
 impl<T: BaseFloat> Cylinder<T> {
-    fn intersect_caps(&self, ray: Ray<T>) -> Vec<Intersection<T>> {
-        let mut xs = Vec::new();
+    fn intersect_caps(&self, ray: Ray<T>) -> Vec<T> {
+        let mut ts = Vec::new();
         if self.closed && abs_diff_ne!(ray.direction.y, T::zero()) {
             for m in [self.minimum, self.maximum] {
                 let t = (m - ray.origin.y) / ray.direction.y;
                 if check_cap(ray, t) {
-                    xs.push(Intersection::new(t, Shape::Cylinder(self.clone()), None));
+                    ts.push(t);
                 }
             }
         }
-        xs
+        ts
     }
 }
 
@@ -78,10 +80,18 @@ impl<T: BaseFloat> Cylinder<T> {
     }
 
     pub fn local_intersect(&self, ray: Ray<T>) -> Vec<Intersection<T>> {
+        self.local_intersect_with(ray, || get_rc(Shape::Cylinder(self.clone())))
+    }
+
+    pub fn local_intersect_with(
+        &self,
+        ray: Ray<T>,
+        object: impl FnOnce() -> ShapeRc<T>,
+    ) -> Vec<Intersection<T>> {
         let two = T::from(2).unwrap();
         let a = ray.direction.x.powi(2) + ray.direction.z.powi(2);
         if abs_diff_eq!(a, T::zero()) {
-            self.intersect_caps(ray)
+            intersections(self.intersect_caps(ray), object)
         } else {
             let b = two * (ray.origin.x * ray.direction.x + ray.origin.z * ray.direction.z);
             let c = ray.origin.x.powi(2) + ray.origin.z.powi(2) - T::one();
@@ -91,18 +101,18 @@ impl<T: BaseFloat> Cylinder<T> {
             } else {
                 let t0 = (-b - disc.sqrt()) / (two * a);
                 let t1 = (-b + disc.sqrt()) / (two * a);
-                let mut xs = Vec::new();
+                let mut ts = Vec::new();
                 let y0 = ray.origin.y + t0 * ray.direction.y;
                 if self.minimum < y0 && y0 < self.maximum {
-                    xs.push(Intersection::new(t0, Shape::Cylinder(self.clone()), None));
+                    ts.push(t0);
                 }
                 let y1 = ray.origin.y + t1 * ray.direction.y;
                 if self.minimum < y1 && y1 < self.maximum {
-                    xs.push(Intersection::new(t1, Shape::Cylinder(self.clone()), None));
+                    ts.push(t1);
                 }
-                xs.append(&mut self.intersect_caps(ray));
-                xs.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap_or(std::cmp::Ordering::Less));
-                xs
+                ts.append(&mut self.intersect_caps(ray));
+                ts.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Less));
+                intersections(ts, object)
             }
         }
     }
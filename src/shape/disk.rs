@@ -0,0 +1,102 @@
+use crate::{
+    bounds::Bounds,
+    intersection::Intersection,
+    material::Material,
+    ray::Ray,
+    shape::{get_rc, Shape, ShapeRc, ShapeWeak},
+};
+use cgmath::{abs_diff_eq, BaseFloat, Matrix4, Point3, SquareMatrix, Vector3};
+
+// A flat ring in the xz plane; an inner radius of zero gives a full disk.
+#[derive(Clone, derive_more::Constructor, Debug, derivative::Derivative)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derivative(PartialEq)]
+pub struct Disk<T> {
+    pub transform: Matrix4<T>,
+    pub material: Material<T>,
+    pub inner_radius: T,
+    pub outer_radius: T,
+    #[cfg_attr(feature = "serde", serde(skip, default = "Option::default"))]
+    #[derivative(PartialEq = "ignore")]
+    pub parent: Option<ShapeWeak<T>>,
+}
+
+impl<T: BaseFloat + Default> Default for Disk<T> {
+    fn default() -> Disk<T> {
+        Disk::<T> {
+            transform: Matrix4::identity(),
+            material: Material::default(),
+            inner_radius: T::zero(),
+            outer_radius: T::one(),
+            parent: None,
+        }
+    }
+}
+
+impl<T: BaseFloat> Disk<T> {
+    pub fn bounds(&self) -> Bounds<T> {
+        let r = self.outer_radius;
+        Bounds::new(Point3::new(-r, T::zero(), -r), Point3::new(r, T::zero(), r))
+    }
+
+    pub fn local_intersect(&self, ray: Ray<T>) -> Vec<Intersection<T>> {
+        self.local_intersect_with(ray, || get_rc(Shape::Disk(self.clone())))
+    }
+
+    pub fn local_intersect_with(
+        &self,
+        ray: Ray<T>,
+        object: impl FnOnce() -> ShapeRc<T>,
+    ) -> Vec<Intersection<T>> {
+        if abs_diff_eq!(ray.direction.y, T::zero()) {
+            return Vec::new();
+        }
+        let t = -ray.origin.y / ray.direction.y;
+        let point = ray.position(t);
+        let rho2 = point.x.powi(2) + point.z.powi(2);
+        if self.inner_radius.powi(2) <= rho2 && rho2 <= self.outer_radius.powi(2) {
+            vec![Intersection::new(t, object(), None)]
+        } else {
+            Vec::new()
+        }
+    }
+
+    pub fn local_normal_at(&self, _point: Point3<T>) -> Vector3<T> {
+        Vector3::unit_y()
+    }
+}
+
+mod tests {
+    use super::*;
+    use cgmath::assert_relative_eq;
+
+    #[test]
+    fn local_intersect() {
+        let disk = Disk::<f32> {
+            inner_radius: 0.5,
+            outer_radius: 2.,
+            ..Disk::default()
+        };
+        let down = -Vector3::unit_y();
+        let xs = disk.local_intersect(Ray::new(Point3::new(1., 3., 0.), down));
+        assert_eq!(xs.len(), 1);
+        assert_relative_eq!(xs[0].t, 3.);
+        // Outside the outer radius.
+        assert_eq!(
+            disk.local_intersect(Ray::new(Point3::new(1.5, 3., 1.5), down)),
+            vec![]
+        );
+        // Through the hole.
+        assert_eq!(
+            disk.local_intersect(Ray::new(Point3::new(0.2, 3., 0.), down)),
+            vec![]
+        );
+        // A full disk is hit at its center.
+        let xs = Disk::default().local_intersect(Ray::new(Point3::new(0., 3., 0.), down));
+        assert_eq!(xs.len(), 1);
+        assert_eq!(
+            disk.bounds(),
+            Bounds::new(Point3::new(-2., 0., -2.), Point3::new(2., 0., 2.))
+        );
+    }
+}
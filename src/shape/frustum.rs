@@ -0,0 +1,239 @@
+use crate::{
+    bounds::Bounds,
+    intersection::{intersections, Intersection},
+    material::Material,
+    ray::Ray,
+    shape::{get_rc, Shape, ShapeRc, ShapeWeak},
+};
+use cgmath::{abs_diff_eq, abs_diff_ne, BaseFloat, Matrix4, Point3, SquareMatrix, Vector3};
+use std::cmp::Ordering::Less;
+
+// A truncated cone around the y axis whose radius changes linearly from bottom_radius at
+// minimum to top_radius at maximum, for buckets and lampshades.
+#[derive(Clone, derive_more::Constructor, Debug, derivative::Derivative)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derivative(PartialEq)]
+pub struct Frustum<T> {
+    pub transform: Matrix4<T>,
+    pub material: Material<T>,
+    pub minimum: T,
+    pub maximum: T,
+    pub bottom_radius: T,
+    pub top_radius: T,
+    pub closed: bool,
+    #[cfg_attr(feature = "serde", serde(skip, default = "Option::default"))]
+    #[derivative(PartialEq = "ignore")]
+    pub parent: Option<ShapeWeak<T>>,
+}
+
+impl<T: BaseFloat + Default> Default for Frustum<T> {
+    fn default() -> Frustum<T> {
+        Frustum::<T> {
+            transform: Matrix4::identity(),
+            material: Material::default(),
+            minimum: T::zero(),
+            maximum: T::one(),
+            bottom_radius: T::one(),
+            top_radius: T::from(0.5).unwrap(),
+            closed: false,
+            parent: None,
+        }
+    }
+}
+
+impl<T: BaseFloat> Frustum<T> {
+    // The change in radius per unit of height.
+    fn slope(&self) -> T {
+        (self.top_radius - self.bottom_radius) / (self.maximum - self.minimum)
+    }
+
+    fn radius_at(&self, y: T) -> T {
+        self.bottom_radius + self.slope() * (y - self.minimum)
+    }
+
+    fn intersect_caps(&self, ray: Ray<T>) -> Vec<T> {
+        let mut ts = Vec::new();
+        if self.closed && abs_diff_ne!(ray.direction.y, T::zero()) {
+            for (m, r) in [
+                (self.minimum, self.bottom_radius),
+                (self.maximum, self.top_radius),
+            ] {
+                let t = (m - ray.origin.y) / ray.direction.y;
+                let point = ray.position(t);
+                if point.x.powi(2) + point.z.powi(2) <= r.powi(2) {
+                    ts.push(t);
+                }
+            }
+        }
+        ts
+    }
+
+    pub fn bounds(&self) -> Bounds<T> {
+        let r = self.bottom_radius.max(self.top_radius);
+        Bounds::new(
+            Point3::new(-r, self.minimum, -r),
+            Point3::new(r, self.maximum, r),
+        )
+    }
+
+    pub fn local_intersect(&self, ray: Ray<T>) -> Vec<Intersection<T>> {
+        self.local_intersect_with(ray, || get_rc(Shape::Frustum(self.clone())))
+    }
+
+    pub fn local_intersect_with(
+        &self,
+        ray: Ray<T>,
+        object: impl FnOnce() -> ShapeRc<T>,
+    ) -> Vec<Intersection<T>> {
+        let two = T::from(2).unwrap();
+        let k = self.slope();
+        // The side is x^2 + z^2 = (r0 + k y)^2, with r0 the radius the side would have at y = 0.
+        let r0 = self.bottom_radius - k * self.minimum;
+        let radius = r0 + k * ray.origin.y;
+        let a = ray.direction.x.powi(2) + ray.direction.z.powi(2) - (k * ray.direction.y).powi(2);
+        let b = two
+            * (ray.origin.x * ray.direction.x + ray.origin.z * ray.direction.z
+                - k * ray.direction.y * radius);
+        let c = ray.origin.x.powi(2) + ray.origin.z.powi(2) - radius.powi(2);
+        let ts = if abs_diff_eq!(a, T::zero()) {
+            if abs_diff_eq!(b, T::zero()) {
+                vec![]
+            } else {
+                vec![-c / b]
+            }
+        } else {
+            let disc = b.powi(2) - T::from(4).unwrap() * a * c;
+            if disc < T::zero() {
+                vec![]
+            } else {
+                vec![
+                    (-b - disc.sqrt()) / (two * a),
+                    (-b + disc.sqrt()) / (two * a),
+                ]
+            }
+        };
+        let mut ts = ts
+            .into_iter()
+            .filter(|t| {
+                let y = ray.origin.y + *t * ray.direction.y;
+                self.minimum < y && y < self.maximum
+            })
+            .collect::<Vec<_>>();
+        ts.append(&mut self.intersect_caps(ray));
+        ts.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Less));
+        intersections(ts, object)
+    }
+
+    pub fn local_normal_at(&self, point: Point3<T>) -> Vector3<T> {
+        let dist = point.x.powi(2) + point.z.powi(2);
+        if dist < self.top_radius.powi(2) && abs_diff_eq!(point.y, self.maximum) {
+            Vector3::unit_y()
+        } else if dist < self.bottom_radius.powi(2) && abs_diff_eq!(point.y, self.minimum) {
+            -Vector3::unit_y()
+        } else {
+            // The side leans outwards as the radius shrinks with height.
+            Vector3::new(point.x, -self.slope() * self.radius_at(point.y), point.z)
+        }
+    }
+}
+
+mod tests {
+    use super::*;
+    use crate::shape::cylinder::Cylinder;
+    use cgmath::{assert_relative_eq, EuclideanSpace, InnerSpace};
+
+    #[test]
+    fn local_intersect() {
+        let frustum = Frustum::<f64>::default();
+        // Straight down the axis through both caps only when closed.
+        let down = Ray::new(Point3::new(0., 2., 0.), -Vector3::unit_y());
+        assert_eq!(frustum.local_intersect(down), vec![]);
+        let closed = Frustum {
+            closed: true,
+            ..Frustum::default()
+        };
+        let ts = closed
+            .local_intersect(down)
+            .iter()
+            .map(|i| i.t)
+            .collect::<Vec<_>>();
+        assert_eq!(ts, vec![1., 2.]);
+        // Across the middle, where the radius is 0.75.
+        let xs = frustum.local_intersect(Ray::new(Point3::new(-5., 0.5, 0.), Vector3::unit_x()));
+        assert_eq!(xs.len(), 2);
+        assert_relative_eq!(xs[0].t, 4.25);
+        assert_relative_eq!(xs[1].t, 5.75);
+        // Above the top.
+        let ray = Ray::new(Point3::new(-5., 1.5, 0.), Vector3::unit_x());
+        assert_eq!(frustum.local_intersect(ray), vec![]);
+    }
+
+    #[test]
+    fn cylinder() {
+        let frustum = Frustum {
+            minimum: -1.,
+            maximum: 2.,
+            bottom_radius: 1.,
+            top_radius: 1.,
+            closed: true,
+            ..Frustum::default()
+        };
+        let cylinder = Cylinder {
+            minimum: -1.,
+            maximum: 2.,
+            closed: true,
+            ..Cylinder::default()
+        };
+        let rays = [
+            Ray::new(Point3::new(0., 0., -5.), Vector3::unit_z()),
+            Ray::new(
+                Point3::new(0.5, 0., -5.),
+                Vector3::new(0.1, 1., 1.).normalize(),
+            ),
+            Ray::new(Point3::new(0., 3., 0.), -Vector3::unit_y()),
+            Ray::new(
+                Point3::new(0.2, 4., -2.),
+                Vector3::new(0., -1., 1.).normalize(),
+            ),
+            Ray::new(Point3::new(2., 0., -5.), Vector3::unit_z()),
+        ];
+        for ray in rays {
+            // Cylinder leaves the caps unsorted for rays along the axis.
+            let mut expected = cylinder.local_intersect(ray);
+            expected.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
+            let xs = frustum.local_intersect(ray);
+            assert_eq!(xs.len(), expected.len());
+            for (x, e) in xs.iter().zip(&expected) {
+                assert_relative_eq!(x.t, e.t);
+            }
+            for x in xs {
+                let point = ray.position(x.t);
+                assert_relative_eq!(
+                    frustum.local_normal_at(point),
+                    cylinder.local_normal_at(point)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn local_normal_at() {
+        let frustum = Frustum::<f64>::default();
+        // The side leans back by the slope.
+        assert_relative_eq!(
+            frustum
+                .local_normal_at(Point3::new(0.75, 0.5, 0.))
+                .normalize(),
+            Vector3::new(1., 0.5, 0.).normalize()
+        );
+        let closed = Frustum {
+            closed: true,
+            ..Frustum::default()
+        };
+        assert_eq!(
+            closed.local_normal_at(Point3::new(0.2, 1., 0.)),
+            Vector3::unit_y()
+        );
+        assert_eq!(closed.local_normal_at(Point3::origin()), -Vector3::unit_y());
+    }
+}
@@ -3,16 +3,20 @@ use crate::{
     intersection::Intersection,
     material::Material,
     ray::Ray,
-    shape::{get_rc_with_parent, Shape, ShapeRc, ShapeWeak},
+    shape::{get_rc_with_parent, intersect_shared, triangle::Triangle, Shape, ShapeRc, ShapeWeak},
 };
-use cgmath::{BaseFloat, Matrix4, SquareMatrix};
-use std::{cmp::Ordering::Less, fmt::Debug, rc::Rc};
+use cgmath::{BaseFloat, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3, Zero};
+use std::{cell::Cell, cmp::Ordering::Less, collections::HashMap, fmt::Debug, rc::Rc};
 
 #[derive(Clone, derive_more::Constructor, Debug, derivative::Derivative)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derivative(PartialEq)]
 pub struct Group<T> {
     pub transform: Matrix4<T>,
     pub children: Vec<ShapeRc<T>>,
+    // Test every child even when the bounds are missed, to rule out bounds bugs.
+    pub disable_bvh: bool,
+    #[cfg_attr(feature = "serde", serde(skip, default = "Option::default"))]
     #[derivative(PartialEq = "ignore")]
     pub parent: Option<ShapeWeak<T>>,
 }
@@ -37,16 +41,60 @@ pub fn push_link<T>(parent: &ShapeRc<T>, child: ShapeRc<T>) {
         .push(child);
 }
 
+// The normals of a group's flat triangles summed around each vertex, for smooth shading.
+// Triangles share a vertex if it has exactly the same coordinates and transform.
+#[derive(Clone, Debug, Default)]
+pub struct VertexNormals<T>(HashMap<Vec<u64>, Vector3<T>>);
+
+impl<T: BaseFloat> VertexNormals<T> {
+    pub fn of(group: &Group<T>) -> VertexNormals<T> {
+        let mut sums = HashMap::new();
+        for child in &group.children {
+            if let Shape::Triangle(t) = &*child.borrow() {
+                for p in [t.p1, t.p2, t.p3] {
+                    *sums.entry(Self::key(t, p)).or_insert_with(Vector3::zero) += t.normal;
+                }
+            }
+        }
+        VertexNormals(sums)
+    }
+
+    fn key(t: &Triangle<T>, p: Point3<T>) -> Vec<u64> {
+        let transform: &[T; 16] = t.transform.as_ref();
+        let point: &[T; 3] = p.as_ref();
+        transform
+            .iter()
+            .chain(point)
+            .map(|&c| (c + T::zero()).to_f64().unwrap().to_bits()) // -0.0 + 0.0 == 0.0
+            .collect()
+    }
+
+    // The averaged normals at p1, p2 and p3 of a triangle of the group.
+    pub fn at(&self, t: &Triangle<T>) -> Option<[Vector3<T>; 3]> {
+        let normal = |p| self.0.get(&Self::key(t, p)).map(|n| n.normalize());
+        Some([normal(t.p1)?, normal(t.p2)?, normal(t.p3)?])
+    }
+}
+
 impl<T: BaseFloat + Default> Default for Group<T> {
     fn default() -> Group<T> {
         Group::<T> {
             transform: Matrix4::identity(),
             children: Vec::new(),
+            disable_bvh: false,
             parent: None,
         }
     }
 }
 
+impl<T> Group<T> {
+    // Children resolve their world transforms through the parent links on demand,
+    // so nothing derived from the old transform needs to be invalidated yet.
+    pub fn set_transform(&mut self, transform: Matrix4<T>) {
+        self.transform = transform;
+    }
+}
+
 impl<T: BaseFloat + Debug> Group<T> {
     pub fn bounds(&self) -> Option<Bounds<T>> {
         Bounds::from_all_points(
@@ -63,12 +111,20 @@ impl<T: BaseFloat + Debug> Group<T> {
     }
 
     pub fn local_intersect(&self, ray: Ray<T>) -> Vec<Intersection<T>> {
-        if self.bounds().map_or(true, |b| b.is_intersected_with(ray)) {
-            let mut xs = self
-                .children
-                .iter()
-                .flat_map(|rc| rc.borrow().intersect(ray))
-                .collect::<Vec<_>>();
+        self.local_intersect_counting(ray, &Cell::new(0))
+    }
+
+    pub fn local_intersect_counting(
+        &self,
+        ray: Ray<T>,
+        counter: &Cell<u64>,
+    ) -> Vec<Intersection<T>> {
+        if self.disable_bvh || self.bounds().is_none_or(|b| b.is_intersected_with(ray)) {
+            // Most shapes are hit at most twice.
+            let mut xs = Vec::with_capacity(2 * self.children.len());
+            for child in &self.children {
+                xs.append(&mut intersect_shared(child, ray, counter));
+            }
             xs.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap_or(Less));
             xs
         } else {
@@ -79,8 +135,8 @@ impl<T: BaseFloat + Debug> Group<T> {
 
 mod tests {
     use super::*;
-    use crate::shape::{get_rc, Cylinder, Sphere};
-    use cgmath::{assert_relative_eq, EuclideanSpace, Matrix4, Point3, Rad, Vector3};
+    use crate::shape::{get_rc, Sphere};
+    use cgmath::{assert_relative_eq, EuclideanSpace, Rad};
     use std::{
         cell::RefCell,
         f32::consts::{FRAC_PI_2, FRAC_PI_3, FRAC_PI_6, SQRT_2},
@@ -90,7 +146,12 @@ mod tests {
 
     #[test]
     fn bounds() {
-        let shape = Shape::Group(Group::<f32>::new(Matrix4::from_scale(2.), Vec::new(), None));
+        let shape = Shape::Group(Group::<f32>::new(
+            Matrix4::from_scale(2.),
+            Vec::new(),
+            false,
+            None,
+        ));
         let rc = get_rc(shape);
         push(
             &rc,
@@ -134,13 +195,19 @@ mod tests {
             assert_eq!(group.children.len(), 3);
             let xs = group.local_intersect(Ray::new(Point3::new(0., 0., -5.), Vector3::unit_z()));
             assert_eq!(xs.len(), 4);
-            assert_eq!(xs[0].object, *group.children[1].borrow().deref());
-            assert_eq!(xs[1].object, *group.children[1].borrow().deref());
-            assert_eq!(xs[2].object, *group.children[0].borrow().deref());
-            assert_eq!(xs[3].object, *group.children[0].borrow().deref());
+            // Hits share the Rc each child is kept in rather than copying it.
+            assert!(Rc::ptr_eq(&xs[0].object, &group.children[1]));
+            assert!(Rc::ptr_eq(&xs[1].object, &group.children[1]));
+            assert!(Rc::ptr_eq(&xs[2].object, &group.children[0]));
+            assert!(Rc::ptr_eq(&xs[3].object, &group.children[0]));
         }
         {
-            let shape = Shape::Group(Group::<f32>::new(Matrix4::from_scale(2.), Vec::new(), None));
+            let shape = Shape::Group(Group::<f32>::new(
+                Matrix4::from_scale(2.),
+                Vec::new(),
+                false,
+                None,
+            ));
             let rc = get_rc(shape);
             push(
                 &rc,
@@ -164,9 +231,10 @@ mod tests {
         let g1 = Shape::Group(Group::new(
             Matrix4::from_angle_y(Rad(FRAC_PI_2)),
             Vec::new(),
+            false,
             None,
         ));
-        let g2 = Shape::Group(Group::new(Matrix4::from_scale(2.), Vec::new(), None));
+        let g2 = Shape::Group(Group::new(Matrix4::from_scale(2.), Vec::new(), false, None));
         let r1 = get_rc(g1);
         push(&r1, g2);
         let shape = Shape::Sphere(Sphere::new(
@@ -192,11 +260,13 @@ mod tests {
         let g1 = Shape::Group(Group::new(
             Matrix4::from_angle_y(Rad(FRAC_PI_2)),
             Vec::new(),
+            false,
             None,
         ));
         let g2 = Shape::Group(Group::new(
             Matrix4::from_nonuniform_scale(1., 2., 3.),
             Vec::new(),
+            false,
             None,
         ));
         let r1 = get_rc(g1);
@@ -224,11 +294,13 @@ mod tests {
         let g1 = Shape::Group(Group::new(
             Matrix4::from_angle_y(Rad(FRAC_PI_2)),
             Vec::new(),
+            false,
             None,
         ));
         let g2 = Shape::Group(Group::new(
             Matrix4::from_nonuniform_scale(1., 2., 3.),
             Vec::new(),
+            false,
             None,
         ));
         let r1 = get_rc(g1);
@@ -249,4 +321,155 @@ mod tests {
             max_relative = 0.001
         );
     }
+
+    #[test]
+    fn set_transform() {
+        let rc = get_rc(Shape::Group(Group::<f32>::default()));
+        push(
+            &rc,
+            Shape::Sphere(Sphere::new(
+                Matrix4::from_translation(Vector3::unit_x() * 5.),
+                Material::default(),
+                None,
+            )),
+        );
+        let ray = Ray::new(Point3::new(10., 0., -10.), Vector3::unit_z());
+        assert_eq!(rc.borrow().intersect(ray).len(), 0);
+        rc.borrow_mut()
+            .as_group_mut()
+            .unwrap()
+            .set_transform(Matrix4::from_scale(2.));
+        assert_eq!(
+            rc.borrow()
+                .intersect(ray)
+                .iter()
+                .map(|i| i.t)
+                .collect::<Vec<_>>(),
+            vec![8., 12.]
+        );
+        let child = rc.borrow().as_group().unwrap().children[0].clone();
+        assert_relative_eq!(
+            child
+                .borrow()
+                .world_to_object(Point3::new(10., 0., 0.))
+                .unwrap(),
+            Point3::origin()
+        );
+    }
+
+    #[test]
+    fn disable_bvh() {
+        let mut triangle = Triangle::from(
+            Point3::new(0., 1., 0.),
+            Point3::new(-1., 0., 0.),
+            Point3::new(1., 0., 0.),
+        );
+        // Break the bounds without changing what the triangle intersects.
+        triangle.p2 = triangle.p1;
+        triangle.p3 = triangle.p1;
+        let rc = get_rc(Shape::Group(Group::<f32>::default()));
+        push(&rc, Shape::Triangle(triangle));
+        let ray = Ray::new(Point3::new(0., 0.5, -2.), Vector3::unit_z());
+        assert_eq!(rc.borrow().intersect(ray).len(), 0);
+        rc.borrow_mut().as_group_mut().unwrap().disable_bvh = true;
+        assert_eq!(rc.borrow().intersect(ray).len(), 1);
+    }
+
+    #[test]
+    fn local_intersect_capacity() {
+        let rc = get_rc(Shape::Group(Group::<f32>::default()));
+        for z in [-3., 0., 3.] {
+            push(
+                &rc,
+                Shape::Sphere(Sphere::new(
+                    Matrix4::from_translation(Vector3::unit_z() * z),
+                    Material::default(),
+                    None,
+                )),
+            );
+        }
+        let group = rc.borrow().as_group().unwrap().clone();
+        let xs = group.local_intersect(Ray::new(Point3::new(0., 0., -10.), Vector3::unit_z()));
+        assert_eq!(
+            xs.iter().map(|i| i.t).collect::<Vec<_>>(),
+            vec![6., 8., 9., 11., 12., 14.]
+        );
+        assert!(xs.capacity() >= 6);
+        // Room for every child is reserved up front, even when only one is hit.
+        let xs = group.local_intersect(Ray::new(Point3::new(-10., 0., 3.), Vector3::unit_x()));
+        assert_eq!(xs.len(), 2);
+        assert!(xs.capacity() >= 2 * group.children.len());
+    }
+
+    #[test]
+    fn intersect_counting() {
+        // Two clusters of four spheres, each in its own group.
+        let build = |disable_bvh: bool| {
+            let rc = get_rc(Shape::Group(Group::<f32>::default()));
+            for x in [-5., 5.] {
+                let cluster = get_rc_with_parent(Shape::Group(Group::default()), &rc);
+                for z in [-3., -1., 1., 3.] {
+                    push(
+                        &cluster,
+                        Shape::Sphere(Sphere::new(
+                            Matrix4::from_translation(Vector3::new(x, 0., z)),
+                            Material::default(),
+                            None,
+                        )),
+                    );
+                }
+                cluster.borrow_mut().as_group_mut().unwrap().disable_bvh = disable_bvh;
+                rc.borrow_mut()
+                    .as_group_mut()
+                    .unwrap()
+                    .children
+                    .push(cluster);
+            }
+            rc.borrow_mut().as_group_mut().unwrap().disable_bvh = disable_bvh;
+            rc
+        };
+        let count = |rc: &ShapeRc<f32>, ray| {
+            let counter = Cell::new(0);
+            let xs = rc.borrow().intersect_counting(ray, &counter);
+            (xs.len(), counter.get())
+        };
+        let (with, without) = (build(false), build(true));
+        // Beside the first cluster, only its spheres are tested.
+        let ray = Ray::new(Point3::new(-5., 0., -10.), Vector3::unit_z());
+        assert_eq!(count(&with, ray), (8, 4));
+        assert_eq!(count(&without, ray), (8, 8));
+        // Off to the side nothing is tested at all.
+        let ray = Ray::new(Point3::new(0., 10., -10.), Vector3::unit_z());
+        assert_eq!(count(&with, ray), (0, 0));
+        assert_eq!(count(&without, ray), (0, 8));
+    }
+
+    #[test]
+    fn vertex_normals() {
+        // Two faces of a roof meeting along the ridge from (0, 1, 0) to (0, 1, 1), and a copy of
+        // the right face moved away, which shares nothing with the others.
+        let (ridge, back) = (Point3::new(0., 1., 0.), Point3::new(0., 1., 1.));
+        let left = Triangle::from(ridge, back, Point3::new(-1., 0., 0.));
+        let right = Triangle::from(ridge, Point3::new(1., 0., 0.), back);
+        let moved = Triangle {
+            transform: Matrix4::from_translation(Vector3::unit_x()),
+            ..right.clone()
+        };
+        let group = get_rc(Shape::Group(Group::default()));
+        for t in [left, right, moved] {
+            push(&group, Shape::Triangle(t));
+        }
+        push(&group, Shape::Sphere(Sphere::default()));
+        let sums = VertexNormals::of(group.borrow().as_group().unwrap());
+        let normals = |i: usize| {
+            let child = group.borrow().as_group().unwrap().children[i].clone();
+            let normals = sums.at(child.borrow().as_triangle().unwrap());
+            normals.unwrap()
+        };
+        let vy = Vector3::unit_y();
+        let right = Vector3::new(1., 1., 0.).normalize();
+        assert_relative_eq!(normals(0)[0], vy, epsilon = 0.00001);
+        assert_relative_eq!(normals(1)[..], [vy, right, vy][..], epsilon = 0.00001);
+        assert_relative_eq!(normals(2)[..], [right; 3][..], epsilon = 0.00001);
+    }
 }
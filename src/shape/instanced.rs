@@ -0,0 +1,173 @@
+use crate::{
+    bounds::Bounds,
+    intersection::Intersection,
+    ray::Ray,
+    shape::{get_rc_with_parent, intersect_shared, Shape, ShapeRc, ShapeWeak},
+};
+use cgmath::{BaseFloat, Matrix4, Point3, Vector3};
+use std::{cell::Cell, cmp::Ordering::Less};
+
+// Copies of one finite child repeated on a lattice, the first at the origin.
+#[derive(Clone, derive_more::Constructor, Debug, derivative::Derivative)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derivative(PartialEq)]
+pub struct Instanced<T> {
+    pub transform: Matrix4<T>,
+    pub child: ShapeRc<T>,
+    // The distance between neighbouring copies along each axis.
+    pub spacing: Vector3<T>,
+    pub counts: [usize; 3],
+    #[cfg_attr(feature = "serde", serde(skip, default = "Option::default"))]
+    #[derivative(PartialEq = "ignore")]
+    pub parent: Option<ShapeWeak<T>>,
+}
+
+pub fn set_child<T>(parent: &ShapeRc<T>, shape: Shape<T>) {
+    let child = get_rc_with_parent(shape, parent);
+    parent.borrow_mut().as_instanced_mut().unwrap().child = child;
+}
+
+impl<T: BaseFloat> Instanced<T> {
+    fn offsets(&self) -> impl Iterator<Item = Vector3<T>> + '_ {
+        let [nx, ny, nz] = self.counts;
+        (0..nx).flat_map(move |i| {
+            (0..ny).flat_map(move |j| {
+                (0..nz).map(move |k| {
+                    let cell = Vector3::new(
+                        T::from(i).unwrap(),
+                        T::from(j).unwrap(),
+                        T::from(k).unwrap(),
+                    );
+                    Vector3::new(
+                        cell.x * self.spacing.x,
+                        cell.y * self.spacing.y,
+                        cell.z * self.spacing.z,
+                    )
+                })
+            })
+        })
+    }
+
+    // The bounds of the child in this shape's space, before it is repeated.
+    fn child_bounds(&self) -> Option<Bounds<T>> {
+        let child = self.child.borrow();
+        child
+            .bounds()
+            .and_then(|b| Bounds::from_all_points(&b.transform(child.transform())))
+    }
+
+    pub fn bounds(&self) -> Option<Bounds<T>> {
+        self.child_bounds().and_then(|b| {
+            Bounds::from_all_points(
+                &self
+                    .offsets()
+                    .flat_map(|offset| [b.minimum + offset, b.maximum + offset])
+                    .collect::<Vec<Point3<T>>>(),
+            )
+        })
+    }
+
+    // The child is shared by every copy: each cell tests it with the ray moved back by the
+    // cell's offset, and the hits keep that offset to find points on the copy that was struck.
+    pub fn local_intersect(&self, ray: Ray<T>) -> Vec<Intersection<T>> {
+        self.local_intersect_counting(ray, &Cell::new(0))
+    }
+
+    pub fn local_intersect_counting(
+        &self,
+        ray: Ray<T>,
+        counter: &Cell<u64>,
+    ) -> Vec<Intersection<T>> {
+        let bounds = self.child_bounds();
+        let mut xs = Vec::new();
+        for offset in self.offsets() {
+            let cell_ray = Ray::new(ray.origin - offset, ray.direction);
+            if bounds.is_none_or(|b| b.is_intersected_with(cell_ray)) {
+                for mut x in intersect_shared(&self.child, cell_ray, counter) {
+                    x.offset += offset;
+                    xs.push(x);
+                }
+            }
+        }
+        xs.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap_or(Less));
+        xs
+    }
+}
+
+mod tests {
+    use super::*;
+    use crate::shape::{
+        get_rc,
+        group::{push, Group},
+        sphere::Sphere,
+    };
+    use cgmath::{assert_relative_eq, SquareMatrix};
+    use std::rc::Rc;
+
+    #[test]
+    fn local_intersect() {
+        let rc = get_rc(Shape::Instanced(Instanced::new(
+            Matrix4::identity(),
+            get_rc(Shape::Sphere(Sphere::default())),
+            Vector3::new(3., 0., 0.),
+            [3, 1, 1],
+            None,
+        )));
+        set_child(&rc, Shape::Sphere(Sphere::default()));
+        let shape = rc.borrow();
+        let instanced = shape.as_instanced().unwrap();
+        for x in [0., 3., 6.] {
+            let xs =
+                instanced.local_intersect(Ray::new(Point3::new(x, 5., 0.), -Vector3::unit_y()));
+            assert_eq!(xs.len(), 2);
+            assert_relative_eq!(xs[0].t, 4.);
+            assert_relative_eq!(xs[1].t, 6.);
+            // Every copy shares the child, so points are moved back onto it by the offset.
+            assert_eq!(xs[0].offset, Vector3::new(x, 0., 0.));
+            let point = Point3::new(x, 1., 0.) - xs[0].offset;
+            assert_relative_eq!(
+                xs[0].object.borrow().normal_at(point, None).unwrap(),
+                Vector3::unit_y()
+            );
+        }
+        // Between the copies and past the last one.
+        for x in [1.5, 9.] {
+            let ray = Ray::new(Point3::new(x, 5., 0.), -Vector3::unit_y());
+            assert_eq!(instanced.local_intersect(ray), vec![]);
+        }
+        let xs = shape.intersect(Ray::new(Point3::new(-5., 0., 0.), Vector3::unit_x()));
+        assert_eq!(xs.len(), 6);
+        assert_relative_eq!(xs[5].t, 12.);
+        assert_eq!(
+            instanced.bounds(),
+            Some(Bounds::new(
+                Point3::new(-1., -1., -1.),
+                Point3::new(7., 1., 1.)
+            ))
+        );
+    }
+
+    #[test]
+    fn group_child() {
+        let rc = get_rc(Shape::Instanced(Instanced::new(
+            Matrix4::from_translation(Vector3::unit_z()),
+            get_rc(Shape::Group(Group::default())),
+            Vector3::new(3., 0., 0.),
+            [2, 1, 1],
+            None,
+        )));
+        set_child(&rc, Shape::Group(Group::default()));
+        let group = rc.borrow().as_instanced().unwrap().child.clone();
+        push(&group, Shape::Sphere(Sphere::default()));
+        let ray = Ray::new(Point3::new(3., 5., 1.), -Vector3::unit_y());
+        let xs = rc.borrow().intersect(ray);
+        assert_eq!(xs.len(), 2);
+        // The hit is on the sphere inside the shared group, not on a copy of it.
+        let sphere = group.borrow().as_group().unwrap().children[0].clone();
+        assert!(Rc::ptr_eq(&xs[0].object, &sphere));
+        let comps = xs[0].precompute(ray, &xs).unwrap();
+        assert_relative_eq!(comps.point, Point3::new(3., 1., 1.));
+        assert_relative_eq!(comps.normalv, Vector3::unit_y());
+        assert_eq!(comps.offset, Vector3::new(3., 0., 0.));
+    }
+}
@@ -0,0 +1,211 @@
+use crate::{
+    bounds::Bounds,
+    intersection::{intersections, Intersection},
+    material::Material,
+    ray::Ray,
+    shape::{get_rc, Shape, ShapeRc, ShapeWeak},
+};
+use cgmath::{abs_diff_eq, BaseFloat, Matrix4, Point2, Point3, SquareMatrix, Vector3};
+use std::cmp::Ordering::Less;
+
+// A solid of revolution: the profile polyline of (radius, y) points is revolved around the y axis.
+// The profile should run from bottom to top so that the normals point outward.
+#[derive(Clone, derive_more::Constructor, Debug, derivative::Derivative)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derivative(PartialEq)]
+pub struct Lathe<T> {
+    pub transform: Matrix4<T>,
+    pub material: Material<T>,
+    pub profile: Vec<Point2<T>>,
+    #[cfg_attr(feature = "serde", serde(skip, default = "Option::default"))]
+    #[derivative(PartialEq = "ignore")]
+    pub parent: Option<ShapeWeak<T>>,
+}
+
+impl<T: BaseFloat> Lathe<T> {
+    pub fn from(profile: Vec<Point2<T>>) -> Lathe<T> {
+        Lathe {
+            transform: Matrix4::identity(),
+            material: Material::default(),
+            profile,
+            parent: None,
+        }
+    }
+
+    pub fn bounds(&self) -> Bounds<T> {
+        let radius = self
+            .profile
+            .iter()
+            .map(|p| p.x.abs())
+            .fold(T::zero(), T::max);
+        let ymin = self
+            .profile
+            .iter()
+            .map(|p| p.y)
+            .fold(T::max_value(), T::min);
+        let ymax = self
+            .profile
+            .iter()
+            .map(|p| p.y)
+            .fold(T::min_value(), T::max);
+        Bounds::new(
+            Point3::new(-radius, ymin, -radius),
+            Point3::new(radius, ymax, radius),
+        )
+    }
+
+    // Intersect the surface swept by the segment from a to b.
+    fn intersect_segment(&self, ray: Ray<T>, a: Point2<T>, b: Point2<T>) -> Vec<T> {
+        let (ymin, ymax) = (a.y.min(b.y), a.y.max(b.y));
+        if abs_diff_eq!(a.y, b.y) {
+            // A horizontal segment sweeps an annulus.
+            if abs_diff_eq!(ray.direction.y, T::zero()) {
+                return vec![];
+            }
+            let t = (a.y - ray.origin.y) / ray.direction.y;
+            let x = ray.origin.x + t * ray.direction.x;
+            let z = ray.origin.z + t * ray.direction.z;
+            let rho2 = x.powi(2) + z.powi(2);
+            return if a.x.min(b.x).powi(2) <= rho2 && rho2 <= a.x.max(b.x).powi(2) {
+                vec![t]
+            } else {
+                vec![]
+            };
+        }
+        // The radius along the ray is r0 + s * t.
+        let two = T::from(2).unwrap();
+        let k = (b.x - a.x) / (b.y - a.y);
+        let r0 = a.x + k * (ray.origin.y - a.y);
+        let s = k * ray.direction.y;
+        let qa = ray.direction.x.powi(2) + ray.direction.z.powi(2) - s.powi(2);
+        let qb = two * (ray.origin.x * ray.direction.x + ray.origin.z * ray.direction.z - r0 * s);
+        let qc = ray.origin.x.powi(2) + ray.origin.z.powi(2) - r0.powi(2);
+        let ts = if abs_diff_eq!(qa, T::zero()) {
+            if abs_diff_eq!(qb, T::zero()) {
+                vec![]
+            } else {
+                vec![-qc / qb]
+            }
+        } else {
+            let disc = qb.powi(2) - T::from(4).unwrap() * qa * qc;
+            if disc < T::zero() {
+                vec![]
+            } else {
+                vec![
+                    (-qb - disc.sqrt()) / (two * qa),
+                    (-qb + disc.sqrt()) / (two * qa),
+                ]
+            }
+        };
+        ts.into_iter()
+            .filter(|&t| {
+                let y = ray.origin.y + t * ray.direction.y;
+                // Half-open so that a joint between two segments is hit only once.
+                ymin <= y && y < ymax && r0 + s * t >= T::zero()
+            })
+            .collect()
+    }
+
+    pub fn local_intersect(&self, ray: Ray<T>) -> Vec<Intersection<T>> {
+        self.local_intersect_with(ray, || get_rc(Shape::Lathe(self.clone())))
+    }
+
+    pub fn local_intersect_with(
+        &self,
+        ray: Ray<T>,
+        object: impl FnOnce() -> ShapeRc<T>,
+    ) -> Vec<Intersection<T>> {
+        let mut ts = self
+            .profile
+            .windows(2)
+            .flat_map(|w| self.intersect_segment(ray, w[0], w[1]))
+            .collect::<Vec<_>>();
+        ts.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Less));
+        intersections(ts, object)
+    }
+
+    pub fn local_normal_at(&self, point: Point3<T>) -> Vector3<T> {
+        let rho = (point.x.powi(2) + point.z.powi(2)).sqrt();
+        let p = Point2::new(rho, point.y);
+        // Use the segment closest to the point in the (radius, y) plane.
+        let (dr, dy) = self
+            .profile
+            .windows(2)
+            .map(|w| {
+                let d = w[1] - w[0];
+                let fraction =
+                    ((p - w[0]).x * d.x + (p - w[0]).y * d.y) / (d.x.powi(2) + d.y.powi(2));
+                let closest = w[0] + d * fraction.max(T::zero()).min(T::one());
+                ((p - closest).x.powi(2) + (p - closest).y.powi(2), d)
+            })
+            .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Less))
+            .map_or((T::zero(), T::one()), |(_, d)| (d.x, d.y));
+        if rho > T::zero() {
+            Vector3::new(dy * point.x / rho, -dr, dy * point.z / rho)
+        } else {
+            Vector3::new(T::zero(), -dr, T::zero())
+        }
+    }
+}
+
+mod tests {
+    use super::*;
+    use crate::shape::cylinder::Cylinder;
+    use cgmath::{assert_relative_eq, InnerSpace};
+
+    #[test]
+    fn local_intersect() {
+        let lathe = Lathe::from(vec![Point2::new(1., -1.), Point2::new(1., 1.)]);
+        let cylinder = Cylinder {
+            minimum: -1.,
+            maximum: 1.,
+            ..Cylinder::default()
+        };
+        for ray in [
+            Ray::new(Point3::new(1., 0., -5.), Vector3::unit_z()),
+            Ray::new(Point3::new(0., 0., -5.), Vector3::unit_z()),
+            Ray::new(
+                Point3::new(0.5, 0., -5.),
+                Vector3::new(0.1, 1., 1.).normalize(),
+            ),
+            Ray::new(Point3::new(0., 0.5, -5.), Vector3::new(0., 0.1, 1.)),
+            Ray::new(Point3::new(0., 3., -5.), Vector3::unit_z()),
+            Ray::new(Point3::new(0., 0., 0.), Vector3::unit_y()),
+        ] {
+            let expected = cylinder
+                .local_intersect(ray)
+                .iter()
+                .map(|i| i.t)
+                .collect::<Vec<_>>();
+            let actual = lathe
+                .local_intersect(ray)
+                .iter()
+                .map(|i| i.t)
+                .collect::<Vec<_>>();
+            assert_eq!(actual.len(), expected.len());
+            assert_relative_eq!(actual[..], expected[..], max_relative = 0.00001);
+        }
+    }
+
+    #[test]
+    fn local_normal_at() {
+        let lathe = Lathe::from(vec![
+            Point2::new(0., 0.),
+            Point2::new(1., 0.),
+            Point2::new(1., 1.),
+            Point2::new(0., 2.),
+        ]);
+        assert_eq!(
+            lathe.local_normal_at(Point3::new(0.5, 0., 0.)),
+            -Vector3::unit_y()
+        );
+        assert_relative_eq!(
+            lathe.local_normal_at(Point3::new(0., 0.5, -1.)).normalize(),
+            -Vector3::unit_z()
+        );
+        assert_relative_eq!(
+            lathe.local_normal_at(Point3::new(0.5, 1.5, 0.)).normalize(),
+            Vector3::new(1., 1., 0.).normalize()
+        );
+    }
+}
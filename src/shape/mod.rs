@@ -2,11 +2,17 @@ pub mod cone;
 pub mod constructive_solid_geometry;
 pub mod cube;
 pub mod cylinder;
+pub mod disk;
+pub mod frustum;
 pub mod group;
+pub mod instanced;
+pub mod lathe;
 pub mod obj_file;
 pub mod plane;
+pub mod quadric;
 pub mod smooth_triangle;
 pub mod sphere;
+pub mod torus;
 pub mod triangle;
 
 use crate::{
@@ -16,27 +22,35 @@ use crate::{
     ray::Ray,
     shape::{
         cone::Cone, constructive_solid_geometry::ConstructiveSolidGeometry, cube::Cube,
-        cylinder::Cylinder, group::Group, plane::Plane, smooth_triangle::SmoothTriangle,
-        sphere::Sphere, triangle::Triangle,
+        cylinder::Cylinder, disk::Disk, frustum::Frustum, group::Group, instanced::Instanced,
+        lathe::Lathe, plane::Plane, quadric::Quadric, smooth_triangle::SmoothTriangle,
+        sphere::Sphere, torus::Torus, triangle::Triangle,
     },
 };
-use cgmath::{BaseFloat, InnerSpace, Matrix, Matrix4, Point3, SquareMatrix, Vector3};
+use cgmath::{BaseFloat, InnerSpace, Matrix, Matrix4, Point3, Rad, SquareMatrix, Vector3, Zero};
 use enum_as_inner::EnumAsInner;
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     rc::{Rc, Weak},
 };
 
 #[derive(Clone, Debug, EnumAsInner, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Shape<T> {
     Cone(Cone<T>),
     ConstructiveSolidGeometry(ConstructiveSolidGeometry<T>),
     Cube(Cube<T>),
     Cylinder(Cylinder<T>),
+    Disk(Disk<T>),
+    Frustum(Frustum<T>),
     Group(Group<T>),
+    Instanced(Instanced<T>),
+    Lathe(Lathe<T>),
     Plane(Plane<T>),
+    Quadric(Quadric<T>),
     SmoothTriangle(SmoothTriangle<T>),
     Sphere(Sphere<T>),
+    Torus(Torus<T>),
     Triangle(Triangle<T>),
 }
 
@@ -47,10 +61,16 @@ impl<T> Shape<T> {
             Shape::ConstructiveSolidGeometry(c) => c.parent.clone(),
             Shape::Cube(c) => c.parent.clone(),
             Shape::Cylinder(c) => c.parent.clone(),
+            Shape::Disk(d) => d.parent.clone(),
+            Shape::Frustum(fr) => fr.parent.clone(),
             Shape::Group(g) => g.parent.clone(),
+            Shape::Instanced(i) => i.parent.clone(),
+            Shape::Lathe(l) => l.parent.clone(),
             Shape::Plane(p) => p.parent.clone(),
+            Shape::Quadric(q) => q.parent.clone(),
             Shape::SmoothTriangle(s) => s.parent.clone(),
             Shape::Sphere(s) => s.parent.clone(),
+            Shape::Torus(t) => t.parent.clone(),
             Shape::Triangle(t) => t.parent.clone(),
         }
     }
@@ -61,10 +81,16 @@ impl<T> Shape<T> {
             Shape::ConstructiveSolidGeometry(c) => c.parent = parent,
             Shape::Cube(c) => c.parent = parent,
             Shape::Cylinder(c) => c.parent = parent,
+            Shape::Disk(d) => d.parent = parent,
+            Shape::Frustum(fr) => fr.parent = parent,
             Shape::Group(g) => g.parent = parent,
+            Shape::Instanced(i) => i.parent = parent,
+            Shape::Lathe(l) => l.parent = parent,
             Shape::Plane(p) => p.parent = parent,
+            Shape::Quadric(q) => q.parent = parent,
             Shape::SmoothTriangle(s) => s.parent = parent,
             Shape::Sphere(s) => s.parent = parent,
+            Shape::Torus(t) => t.parent = parent,
             Shape::Triangle(t) => t.parent = parent,
         }
     }
@@ -77,25 +103,115 @@ impl<T: BaseFloat> Shape<T> {
             Shape::ConstructiveSolidGeometry(c) => c.transform,
             Shape::Cube(c) => c.transform,
             Shape::Cylinder(c) => c.transform,
+            Shape::Disk(d) => d.transform,
+            Shape::Frustum(fr) => fr.transform,
             Shape::Group(g) => g.transform,
+            Shape::Instanced(i) => i.transform,
+            Shape::Lathe(l) => l.transform,
             Shape::Plane(p) => p.transform,
-            Shape::SmoothTriangle(_) => Matrix4::identity(),
+            Shape::Quadric(q) => q.transform,
+            Shape::SmoothTriangle(s) => s.transform,
             Shape::Sphere(s) => s.transform,
+            Shape::Torus(t) => t.transform,
             Shape::Triangle(t) => t.transform,
         }
     }
 
-    pub fn material(&self) -> Option<Material<T>> {
+    // Compose m onto the current transform, moving the shape within its parent's space.
+    pub fn apply_transform(&mut self, m: Matrix4<T>) {
         match self {
-            Shape::Cone(c) => Some(c.material),
+            Shape::Cone(c) => c.transform = m * c.transform,
+            Shape::ConstructiveSolidGeometry(c) => c.transform = m * c.transform,
+            Shape::Cube(c) => c.transform = m * c.transform,
+            Shape::Cylinder(c) => c.transform = m * c.transform,
+            Shape::Disk(d) => d.transform = m * d.transform,
+            Shape::Frustum(fr) => fr.transform = m * fr.transform,
+            Shape::Group(g) => g.transform = m * g.transform,
+            Shape::Instanced(i) => i.transform = m * i.transform,
+            Shape::Lathe(l) => l.transform = m * l.transform,
+            Shape::Plane(p) => p.transform = m * p.transform,
+            Shape::Quadric(q) => q.transform = m * q.transform,
+            Shape::SmoothTriangle(s) => s.transform = m * s.transform,
+            Shape::Sphere(s) => s.transform = m * s.transform,
+            Shape::Torus(t) => t.transform = m * t.transform,
+            Shape::Triangle(t) => t.transform = m * t.transform,
+        }
+    }
+
+    pub fn translate(mut self, x: T, y: T, z: T) -> Shape<T> {
+        self.apply_transform(Matrix4::from_translation(Vector3::new(x, y, z)));
+        self
+    }
+
+    pub fn scale(mut self, x: T, y: T, z: T) -> Shape<T> {
+        self.apply_transform(Matrix4::from_nonuniform_scale(x, y, z));
+        self
+    }
+
+    pub fn rotate_x(mut self, angle: T) -> Shape<T> {
+        self.apply_transform(Matrix4::from_angle_x(Rad(angle)));
+        self
+    }
+
+    pub fn rotate_y(mut self, angle: T) -> Shape<T> {
+        self.apply_transform(Matrix4::from_angle_y(Rad(angle)));
+        self
+    }
+
+    pub fn rotate_z(mut self, angle: T) -> Shape<T> {
+        self.apply_transform(Matrix4::from_angle_z(Rad(angle)));
+        self
+    }
+
+    pub fn material(&self) -> Option<&Material<T>> {
+        match self {
+            Shape::Cone(c) => Some(&c.material),
             Shape::ConstructiveSolidGeometry(_) => None,
-            Shape::Cube(c) => Some(c.material),
-            Shape::Cylinder(c) => Some(c.material),
+            Shape::Cube(c) => Some(&c.material),
+            Shape::Cylinder(c) => Some(&c.material),
+            Shape::Disk(d) => Some(&d.material),
+            Shape::Frustum(fr) => Some(&fr.material),
             Shape::Group(_) => None,
-            Shape::Plane(p) => Some(p.material),
-            Shape::SmoothTriangle(s) => Some(s.material),
-            Shape::Sphere(s) => Some(s.material),
-            Shape::Triangle(t) => Some(t.material),
+            Shape::Instanced(_) => None,
+            Shape::Lathe(l) => Some(&l.material),
+            Shape::Plane(p) => Some(&p.material),
+            Shape::Quadric(q) => Some(&q.material),
+            Shape::SmoothTriangle(s) => Some(&s.material),
+            Shape::Sphere(s) => Some(&s.material),
+            Shape::Torus(t) => Some(&t.material),
+            Shape::Triangle(t) => Some(&t.material),
+        }
+    }
+
+    // Apply f to the material of every leaf below this shape.
+    pub fn map_materials(&mut self, f: impl Fn(&mut Material<T>)) {
+        self.map_materials_dyn(&f);
+    }
+
+    fn map_materials_dyn(&mut self, f: &dyn Fn(&mut Material<T>)) {
+        match self {
+            Shape::Cone(c) => f(&mut c.material),
+            Shape::ConstructiveSolidGeometry(c) => {
+                c.left.borrow_mut().map_materials_dyn(f);
+                c.right.borrow_mut().map_materials_dyn(f);
+            }
+            Shape::Cube(c) => f(&mut c.material),
+            Shape::Cylinder(c) => f(&mut c.material),
+            Shape::Disk(d) => f(&mut d.material),
+            Shape::Frustum(fr) => f(&mut fr.material),
+            Shape::Group(g) => {
+                for child in &g.children {
+                    child.borrow_mut().map_materials_dyn(f);
+                }
+            }
+            Shape::Instanced(i) => i.child.borrow_mut().map_materials_dyn(f),
+            Shape::Lathe(l) => f(&mut l.material),
+            Shape::Plane(p) => f(&mut p.material),
+            Shape::Quadric(q) => f(&mut q.material),
+            Shape::SmoothTriangle(s) => f(&mut s.material),
+            Shape::Sphere(s) => f(&mut s.material),
+            Shape::Torus(t) => f(&mut t.material),
+            Shape::Triangle(t) => f(&mut t.material),
         }
     }
 
@@ -105,10 +221,16 @@ impl<T: BaseFloat> Shape<T> {
             Shape::ConstructiveSolidGeometry(c) => Some(c.bounds()),
             Shape::Cube(c) => Some(c.bounds()),
             Shape::Cylinder(c) => Some(c.bounds()),
+            Shape::Disk(d) => Some(d.bounds()),
+            Shape::Frustum(fr) => Some(fr.bounds()),
             Shape::Group(g) => g.bounds(),
+            Shape::Instanced(i) => i.bounds(),
+            Shape::Lathe(l) => Some(l.bounds()),
             Shape::Plane(p) => Some(p.bounds()),
+            Shape::Quadric(q) => Some(q.bounds()),
             Shape::SmoothTriangle(s) => Some(s.bounds()),
             Shape::Sphere(s) => Some(s.bounds()),
+            Shape::Torus(t) => Some(t.bounds()),
             Shape::Triangle(t) => Some(t.bounds()),
         }
     }
@@ -130,28 +252,76 @@ impl<T: BaseFloat> Shape<T> {
                 panic!("The local_normal_at() is not supposed to by called on Shape::ConstructiveSolidGeometry."),
             Shape::Cube(c) => c.local_normal_at(point),
             Shape::Cylinder(c) => c.local_normal_at(point),
+            Shape::Disk(d) => d.local_normal_at(point),
+            Shape::Frustum(fr) => fr.local_normal_at(point),
             Shape::Group(_) =>
                 panic!("The local_normal_at() is not supposed to by called on Shape::Group."),
+            Shape::Instanced(_) =>
+                panic!("The local_normal_at() is not supposed to by called on Shape::Instanced."),
+            Shape::Lathe(l) => l.local_normal_at(point),
             Shape::Plane(p) => p.local_normal_at(point),
+            Shape::Quadric(q) => q.local_normal_at(point),
             Shape::SmoothTriangle(s) => s.local_normal_at(point, uv),
             Shape::Sphere(s) => s.local_normal_at(point),
+            Shape::Torus(t) => t.local_normal_at(point),
             Shape::Triangle(t) => t.local_normal_at(point),
         }
     }
 
     pub fn intersect(&self, ray: Ray<T>) -> Vec<Intersection<T>> {
+        self.intersect_counting(ray, &Cell::new(0))
+    }
+
+    // Like intersect, but adds one to the counter for every primitive tested on the way,
+    // including those inside groups, CSG and instances. Useful to measure how much the
+    // bounding volumes save. A shape that is not kept in an Rc is copied into one if it is hit.
+    pub fn intersect_counting(&self, ray: Ray<T>, counter: &Cell<u64>) -> Vec<Intersection<T>> {
+        self.intersect_as(ray, counter, || get_rc(self.clone()))
+    }
+
+    // The object gives the Rc that hits on this shape point to.
+    fn intersect_as(
+        &self,
+        ray: Ray<T>,
+        counter: &Cell<u64>,
+        object: impl FnOnce() -> ShapeRc<T>,
+    ) -> Vec<Intersection<T>> {
+        let mut xs = self.local_intersect_as(ray, counter, object);
+        // Offsets of instanced copies come back in this shape's space.
+        for x in xs.iter_mut().filter(|x| !x.offset.is_zero()) {
+            x.offset = (self.transform() * x.offset.extend(T::zero())).truncate();
+        }
+        xs
+    }
+
+    fn local_intersect_as(
+        &self,
+        ray: Ray<T>,
+        counter: &Cell<u64>,
+        object: impl FnOnce() -> ShapeRc<T>,
+    ) -> Vec<Intersection<T>> {
         if let Some(i) = self.transform().invert() {
             let r = ray.transform(i);
+            let tested = |xs| {
+                counter.set(counter.get() + 1);
+                xs
+            };
             match self {
-                Shape::Cone(c) => c.local_intersect(r),
-                Shape::ConstructiveSolidGeometry(c) => c.local_intersect(r),
-                Shape::Cube(c) => c.local_intersect(r),
-                Shape::Cylinder(c) => c.local_intersect(r),
-                Shape::Group(g) => g.local_intersect(r),
-                Shape::Plane(p) => p.local_intersect(r),
-                Shape::SmoothTriangle(s) => s.local_intersect(r),
-                Shape::Sphere(s) => s.local_intersect(r),
-                Shape::Triangle(t) => t.local_intersect(r),
+                Shape::Cone(c) => tested(c.local_intersect_with(r, object)),
+                Shape::ConstructiveSolidGeometry(c) => c.local_intersect_counting(r, counter),
+                Shape::Cube(c) => tested(c.local_intersect_with(r, object)),
+                Shape::Cylinder(c) => tested(c.local_intersect_with(r, object)),
+                Shape::Disk(d) => tested(d.local_intersect_with(r, object)),
+                Shape::Frustum(fr) => tested(fr.local_intersect_with(r, object)),
+                Shape::Group(g) => g.local_intersect_counting(r, counter),
+                Shape::Instanced(i) => i.local_intersect_counting(r, counter),
+                Shape::Lathe(l) => tested(l.local_intersect_with(r, object)),
+                Shape::Plane(p) => tested(p.local_intersect_with(r, object)),
+                Shape::Quadric(q) => tested(q.local_intersect_with(r, object)),
+                Shape::SmoothTriangle(s) => tested(s.local_intersect_with(r, object)),
+                Shape::Sphere(s) => tested(s.local_intersect_with(r, object)),
+                Shape::Torus(t) => tested(t.local_intersect_with(r, object)),
+                Shape::Triangle(t) => tested(t.local_intersect_with(r, object)),
             }
         } else {
             Vec::new()
@@ -192,17 +362,71 @@ impl<T: BaseFloat> Shape<T> {
         })
     }
 
+    // The world normal of a flat triangle as if its mesh were smooth, blending its own vertex
+    // normals or else those vertex_normals gives; None for other shapes.
+    pub fn smoothed_normal_at(
+        &self,
+        uv: Option<(T, T)>,
+        vertex_normals: impl FnOnce(&Triangle<T>) -> Option<[Vector3<T>; 3]>,
+    ) -> Option<Vector3<T>> {
+        match (self, uv) {
+            (Shape::Triangle(t), Some(uv)) => {
+                let normals = t.vertex_normals.or_else(|| vertex_normals(t))?;
+                self.normal_to_world(t.interpolated_normal(normals, uv))
+            }
+            _ => None,
+        }
+    }
+
     pub fn normal_at(&self, world_point: Point3<T>, uv: Option<(T, T)>) -> Option<Vector3<T>> {
         self.world_to_object(world_point)
             .map(|local_point| self.local_normal_at(local_point, uv))
             .map(|local_normal| self.normal_to_world(local_normal).unwrap())
     }
+
+    fn vector_to_world(&self, vector: Vector3<T>) -> Vector3<T> {
+        let v = (self.transform() * vector.extend(T::zero())).truncate();
+        self.parent()
+            .and_then(|weak| weak.upgrade())
+            .map_or(v, |rc| rc.borrow().vector_to_world(v))
+    }
+
+    // The world directions dP/du and dP/dv the surface moves in as its uv grow, for the shapes
+    // that give their hits a uv.
+    pub fn uv_derivatives(&self, world_point: Point3<T>) -> Option<(Vector3<T>, Vector3<T>)> {
+        let (dpdu, dpdv) = match self {
+            Shape::Sphere(s) => s.local_uv_derivatives(self.world_to_object(world_point)?),
+            Shape::SmoothTriangle(s) => s.local_uv_derivatives(),
+            Shape::Triangle(t) => t.local_uv_derivatives(),
+            _ => return None,
+        };
+        Some((self.vector_to_world(dpdu), self.vector_to_world(dpdv)))
+    }
+
+    // Where a hit with the given barycentric uv falls in the texture coordinates of a mesh
+    // triangle, blending those of its vertices; None if the triangle has none.
+    pub fn texture_uv(&self, uv: Option<(T, T)>) -> Option<(T, T)> {
+        let [a, b, c] = match self {
+            Shape::Triangle(t) => t.texture_coords?,
+            Shape::SmoothTriangle(s) => s.texture_coords?,
+            _ => return None,
+        };
+        let (u, v) = uv?;
+        let w = T::one() - u - v;
+        Some((a.0 * w + b.0 * u + c.0 * v, a.1 * w + b.1 * u + c.1 * v))
+    }
 }
 
 pub fn reflect<T: BaseFloat>(v: Vector3<T>, normal: Vector3<T>) -> Vector3<T> {
     v - normal * T::from(2).unwrap() * v.dot(normal)
 }
 
+// Triangles follow the book's convention: the normal is (p3 - p1) x (p2 - p1) with the
+// right-handed cross product, so the vertices run clockwise seen from the side it points to.
+pub fn face_normal<T: BaseFloat>(p1: Point3<T>, p2: Point3<T>, p3: Point3<T>) -> Vector3<T> {
+    (p3 - p1).cross(p2 - p1).normalize()
+}
+
 pub type ShapeWeak<T> = Weak<RefCell<Shape<T>>>;
 
 pub type ShapeRc<T> = Rc<RefCell<Shape<T>>>;
@@ -216,9 +440,26 @@ pub fn get_rc_with_parent<T>(mut shape: Shape<T>, parent: &ShapeRc<T>) -> ShapeR
     Rc::new(RefCell::new(shape))
 }
 
+impl<T> From<Shape<T>> for ShapeRc<T> {
+    fn from(shape: Shape<T>) -> ShapeRc<T> {
+        get_rc(shape)
+    }
+}
+
+// Intersect a shape kept in an Rc, so that its hits share that Rc instead of a copy.
+pub fn intersect_shared<T: BaseFloat>(
+    shape: &ShapeRc<T>,
+    ray: Ray<T>,
+    counter: &Cell<u64>,
+) -> Vec<Intersection<T>> {
+    shape
+        .borrow()
+        .intersect_as(ray, counter, || Rc::clone(shape))
+}
+
 mod tests {
     use super::*;
-    use cgmath::{assert_relative_eq, EuclideanSpace, Rad};
+    use cgmath::{assert_relative_eq, EuclideanSpace};
     use std::f32::consts::{FRAC_1_SQRT_2, PI};
 
     #[test]
@@ -261,4 +502,42 @@ mod tests {
             Vector3::unit_x()
         );
     }
+
+    #[test]
+    fn transform_builders() {
+        let sphere = Shape::Sphere(Sphere::default());
+        assert_eq!(
+            sphere
+                .clone()
+                .translate(1., 0., 0.)
+                .scale(2., 2., 2.)
+                .transform(),
+            Matrix4::from_scale(2.) * Matrix4::from_translation(Vector3::unit_x())
+        );
+        assert_eq!(
+            sphere.rotate_y(PI).rotate_x(1.).transform(),
+            Matrix4::from_angle_x(Rad(1.)) * Matrix4::from_angle_y(Rad(PI))
+        );
+    }
+
+    #[test]
+    fn map_materials() {
+        let group = get_rc(Shape::Group(group::Group::default()));
+        let inner = get_rc(Shape::Group(group::Group::default()));
+        for _ in 0..2 {
+            group::push(
+                &inner,
+                Shape::Triangle(triangle::Triangle::from(
+                    Point3::origin(),
+                    Point3::new(1., 0., 0.),
+                    Point3::new(0., 1., 0.),
+                )),
+            );
+        }
+        group::push_link(&group, inner.clone());
+        group.borrow_mut().map_materials(|m| m.reflective = 0.5);
+        for child in &inner.borrow().as_group().unwrap().children {
+            assert_eq!(child.borrow().material().unwrap().reflective, 0.5);
+        }
+    }
 }
@@ -1,72 +1,233 @@
 use crate::{
+    canvas::Canvas,
     material::Material,
-    shape::{
-        get_rc,
-        group::{push, push_link},
-        Group, Shape, ShapeRc, SmoothTriangle, Triangle,
-    },
+    pattern::{image_texture::ImageTexture, uv::UvMapping, Pattern},
+    shape::{get_rc, group::push_link, Group, Shape, ShapeRc, SmoothTriangle, Triangle},
+};
+use cgmath::{BaseFloat, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3, Zero};
+use rgb::RGB;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::{self, Read},
+    path::Path,
+    rc::Rc,
+    str::FromStr,
 };
-use cgmath::{BaseFloat, Point3, Vector3};
-use std::{collections::HashMap, rc::Rc, str::FromStr};
 
 pub struct Parser<T> {
-    groups: HashMap<String, ShapeRc<T>>,
+    // Ordered by label so that obj_to_group is reproducible.
+    groups: BTreeMap<String, ShapeRc<T>>,
+    // The triangles carry their own copies, so only the tests look at these.
+    #[cfg(test)]
     vertices: Vec<Point3<T>>,
-    normals: Vec<Vector3<T>>,
+    #[cfg(test)]
+    texture_coords: Vec<(T, T)>,
+}
+
+// The materials of an MTL file by name. Kd sets the color, the Ks channels are averaged into
+// the specular strength, and d (opacity) or Tr (its complement) set the transparency.
+pub fn parse_mtl_file<T: BaseFloat + FromStr + Default>(s: &str) -> HashMap<String, Material<T>> {
+    parse_mtl_file_with_loader(s, |_| None)
+}
+
+// Like parse_mtl_file, but `map_Kd file` replaces the color with the PPM image load(file)
+// returns, if it finds and parses it. The image is sampled at the vt coordinates of each hit,
+// blended across the face; faces without them fall back to planar mapping.
+pub fn parse_mtl_file_with_loader<T: BaseFloat + FromStr + Default>(
+    s: &str,
+    load: impl Fn(&str) -> Option<String>,
+) -> HashMap<String, Material<T>> {
+    let mut materials = HashMap::new();
+    let mut current = None;
+    for l in s.lines() {
+        let l = l.split('#').next().unwrap_or_default();
+        let mut iter = l.split_whitespace();
+        let keyword = iter.next();
+        if keyword == Some("newmtl") {
+            current = iter.next().map(|name| {
+                materials.insert(name.to_string(), Material::default());
+                name.to_string()
+            });
+            continue;
+        }
+        let Some(material) = current.as_ref().and_then(|name| materials.get_mut(name)) else {
+            continue;
+        };
+        if keyword == Some("map_Kd") {
+            // Any options come before the file name.
+            if let Some(canvas) = iter
+                .last()
+                .and_then(&load)
+                .and_then(|text| Canvas::from_ppm(&text).ok())
+            {
+                material.pattern = Pattern::ImageTexture(ImageTexture::new(
+                    canvas,
+                    UvMapping::Mesh,
+                    Matrix4::identity(),
+                ));
+            }
+            continue;
+        }
+        let values = iter
+            .map(|v| T::from_str(v).unwrap_or_default())
+            .collect::<Vec<_>>();
+        let value = values.first().copied().unwrap_or_default();
+        let color = || match values[..] {
+            [r, g, b, ..] => RGB::new(r, g, b),
+            _ => RGB::new(value, value, value),
+        };
+        match keyword {
+            Some("Kd") => material.pattern = Pattern::Solid(color()),
+            Some("Ks") => {
+                let c = color();
+                material.specular = (c.r + c.g + c.b) / T::from(3).unwrap();
+            }
+            Some("Ns") => material.shininess = value,
+            Some("d") => material.transparency = T::one() - value,
+            Some("Tr") => material.transparency = value,
+            Some("Ni") => material.refractive_index = value,
+            _ => {}
+        }
+    }
+    materials
 }
 
 fn fan_tranigulation<T: BaseFloat + Default>(
     vertices: &[Point3<T>],
     normals: &[Vector3<T>],
+    texture_coords: &[(T, T)],
     index: &[Vec<Option<usize>>],
-) -> Vec<Shape<T>> {
-    // Assuming a convex polygon
+    material: Material<T>,
+) -> Vec<(Shape<T>, [usize; 3])> {
+    // The vt of a face vertex, if it has a valid one.
+    let texture = |v: &Vec<Option<usize>>| {
+        v.get(1)
+            .copied()
+            .flatten()
+            .and_then(|i| i.checked_sub(1))
+            .and_then(|i| texture_coords.get(i))
+            .copied()
+    };
+    let vertex_index = |v: &Vec<Option<usize>>| v[0].unwrap() - 1;
+    let vertex = |v: &Vec<Option<usize>>| vertices[vertex_index(v)];
+    let normal = |v: &Vec<Option<usize>>| normals[v[2].unwrap() - 1];
+    // Assuming a convex polygon, fan out from its first vertex. Each triangle comes with the
+    // indices of its vertices.
     (1..index.len() - 1)
-        .map(|i| &index[i])
-        .map(|v| {
-            if v.len() == 1 {
-                Shape::Triangle(Triangle::from(
-                    vertices[index[0][0].unwrap() - 1],
-                    vertices[v[0].unwrap() - 1],
-                    vertices[v[0].unwrap()],
-                ))
+        .map(|i| (&index[i], &index[i + 1]))
+        .map(|(v, next)| {
+            let texture_coords = match (texture(&index[0]), texture(v), texture(next)) {
+                (Some(a), Some(b), Some(c)) => Some([a, b, c]),
+                _ => None,
+            };
+            // Without a vn index the face is flat, even with a vt index.
+            let shape = if v.get(2).copied().flatten().is_none() {
+                Shape::Triangle(Triangle {
+                    material: material.clone(),
+                    texture_coords,
+                    ..Triangle::from(vertex(&index[0]), vertex(v), vertex(next))
+                })
             } else {
-                Shape::SmoothTriangle(SmoothTriangle::new(
-                    Material::default(),
-                    vertices[index[0][0].unwrap() - 1],
-                    vertices[v[0].unwrap() - 1],
-                    vertices[v[0].unwrap()],
-                    normals[index[0][2].unwrap() - 1],
-                    normals[v[2].unwrap() - 1],
-                    normals[v[2].unwrap()],
+                let mut triangle = SmoothTriangle::new(
+                    material.clone(),
+                    vertex(&index[0]),
+                    vertex(v),
+                    vertex(next),
+                    normal(&index[0]),
+                    normal(v),
+                    normal(next),
                     None,
-                ))
-            }
+                );
+                triangle.texture_coords = texture_coords;
+                Shape::SmoothTriangle(triangle)
+            };
+            (shape, [&index[0], v, next].map(vertex_index))
         })
         .collect::<Vec<_>>()
 }
 
 impl<T: BaseFloat + FromStr + Default> Parser<T> {
     pub fn parse_obj_file(s: &str) -> Parser<T> {
+        Parser::parse_obj_file_with_materials(s, &HashMap::new())
+    }
+
+    pub fn from_reader<R: Read>(mut r: R) -> io::Result<Parser<T>> {
+        let mut s = String::new();
+        r.read_to_string(&mut s)?;
+        Ok(Parser::parse_obj_file(&s))
+    }
+
+    // MTL files named by mtllib are read relative to the OBJ file, and their textures relative
+    // to the MTL file.
+    pub fn from_path(path: &Path) -> io::Result<Parser<T>> {
+        let mut s = String::new();
+        File::open(path)?.read_to_string(&mut s)?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        Ok(Parser::parse_obj_file_with_loader(&s, |file| {
+            fs::read_to_string(dir.join(file)).ok()
+        }))
+    }
+
+    // Faces following `usemtl name` get materials[name], or the default material if it is unknown.
+    pub fn parse_obj_file_with_materials(
+        s: &str,
+        materials: &HashMap<String, Material<T>>,
+    ) -> Parser<T> {
+        Parser::parse(s, materials.clone(), |_| None)
+    }
+
+    // Like parse_obj_file, but `mtllib file` reads the materials from load(file), if it finds it.
+    pub fn parse_obj_file_with_loader(s: &str, load: impl Fn(&str) -> Option<String>) -> Parser<T> {
+        Parser::parse(s, HashMap::new(), load)
+    }
+
+    fn parse(
+        s: &str,
+        mut materials: HashMap<String, Material<T>>,
+        load: impl Fn(&str) -> Option<String>,
+    ) -> Parser<T> {
         let mut vertices = Vec::new();
         let mut normals = Vec::new();
+        let mut texture_coords = Vec::new();
         let group = Group::default();
-        let mut groups = HashMap::new();
+        let mut groups = BTreeMap::new();
         groups.insert("default".to_string(), get_rc(Shape::Group(group)));
         let mut current_label = "default";
+        let mut current_material = Material::default();
+        let mut flat_triangles = Vec::new();
         for l in s.lines() {
+            // Everything after `#` is a comment; a stray `\r` is whitespace to split_whitespace.
+            let l = l.split('#').next().unwrap_or_default();
             let mut iter = l.split_whitespace();
             match iter.next() {
                 Some("f") => {
+                    // Negative indices count back from the end of the lists read so far.
+                    let lens = [vertices.len(), texture_coords.len(), normals.len()];
                     let index = iter
                         .map(|s| {
                             s.split_terminator('/')
-                                .map(|s| usize::from_str(s).ok())
+                                .zip(lens)
+                                .map(|(s, len)| match isize::from_str(s).ok()? {
+                                    i if i < 0 => (len + 1).checked_sub(i.unsigned_abs()),
+                                    i => Some(i as usize),
+                                })
                                 .collect::<Vec<_>>()
                         })
                         .collect::<Vec<_>>();
-                    for tri in fan_tranigulation(&vertices, &normals, &index) {
-                        push(groups.get(current_label).unwrap(), tri);
+                    for (tri, corners) in fan_tranigulation(
+                        &vertices,
+                        &normals,
+                        &texture_coords,
+                        &index,
+                        current_material.clone(),
+                    ) {
+                        let tri = get_rc(tri);
+                        let group = groups.get(current_label).unwrap();
+                        if matches!(*tri.borrow(), Shape::Triangle(_)) {
+                            flat_triangles.push((Rc::as_ptr(group), tri.clone(), corners));
+                        }
+                        push_link(group, tri);
                     }
                 }
                 Some("g") => {
@@ -77,6 +238,24 @@ impl<T: BaseFloat + FromStr + Default> Parser<T> {
                     }
                 }
                 Some("l") => {}
+                Some("mtllib") => {
+                    for file in iter {
+                        if let Some(text) = load(file) {
+                            // Textures are named relative to the MTL file.
+                            let dir = Path::new(file).parent().unwrap_or(Path::new(""));
+                            materials.extend(parse_mtl_file_with_loader(&text, |texture| {
+                                load(dir.join(texture).to_str()?)
+                            }));
+                        }
+                    }
+                }
+                Some("usemtl") => {
+                    current_material = iter
+                        .next()
+                        .and_then(|name| materials.get(name))
+                        .cloned()
+                        .unwrap_or_default();
+                }
                 Some("v") => vertices.push(Point3::new(
                     T::from_str(iter.next().unwrap()).unwrap_or_default(),
                     T::from_str(iter.next().unwrap()).unwrap_or_default(),
@@ -88,30 +267,54 @@ impl<T: BaseFloat + FromStr + Default> Parser<T> {
                     T::from_str(iter.next().unwrap()).unwrap_or_default(),
                 )),
                 Some("vp") => {}
-                Some("vt") => {}
+                Some("vt") => texture_coords.push((
+                    T::from_str(iter.next().unwrap()).unwrap_or_default(),
+                    iter.next()
+                        .and_then(|v| T::from_str(v).ok())
+                        .unwrap_or_default(),
+                )),
                 _ => {}
             }
         }
+        // Average the normals of the flat faces of each group around each vertex once, for
+        // smooth shading.
+        let mut sums = HashMap::new();
+        for (group, tri, corners) in &flat_triangles {
+            let normal = tri.borrow().as_triangle().unwrap().normal;
+            for &i in corners {
+                *sums.entry((*group, i)).or_insert_with(Vector3::zero) += normal;
+            }
+        }
+        for (group, tri, corners) in flat_triangles {
+            tri.borrow_mut().as_triangle_mut().unwrap().vertex_normals =
+                Some(corners.map(|i| sums[&(group, i)].normalize()));
+        }
         Parser {
             groups,
+            #[cfg(test)]
             vertices,
-            normals,
+            #[cfg(test)]
+            texture_coords,
         }
     }
 
-    pub fn obj_to_group(self) -> Shape<T> {
+    // The group is returned shared so that its children can still reach it as their parent.
+    // Before, it was a plain Shape; see the changelog.
+    pub fn obj_to_group(self) -> ShapeRc<T> {
         let top_group = get_rc(Shape::Group(Group::default()));
         for (_, group) in self.groups {
             if !group.borrow().as_group().unwrap().children.is_empty() {
                 push_link(&top_group, group);
             }
         }
-        Rc::try_unwrap(top_group).unwrap().into_inner()
+        top_group
     }
 }
 
 mod tests {
     use super::*;
+    use crate::ray::Ray;
+    use cgmath::{assert_relative_eq, Matrix4};
     use std::ops::Deref;
 
     #[test]
@@ -306,17 +509,409 @@ mod tests {
                 .clone();
             assert_eq!(
                 *children[0].borrow().deref(),
-                Shape::SmoothTriangle(SmoothTriangle::new(
-                    Material::default(),
+                Shape::SmoothTriangle(SmoothTriangle::from(
                     Point3::new(0., 1., 0.),
                     Point3::new(-1., 0., 0.),
                     Point3::new(1., 0., 0.),
                     Vector3::unit_y(),
                     -Vector3::unit_x(),
                     Vector3::unit_x(),
-                    None,
                 ))
             );
         }
     }
+
+    #[test]
+    fn obj_to_group() {
+        let obj = r#"
+            v -1 1 0
+            v -1 0 0
+            v 1 0 0
+            v 1 1 0
+
+            g Second
+            f 1 2 3
+            g First
+            f 1 3 4
+            g Third
+            f 2 3 4
+            "#;
+        let group = Parser::<f32>::parse_obj_file(obj).obj_to_group();
+        assert_eq!(group, Parser::<f32>::parse_obj_file(obj).obj_to_group());
+        let children = &group.borrow().as_group().unwrap().children.clone();
+        assert_eq!(children.len(), 3);
+        assert_eq!(
+            *children[0].borrow().as_group().unwrap().children[0]
+                .borrow()
+                .deref(),
+            Shape::Triangle(Triangle::from(
+                Point3::new(-1., 1., 0.),
+                Point3::new(1., 0., 0.),
+                Point3::new(1., 1., 0.),
+            ))
+        );
+    }
+
+    #[test]
+    fn usemtl() {
+        let red = Material {
+            ambient: 0.5,
+            ..Material::default()
+        };
+        let blue = Material {
+            diffuse: 0.5,
+            ..Material::default()
+        };
+        let materials = HashMap::from([
+            ("red".to_string(), red.clone()),
+            ("blue".to_string(), blue.clone()),
+        ]);
+        let parser = Parser::<f32>::parse_obj_file_with_materials(
+            r#"
+            v -1 1 0
+            v -1 0 0
+            v 1 0 0
+            v 1 1 0
+
+            g Square
+            usemtl red
+            f 1 2 3
+            usemtl blue
+            f 1 3 4
+            usemtl missing
+            f 1 2 4
+            "#,
+            &materials,
+        );
+        let children = parser
+            .groups
+            .get("Square")
+            .unwrap()
+            .borrow()
+            .as_group()
+            .unwrap()
+            .children
+            .clone();
+        assert_eq!(
+            children
+                .iter()
+                .map(|c| c.borrow().material().unwrap().clone())
+                .collect::<Vec<_>>(),
+            vec![red, blue, Material::default()]
+        );
+    }
+
+    #[test]
+    fn mtllib() {
+        let mtl = r#"
+            newmtl red
+            Kd 1 0 0
+            Ks 0.3 0.6 0.9
+            Ns 50
+
+            newmtl glass
+            Kd 0.1 0.1 0.1
+            d 0.25
+            Ni 1.5
+            "#;
+        let load = |file: &str| (file == "cube.mtl").then(|| mtl.to_string());
+        let parser = Parser::<f64>::parse_obj_file_with_loader(
+            r#"
+            mtllib cube.mtl
+            v -1 -1 -1
+            v 1 -1 -1
+            v 1 1 -1
+            v -1 1 -1
+            v -1 -1 1
+            v 1 -1 1
+            v 1 1 1
+            v -1 1 1
+
+            usemtl red
+            f 1 4 3 2
+            f 5 6 7 8
+            f 1 5 8 4
+            usemtl glass
+            f 2 3 7 6
+            f 4 8 7 3
+            f 1 2 6 5
+            "#,
+            load,
+        );
+        let red = Material {
+            pattern: Pattern::Solid(RGB::new(1., 0., 0.)),
+            specular: 0.6,
+            shininess: 50.,
+            ..Material::default()
+        };
+        let glass = Material {
+            pattern: Pattern::Solid(RGB::new(0.1, 0.1, 0.1)),
+            transparency: 0.75,
+            refractive_index: 1.5,
+            ..Material::default()
+        };
+        let children = parser.groups["default"]
+            .borrow()
+            .as_group()
+            .unwrap()
+            .children
+            .clone();
+        assert_eq!(children.len(), 12);
+        for (i, child) in children.iter().enumerate() {
+            let material = child.borrow().material().unwrap().clone();
+            approx::assert_relative_eq!(material.specular, if i < 6 { 0.6 } else { 0.9 });
+            let expected = if i < 6 { &red } else { &glass };
+            assert_eq!(
+                Material {
+                    specular: expected.specular,
+                    ..material
+                },
+                *expected
+            );
+        }
+        // Tr is the transparency itself, and an unknown library leaves the default material.
+        assert_eq!(
+            parse_mtl_file::<f64>("newmtl clear\nTr 0.4")["clear"].transparency,
+            0.4
+        );
+        let parser = Parser::<f64>::parse_obj_file_with_loader(
+            "mtllib missing.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl red\nf 1 2 3",
+            load,
+        );
+        assert_eq!(
+            parser.groups["default"]
+                .borrow()
+                .as_group()
+                .unwrap()
+                .children[0]
+                .borrow()
+                .material(),
+            Some(&Material::default())
+        );
+    }
+
+    #[test]
+    fn texture_coords() {
+        let parser = Parser::<f32>::parse_obj_file(
+            r#"
+            v -1 -1 -1
+            v 1 -1 -1
+            v 1 1 -1
+            v -1 1 -1
+            v -1 -1 1
+            v 1 -1 1
+            v 1 1 1
+            v -1 1 1
+
+            vt 0 0
+            vt 1 0
+            vt 1 1
+            vt 0 1
+
+            vn 0 0 1
+            vn 0 0 -1
+
+            g Front
+            f 1/1 2/2 3/3 4/4
+            g Back
+            f 5/1/1 6/2/1 7/3/1 8/4/1
+            g Plain
+            f 1 5 8
+            "#,
+        );
+        let (a, b, c, d) = ((0., 0.), (1., 0.), (1., 1.), (0., 1.));
+        assert_eq!(parser.texture_coords, vec![a, b, c, d]);
+        let coords = |label: &str| {
+            parser.groups[label]
+                .borrow()
+                .as_group()
+                .unwrap()
+                .children
+                .iter()
+                .map(|c| match &*c.borrow() {
+                    Shape::Triangle(t) => t.texture_coords,
+                    Shape::SmoothTriangle(s) => s.texture_coords,
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>()
+        };
+        let fan = vec![Some([a, b, c]), Some([a, c, d])];
+        assert_eq!(coords("Front"), fan);
+        assert_eq!(coords("Back"), fan);
+        assert_eq!(coords("Plain"), vec![None]);
+        assert!(
+            parser.groups["Back"].borrow().as_group().unwrap().children[0]
+                .borrow()
+                .as_smooth_triangle()
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn fan_triangulation() {
+        // The vertices and normals are listed out of order on purpose.
+        let parser = Parser::<f32>::parse_obj_file(
+            r#"
+            v 0 2 0
+            v 1 1 0
+            v -1 0 0
+            v -1 1 0
+            v 1 0 0
+
+            vn 0 0 -1
+            vn 0 1 0
+            vn 1 0 0
+            vn 0 -1 0
+            vn -1 0 0
+
+            f 4//5 3//4 5//1 2//3 1//2
+            "#,
+        );
+        let children = parser.groups["default"]
+            .borrow()
+            .as_group()
+            .unwrap()
+            .children
+            .clone();
+        let p = |x, y| Point3::new(x, y, 0.);
+        let expected = [
+            (
+                p(-1., 0.),
+                p(1., 0.),
+                -Vector3::unit_y(),
+                -Vector3::unit_z(),
+            ),
+            (p(1., 0.), p(1., 1.), -Vector3::unit_z(), Vector3::unit_x()),
+            (p(1., 1.), p(0., 2.), Vector3::unit_x(), Vector3::unit_y()),
+        ];
+        assert_eq!(children.len(), expected.len());
+        for (child, (p2, p3, n2, n3)) in children.iter().zip(expected) {
+            assert_eq!(
+                *child.borrow(),
+                Shape::SmoothTriangle(SmoothTriangle::from(
+                    p(-1., 1.),
+                    p2,
+                    p3,
+                    -Vector3::unit_x(),
+                    n2,
+                    n3
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn negative_indices() {
+        let parser = Parser::<f32>::parse_obj_file(
+            r#"
+            v 0 1 0
+            v -1 0 0
+            v 1 0 0
+            vn 0 0 1
+            vn 0 1 0
+            f 1 2 3
+            f -3 -2 -1
+            f 1//1 2//2 3//2
+            f -3//-2 -2//-1 -1//-1
+            "#,
+        );
+        let children = parser.groups["default"]
+            .borrow()
+            .as_group()
+            .unwrap()
+            .children
+            .clone();
+        assert_eq!(children.len(), 4);
+        assert_eq!(*children[0].borrow(), *children[1].borrow());
+        assert_eq!(*children[2].borrow(), *children[3].borrow());
+    }
+
+    #[test]
+    fn comments_and_crlf() {
+        let parser = Parser::<f32>::parse_obj_file(
+            "# exported on Windows\r\nv -1 1 0 # top left\r\nv -1 0 0\r\nv 1 0 0\t# bottom right\r\nf 1 2 3 # face\r\n",
+        );
+        assert_eq!(
+            parser.vertices,
+            vec![
+                Point3::new(-1., 1., 0.),
+                Point3::new(-1., 0., 0.),
+                Point3::new(1., 0., 0.)
+            ]
+        );
+        let group = parser.groups["default"].borrow();
+        assert_eq!(group.as_group().unwrap().children.len(), 1);
+    }
+
+    #[test]
+    fn transform_group() {
+        let group = Parser::<f32>::parse_obj_file(
+            r#"
+            v -1 1 0
+            v -1 0 0
+            v 1 0 0
+            v 1 1 0
+
+            f 1 2 3
+            f 1 3 4
+            "#,
+        )
+        .obj_to_group();
+        let rays = [
+            Ray::new(Point3::new(-0.5, 0.25, -2.), Vector3::unit_z()),
+            Ray::new(Point3::new(0.5, 0.75, -2.), Vector3::unit_z()),
+        ];
+        let ts = |group: &ShapeRc<f32>| {
+            rays.iter()
+                .map(|&r| group.borrow().intersect(r)[0].t)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ts(&group), vec![2., 2.]);
+        group
+            .borrow_mut()
+            .apply_transform(Matrix4::from_translation(Vector3::unit_z() * 3.));
+        assert_eq!(ts(&group), vec![5., 5.]);
+        let default_group = group.borrow().as_group().unwrap().children[0].clone();
+        let triangle = default_group.borrow().as_group().unwrap().children[1].clone();
+        assert_eq!(
+            triangle
+                .borrow()
+                .world_to_object(Point3::new(0.5, 0.75, 3.))
+                .unwrap(),
+            Point3::new(0.5, 0.75, 0.)
+        );
+    }
+
+    #[test]
+    fn vertex_normals() {
+        // Two faces of a roof meeting along the ridge from vertex 1 to vertex 2, and a copy of
+        // one of them on its own.
+        let group = Parser::<f32>::parse_obj_file(
+            r#"
+            v 0 1 0
+            v 0 1 1
+            v -1 0 0
+            v 1 0 0
+
+            g Roof
+            f 1 2 3
+            f 1 4 2
+            g Side
+            f 1 4 2
+            "#,
+        )
+        .obj_to_group();
+        let normals = |i: usize| {
+            let default_group = group.borrow().as_group().unwrap().children[i].clone();
+            let triangle = default_group.borrow().as_group().unwrap().children[0].clone();
+            let normals = triangle.borrow().as_triangle().unwrap().vertex_normals;
+            normals.unwrap()
+        };
+        let left = Vector3::new(-1., 1., 0.).normalize();
+        let right = Vector3::new(1., 1., 0.).normalize();
+        // Faces share vertices by index, but only within their group.
+        let vy = Vector3::unit_y();
+        assert_relative_eq!(normals(0)[..], [vy, vy, left][..], epsilon = 0.00001);
+        assert_relative_eq!(normals(1)[..], [right; 3][..], epsilon = 0.00001);
+    }
 }
@@ -3,15 +3,17 @@ use crate::{
     intersection::Intersection,
     material::Material,
     ray::Ray,
-    shape::{Shape, ShapeWeak},
+    shape::{get_rc, Shape, ShapeRc, ShapeWeak},
 };
 use cgmath::{abs_diff_eq, BaseFloat, Matrix4, Point3, SquareMatrix, Vector3};
 
 #[derive(Clone, derive_more::Constructor, Debug, derivative::Derivative)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derivative(PartialEq)]
 pub struct Plane<T> {
     pub transform: Matrix4<T>,
     pub material: Material<T>,
+    #[cfg_attr(feature = "serde", serde(skip, default = "Option::default"))]
     #[derivative(PartialEq = "ignore")]
     pub parent: Option<ShapeWeak<T>>,
 }
@@ -34,12 +36,20 @@ impl<T: BaseFloat> Plane<T> {
     }
 
     pub fn local_intersect(&self, ray: Ray<T>) -> Vec<Intersection<T>> {
+        self.local_intersect_with(ray, || get_rc(Shape::Plane(self.clone())))
+    }
+
+    pub fn local_intersect_with(
+        &self,
+        ray: Ray<T>,
+        object: impl FnOnce() -> ShapeRc<T>,
+    ) -> Vec<Intersection<T>> {
         if abs_diff_eq!(ray.direction.y, T::zero()) {
             Vec::new()
         } else {
             vec![Intersection::new(
                 -ray.origin.y / ray.direction.y,
-                Shape::Plane(self.clone()),
+                object(),
                 None,
             )]
         }
@@ -52,7 +62,7 @@ impl<T: BaseFloat> Plane<T> {
 
 mod tests {
     use super::*;
-    use cgmath::{assert_relative_eq, EuclideanSpace};
+    use cgmath::EuclideanSpace;
 
     #[test]
     fn local_intersect() {
@@ -0,0 +1,202 @@
+use crate::{
+    bounds::Bounds,
+    intersection::{intersections, Intersection},
+    material::Material,
+    ray::Ray,
+    shape::{get_rc, Shape, ShapeRc, ShapeWeak},
+};
+use cgmath::{abs_diff_eq, BaseFloat, Matrix4, Point3, SquareMatrix, Vector3};
+
+// The surface Ax² + By² + Cz² + Dxy + Exz + Fyz + Gx + Hy + Iz + J = 0, which covers
+// ellipsoids, paraboloids, hyperboloids and more.
+#[derive(Clone, derive_more::Constructor, Debug, derivative::Derivative)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derivative(PartialEq)]
+pub struct Quadric<T> {
+    pub transform: Matrix4<T>,
+    pub material: Material<T>,
+    // A through J, in the order above.
+    pub coefficients: [T; 10],
+    #[cfg_attr(feature = "serde", serde(skip, default = "Option::default"))]
+    #[derivative(PartialEq = "ignore")]
+    pub parent: Option<ShapeWeak<T>>,
+}
+
+impl<T: BaseFloat + Default> Default for Quadric<T> {
+    // The unit sphere.
+    fn default() -> Quadric<T> {
+        let (zero, one) = (T::zero(), T::one());
+        Quadric::<T> {
+            transform: Matrix4::identity(),
+            material: Material::default(),
+            coefficients: [one, one, one, zero, zero, zero, zero, zero, zero, -one],
+            parent: None,
+        }
+    }
+}
+
+impl<T: BaseFloat> Quadric<T> {
+    // Most quadrics are unbounded, so treat them all as filling space, with bounds small
+    // enough that transforming them cannot overflow into infinities, as for cones.
+    pub fn bounds(&self) -> Bounds<T> {
+        let limit = T::max_value().sqrt();
+        Bounds::new(
+            Point3::new(-limit, -limit, -limit),
+            Point3::new(limit, limit, limit),
+        )
+    }
+
+    pub fn local_intersect(&self, ray: Ray<T>) -> Vec<Intersection<T>> {
+        self.local_intersect_with(ray, || get_rc(Shape::Quadric(self.clone())))
+    }
+
+    pub fn local_intersect_with(
+        &self,
+        ray: Ray<T>,
+        object: impl FnOnce() -> ShapeRc<T>,
+    ) -> Vec<Intersection<T>> {
+        let [a, b, c, d, e, f, g, h, i, j] = self.coefficients;
+        let (o, v) = (ray.origin, ray.direction);
+        let two = T::from(2).unwrap();
+        // The surface equation along the ray is qa t² + qb t + qc = 0.
+        let qa = a * v.x * v.x
+            + b * v.y * v.y
+            + c * v.z * v.z
+            + d * v.x * v.y
+            + e * v.x * v.z
+            + f * v.y * v.z;
+        let qb = two * (a * o.x * v.x + b * o.y * v.y + c * o.z * v.z)
+            + d * (o.x * v.y + o.y * v.x)
+            + e * (o.x * v.z + o.z * v.x)
+            + f * (o.y * v.z + o.z * v.y)
+            + g * v.x
+            + h * v.y
+            + i * v.z;
+        let qc = a * o.x * o.x
+            + b * o.y * o.y
+            + c * o.z * o.z
+            + d * o.x * o.y
+            + e * o.x * o.z
+            + f * o.y * o.z
+            + g * o.x
+            + h * o.y
+            + i * o.z
+            + j;
+        let ts = if abs_diff_eq!(qa, T::zero()) {
+            if abs_diff_eq!(qb, T::zero()) {
+                vec![]
+            } else {
+                vec![-qc / qb]
+            }
+        } else {
+            let disc = qb.powi(2) - T::from(4).unwrap() * qa * qc;
+            if disc < T::zero() {
+                vec![]
+            } else {
+                let (t0, t1) = (
+                    (-qb - disc.sqrt()) / (two * qa),
+                    (-qb + disc.sqrt()) / (two * qa),
+                );
+                vec![t0.min(t1), t0.max(t1)]
+            }
+        };
+        intersections(ts, object)
+    }
+
+    // The gradient of the surface equation.
+    pub fn local_normal_at(&self, point: Point3<T>) -> Vector3<T> {
+        let [a, b, c, d, e, f, g, h, i, _] = self.coefficients;
+        let (x, y, z) = (point.x, point.y, point.z);
+        let two = T::from(2).unwrap();
+        Vector3::new(
+            two * a * x + d * y + e * z + g,
+            two * b * y + d * x + f * z + h,
+            two * c * z + e * x + f * y + i,
+        )
+    }
+}
+
+mod tests {
+    use super::*;
+    use crate::shape::{
+        get_rc,
+        group::{push, push_link, Group},
+        sphere::Sphere,
+    };
+    use cgmath::{assert_relative_eq, InnerSpace, Rad};
+    use std::f32::consts::PI;
+
+    #[test]
+    fn local_intersect() {
+        let quadric = Quadric::<f32>::default();
+        let sphere = Sphere::default();
+        for ray in [
+            Ray::new(Point3::new(0., 0., -5.), Vector3::unit_z()),
+            Ray::new(Point3::new(0., 1., -5.), Vector3::unit_z()),
+            Ray::new(Point3::new(0., 2., -5.), Vector3::unit_z()),
+            Ray::new(Point3::new(0., 0., 0.), Vector3::unit_z()),
+            Ray::new(Point3::new(0.5, -3., 0.2), Vector3::new(0.1, 1., -0.2)),
+        ] {
+            let ts = |xs: Vec<Intersection<f32>>| xs.iter().map(|i| i.t).collect::<Vec<_>>();
+            let (expected, actual) = (
+                ts(sphere.local_intersect(ray)),
+                ts(quadric.local_intersect(ray)),
+            );
+            // Sphere reports a single root for a tangent ray.
+            let actual = if expected.len() == 1 {
+                actual[..1].to_vec()
+            } else {
+                actual
+            };
+            assert_eq!(actual.len(), expected.len());
+            for (a, e) in actual.into_iter().zip(expected) {
+                assert_relative_eq!(a, e, max_relative = 0.0001);
+            }
+        }
+        // A paraboloid y = x² + z² opening upwards is crossed once by a vertical ray.
+        let paraboloid = Quadric::new(
+            Matrix4::identity(),
+            Material::default(),
+            [1., 0., 1., 0., 0., 0., 0., -1., 0., 0.],
+            None,
+        );
+        let xs = paraboloid.local_intersect(Ray::new(Point3::new(1., 5., 0.), -Vector3::unit_y()));
+        assert_eq!(xs.len(), 1);
+        assert_relative_eq!(xs[0].t, 4.);
+    }
+
+    #[test]
+    fn local_normal_at() {
+        let quadric = Quadric::<f32>::default();
+        let point = Point3::new(0.6, 0., 0.8);
+        assert_relative_eq!(
+            quadric.local_normal_at(point).normalize(),
+            Sphere::default().local_normal_at(point)
+        );
+    }
+
+    #[test]
+    fn bounds() {
+        // The bounds survive nested rotated groups without overflowing.
+        let outer = get_rc(Shape::Group(Group::new(
+            Matrix4::from_angle_x(Rad(PI / 3.)),
+            Vec::new(),
+            false,
+            None,
+        )));
+        let inner = get_rc(Shape::Group(Group::new(
+            Matrix4::from_angle_z(Rad(PI / 4.)),
+            Vec::new(),
+            false,
+            None,
+        )));
+        push(&inner, Shape::Quadric(Quadric::default()));
+        push_link(&outer, inner);
+        let b = outer.borrow().bounds().unwrap();
+        for p in b.all_points() {
+            assert!(p.x.is_finite() && p.y.is_finite() && p.z.is_finite());
+        }
+        let ray = Ray::new(Point3::new(0., 0., -5.), Vector3::unit_z());
+        assert_eq!(outer.borrow().intersect(ray).len(), 2);
+    }
+}
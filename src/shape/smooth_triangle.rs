@@ -3,13 +3,15 @@ use crate::{
     intersection::Intersection,
     material::Material,
     ray::Ray,
-    shape::{Shape, ShapeWeak, Triangle},
+    shape::{get_rc, triangle::intersect_edges, Shape, ShapeRc, ShapeWeak},
 };
 use cgmath::{BaseFloat, Matrix4, Point3, SquareMatrix, Vector3};
 
-#[derive(Clone, derive_more::Constructor, Debug, derivative::Derivative)]
+#[derive(Clone, Debug, derivative::Derivative)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derivative(PartialEq)]
 pub struct SmoothTriangle<T> {
+    pub transform: Matrix4<T>,
     pub material: Material<T>,
     pub p1: Point3<T>,
     pub p2: Point3<T>,
@@ -17,30 +19,91 @@ pub struct SmoothTriangle<T> {
     pub n1: Vector3<T>,
     pub n2: Vector3<T>,
     pub n3: Vector3<T>,
+    // The edges from p1, kept so that intersecting does not recompute them. They are worked out
+    // by new and from, so build a new triangle rather than moving the points of this one.
+    e1: Vector3<T>,
+    e2: Vector3<T>,
+    // Texture coordinates at p1, p2 and p3, as given by the vt lines of an OBJ file.
+    pub texture_coords: Option<[(T, T); 3]>,
+    #[cfg_attr(feature = "serde", serde(skip, default = "Option::default"))]
     #[derivative(PartialEq = "ignore")]
     pub parent: Option<ShapeWeak<T>>,
 }
 
 impl<T: BaseFloat> SmoothTriangle<T> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        material: Material<T>,
+        p1: Point3<T>,
+        p2: Point3<T>,
+        p3: Point3<T>,
+        n1: Vector3<T>,
+        n2: Vector3<T>,
+        n3: Vector3<T>,
+        parent: Option<ShapeWeak<T>>,
+    ) -> SmoothTriangle<T> {
+        SmoothTriangle {
+            material,
+            parent,
+            ..SmoothTriangle::from(p1, p2, p3, n1, n2, n3)
+        }
+    }
+
+    pub fn from(
+        p1: Point3<T>,
+        p2: Point3<T>,
+        p3: Point3<T>,
+        n1: Vector3<T>,
+        n2: Vector3<T>,
+        n3: Vector3<T>,
+    ) -> SmoothTriangle<T> {
+        SmoothTriangle {
+            transform: Matrix4::identity(),
+            material: Material::default(),
+            p1,
+            p2,
+            p3,
+            n1,
+            n2,
+            n3,
+            e1: p2 - p1,
+            e2: p3 - p1,
+            texture_coords: None,
+            parent: None,
+        }
+    }
+
     pub fn bounds(&self) -> Bounds<T> {
         Bounds::from_all_points(&[self.p1, self.p2, self.p3]).unwrap()
     }
 
     pub fn local_intersect(&self, ray: Ray<T>) -> Vec<Intersection<T>> {
-        Triangle::from(self.p1, self.p2, self.p3)
-            .local_intersect(ray)
-            .iter()
-            .map(|i| Intersection::new(i.t, Shape::SmoothTriangle(self.clone()), i.uv))
-            .collect::<Vec<_>>()
+        self.local_intersect_with(ray, || get_rc(Shape::SmoothTriangle(self.clone())))
+    }
+
+    pub fn local_intersect_with(
+        &self,
+        ray: Ray<T>,
+        object: impl FnOnce() -> ShapeRc<T>,
+    ) -> Vec<Intersection<T>> {
+        match intersect_edges(self.p1, self.e1, self.e2, ray) {
+            Some((t, uv)) => vec![Intersection::new(t, object(), Some(uv))],
+            None => vec![],
+        }
     }
 
     pub fn local_normal_at(&self, _point: Point3<T>, uv: Option<(T, T)>) -> Vector3<T> {
         let (u, v) = uv.unwrap();
         self.n2 * u + self.n3 * v + self.n1 * (T::one() - u - v)
     }
+
+    pub fn local_uv_derivatives(&self) -> (Vector3<T>, Vector3<T>) {
+        (self.e1, self.e2)
+    }
 }
 mod tests {
     use super::*;
+    use crate::shape::triangle::Triangle;
     use cgmath::{assert_relative_eq, EuclideanSpace};
 
     #[test]
@@ -63,15 +126,13 @@ mod tests {
 
     #[test]
     fn normal_at() {
-        let tri = SmoothTriangle::new(
-            Material::default(),
+        let tri = SmoothTriangle::from(
             Point3::new(0., 1., 0.),
             Point3::new(-1., 0., 0.),
             Point3::new(1., 0., 0.),
             Vector3::unit_y(),
             -Vector3::unit_x(),
             Vector3::unit_x(),
-            None,
         );
         assert_relative_eq!(
             Shape::SmoothTriangle(tri)
@@ -86,15 +147,13 @@ mod tests {
     fn precompute() {
         let i = Intersection::new(
             1.,
-            Shape::SmoothTriangle(SmoothTriangle::new(
-                Material::default(),
+            Shape::SmoothTriangle(SmoothTriangle::from(
                 Point3::new(0., 1., 0.),
                 Point3::new(-1., 0., 0.),
                 Point3::new(1., 0., 0.),
                 Vector3::unit_y(),
                 -Vector3::unit_x(),
                 Vector3::unit_x(),
-                None,
             )),
             Some((0.45, 0.25)),
         );
@@ -105,4 +164,66 @@ mod tests {
             max_relative = 0.00001
         );
     }
+
+    #[test]
+    fn transform() {
+        let tri = Shape::SmoothTriangle(SmoothTriangle::from(
+            Point3::new(0., 1., 0.),
+            Point3::new(-1., 0., 0.),
+            Point3::new(1., 0., 0.),
+            Vector3::unit_y(),
+            -Vector3::unit_x(),
+            Vector3::unit_x(),
+        ))
+        .rotate_y(std::f32::consts::PI)
+        .translate(0., 0., 5.);
+        let xs = tri.intersect(Ray::new(Point3::new(0.2, 0.3, -2.), Vector3::unit_z()));
+        assert_eq!(xs.len(), 1);
+        assert_relative_eq!(xs[0].t, 7.);
+        let (u, v) = xs[0].uv.unwrap();
+        assert_relative_eq!(u, 0.45);
+        assert_relative_eq!(v, 0.25);
+        assert_relative_eq!(
+            tri.normal_at(Point3::new(0.2, 0.3, 5.), xs[0].uv).unwrap(),
+            Vector3::new(0.5547, 0.83205, 0.),
+            max_relative = 0.0001
+        );
+    }
+
+    #[test]
+    fn matches_triangle() {
+        let (p1, p2, p3) = (
+            Point3::new(0., 1., 0.),
+            Point3::new(-1., 0., 0.),
+            Point3::new(1., 0., 0.),
+        );
+        let tri = SmoothTriangle::from(
+            p1,
+            p2,
+            p3,
+            Vector3::unit_y(),
+            -Vector3::unit_x(),
+            Vector3::unit_x(),
+        );
+        let flat = Triangle::from(p1, p2, p3);
+        assert_eq!((tri.e1, tri.e2), (flat.e1, flat.e2));
+        for ray in [
+            Ray::new(Point3::new(-0.2, 0.3, -2.), Vector3::unit_z()),
+            Ray::new(Point3::new(0.4, 0.1, 3.), -Vector3::unit_z()),
+            Ray::new(Point3::new(1., 1., -2.), Vector3::unit_z()),
+            Ray::new(Point3::new(0., -1., -2.), Vector3::unit_y()),
+        ] {
+            let expected = flat
+                .local_intersect(ray)
+                .iter()
+                .map(|i| (i.t, i.uv))
+                .collect::<Vec<_>>();
+            let actual = tri
+                .local_intersect(ray)
+                .iter()
+                .map(|i| (i.t, i.uv))
+                .collect::<Vec<_>>();
+            assert_eq!(actual, expected);
+        }
+    }
 }
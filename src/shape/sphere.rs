@@ -3,15 +3,19 @@ use crate::{
     intersection::Intersection,
     material::Material,
     ray::Ray,
-    shape::{Shape, ShapeWeak},
+    scalar::Scalar,
+    shape::{get_rc, Shape, ShapeRc, ShapeWeak},
 };
 use cgmath::{BaseFloat, EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3};
+use std::rc::Rc;
 
 #[derive(Clone, derive_more::Constructor, Debug, derivative::Derivative)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derivative(PartialEq)]
 pub struct Sphere<T> {
     pub transform: Matrix4<T>,
     pub material: Material<T>,
+    #[cfg_attr(feature = "serde", serde(skip, default = "Option::default"))]
     #[derivative(PartialEq = "ignore")]
     pub parent: Option<ShapeWeak<T>>,
 }
@@ -26,6 +30,44 @@ impl<T: BaseFloat + Default> Default for Sphere<T> {
     }
 }
 
+impl<T: BaseFloat + Default> Sphere<T> {
+    // The book's glass_sphere, a unit sphere made of Material::glass.
+    pub fn glass() -> Sphere<T> {
+        Sphere {
+            material: Material::glass(),
+            ..Sphere::default()
+        }
+    }
+}
+
+// Map a point on the unit sphere to (u, v) in [0, 1), with u running around the y axis.
+pub fn spherical_uv<T: BaseFloat>(point: Point3<T>) -> (T, T) {
+    let two_pi = T::from(2. * std::f64::consts::PI).unwrap();
+    let theta = point.x.atan2(point.z);
+    let radius = point.to_vec().magnitude();
+    let phi = (point.y / radius).acos();
+    let u = T::one() - (theta / two_pi + T::from(0.5).unwrap());
+    let v = T::one() - phi / T::from(std::f64::consts::PI).unwrap();
+    (u, v)
+}
+
+// The discriminant and both roots for a ray against the unit sphere; the roots are only
+// meaningful where the discriminant is not negative.
+pub fn unit_sphere_roots<T: Scalar>(ray: Ray<T>) -> (T, T, T) {
+    let (o, d) = (ray.origin, ray.direction);
+    let a = d.x * d.x + d.y * d.y + d.z * d.z;
+    let two = T::splat(2.);
+    let b = (d.x * o.x + d.y * o.y + d.z * o.z) * two;
+    let c = o.x * o.x + o.y * o.y + o.z * o.z - T::splat(1.);
+    let discriminant = b * b - T::splat(4.) * a * c;
+    let root = discriminant.simd_max(T::splat(0.)).simd_sqrt();
+    (
+        discriminant,
+        (-b - root) / (two * a),
+        (-b + root) / (two * a),
+    )
+}
+
 impl<T: BaseFloat> Sphere<T> {
     pub fn bounds(&self) -> Bounds<T> {
         let one = T::one();
@@ -35,43 +77,53 @@ impl<T: BaseFloat> Sphere<T> {
     }
 
     pub fn local_intersect(&self, ray: Ray<T>) -> Vec<Intersection<T>> {
-        let sphere_to_ray = ray.origin.to_vec();
-        let a = ray.direction.dot(ray.direction);
-        let two = T::from(2).unwrap();
-        let b = ray.direction.dot(sphere_to_ray) * two;
-        let c = sphere_to_ray.dot(sphere_to_ray) - T::one();
-        let discriminant = b.powi(2) - T::from(4).unwrap() * a * c;
-        match discriminant {
-            d if d > T::zero() => vec![
-                Intersection::new(
-                    (-b - d.sqrt()) / (two * a),
-                    Shape::Sphere(self.clone()),
-                    None,
-                ),
-                Intersection::new(
-                    (-b + d.sqrt()) / (two * a),
-                    Shape::Sphere(self.clone()),
-                    None,
-                ),
-            ],
-            d if d == T::zero() => vec![Intersection::new(
-                -b / (two * a),
-                Shape::Sphere(self.clone()),
-                None,
-            )],
-            _ => vec![],
-        }
+        self.local_intersect_with(ray, || get_rc(Shape::Sphere(self.clone())))
+    }
+
+    pub fn local_intersect_with(
+        &self,
+        ray: Ray<T>,
+        object: impl FnOnce() -> ShapeRc<T>,
+    ) -> Vec<Intersection<T>> {
+        let (discriminant, t0, t1) = unit_sphere_roots(ray);
+        let ts = match discriminant {
+            d if d > T::zero() => vec![t0, t1],
+            d if d == T::zero() => vec![t0],
+            _ => return vec![],
+        };
+        let object = object();
+        ts.into_iter()
+            .map(|t| Intersection::new(t, object.clone(), Some(spherical_uv(ray.position(t)))))
+            .collect()
     }
 
     pub fn local_normal_at(&self, point: Point3<T>) -> Vector3<T> {
         point.to_vec()
     }
+
+    // The directions the point moves in as u and v of spherical_uv grow, both zero at the poles.
+    pub fn local_uv_derivatives(&self, point: Point3<T>) -> (Vector3<T>, Vector3<T>) {
+        let ring = point.x.powi(2) + point.z.powi(2);
+        (
+            Vector3::new(-point.z, T::zero(), point.x),
+            Vector3::new(-point.x * point.y, ring, -point.z * point.y),
+        )
+    }
 }
 
 mod tests {
     use super::*;
     use cgmath::assert_relative_eq;
 
+    #[test]
+    fn glass() {
+        let sphere = Sphere::<f64>::glass();
+        assert_eq!(sphere.transform, Matrix4::identity());
+        assert_eq!(sphere.material.transparency, 1.);
+        assert_eq!(sphere.material.refractive_index, 1.5);
+        assert_eq!(sphere.material.reflective, 0.);
+    }
+
     #[test]
     fn local_normal_at() {
         {
@@ -94,4 +146,65 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn local_intersect() {
+        let xs = Sphere::default()
+            .local_intersect(Ray::new(Point3::new(5., 0., 0.), -Vector3::unit_x()));
+        assert_eq!(xs.len(), 2);
+        assert_relative_eq!(xs[0].t, 4.);
+        let (u, v) = xs[0].uv.unwrap();
+        assert_relative_eq!(u, 0.25);
+        assert_relative_eq!(v, 0.5);
+        assert_eq!(xs[0].uv, Some(spherical_uv(Point3::new(1., 0., 0.))));
+    }
+
+    #[test]
+    fn unit_sphere_roots() {
+        // The roots as computed before the arithmetic was made generic over Scalar.
+        let reference = |ray: Ray<f32>| {
+            let sphere_to_ray = ray.origin.to_vec();
+            let a = ray.direction.dot(ray.direction);
+            let b = ray.direction.dot(sphere_to_ray) * 2.;
+            let c = sphere_to_ray.dot(sphere_to_ray) - 1.;
+            let d = b.powi(2) - 4. * a * c;
+            (d, (-b - d.sqrt()) / (2. * a), (-b + d.sqrt()) / (2. * a))
+        };
+        for ray in [
+            Ray::new(Point3::new(0., 0., -5.), Vector3::unit_z()),
+            Ray::new(Point3::new(0.3, -0.2, 0.1), Vector3::new(0.2, 1., -0.7)),
+            Ray::new(Point3::new(5., 1., 0.), -Vector3::unit_x()),
+            Ray::new(Point3::new(1.5, 4., -3.), Vector3::new(-0.1, -0.8, 0.6)),
+        ] {
+            let (d, t0, t1) = super::unit_sphere_roots(ray);
+            let (rd, r0, r1) = reference(ray);
+            assert_eq!(d, rd);
+            if d >= 0. {
+                assert_eq!((t0, t1), (r0, r1));
+            }
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        use crate::pattern::{stripe::Stripe, Pattern};
+        use rgb::RGB;
+        let sphere = Shape::Sphere(Sphere {
+            transform: Matrix4::from_translation(Vector3::new(1., 2., 3.))
+                * Matrix4::from_scale(2.),
+            material: Material {
+                pattern: Pattern::Stripe(Stripe::new(
+                    RGB::new(1., 0.5, 0.),
+                    RGB::new(0., 0.2, 1.),
+                    Matrix4::from_angle_z(cgmath::Deg(30.)),
+                )),
+                reflective: 0.3,
+                ..Material::default()
+            },
+            ..Sphere::default()
+        });
+        let json = serde_json::to_string(&sphere).unwrap();
+        assert_eq!(serde_json::from_str::<Shape<f64>>(&json).unwrap(), sphere);
+    }
 }
@@ -0,0 +1,231 @@
+use crate::{
+    bounds::Bounds,
+    intersection::{intersections, Intersection},
+    material::Material,
+    ray::Ray,
+    shape::{get_rc, Shape, ShapeRc, ShapeWeak},
+};
+use cgmath::{
+    Array, BaseFloat, EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3,
+};
+
+// A ring around the y axis: a tube of minor_radius swept along a circle of major_radius.
+#[derive(Clone, derive_more::Constructor, Debug, derivative::Derivative)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derivative(PartialEq)]
+pub struct Torus<T> {
+    pub transform: Matrix4<T>,
+    pub material: Material<T>,
+    pub major_radius: T,
+    pub minor_radius: T,
+    #[cfg_attr(feature = "serde", serde(skip, default = "Option::default"))]
+    #[derivative(PartialEq = "ignore")]
+    pub parent: Option<ShapeWeak<T>>,
+}
+
+impl<T: BaseFloat + Default> Default for Torus<T> {
+    fn default() -> Torus<T> {
+        Torus::<T> {
+            transform: Matrix4::identity(),
+            material: Material::default(),
+            major_radius: T::one(),
+            minor_radius: T::from(0.25).unwrap(),
+            parent: None,
+        }
+    }
+}
+
+const BISECTIONS: usize = 64;
+
+// The polynomial with the coefficients, lowest power first, at x.
+fn evaluate<T: BaseFloat>(coefficients: &[T], x: T) -> T {
+    coefficients
+        .iter()
+        .rev()
+        .fold(T::zero(), |sum, &c| sum * x + c)
+}
+
+fn derivative<T: BaseFloat>(coefficients: &[T]) -> Vec<T> {
+    coefficients
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(power, &c)| c * T::from(power).unwrap())
+        .collect()
+}
+
+// The real roots of the polynomial within [lo, hi] in order, a root it only touches given twice.
+// Between neighbouring roots of its derivative the polynomial is monotonic, so each piece holds
+// at most one root, which bisection is then sure to find.
+fn roots_between<T: BaseFloat>(coefficients: &[T], lo: T, hi: T) -> Vec<T> {
+    if coefficients.len() < 3 {
+        let root = match coefficients {
+            [c0, c1] if *c1 != T::zero() => -*c0 / *c1,
+            _ => return vec![],
+        };
+        return if lo <= root && root <= hi {
+            vec![root]
+        } else {
+            vec![]
+        };
+    }
+    let f = |x| evaluate(coefficients, x);
+    // Horner's rule is off by at most about 2n machine epsilons of the sum of the terms, for
+    // degree n, so a value that small is as good as zero.
+    let tolerance = |x: T| {
+        let terms = coefficients
+            .iter()
+            .rev()
+            .fold(T::zero(), |sum, &c| sum * x.abs() + c.abs());
+        terms * T::epsilon() * T::from(2 * (coefficients.len() - 1)).unwrap()
+    };
+    let mut points = vec![lo];
+    points.extend(roots_between(&derivative(coefficients), lo, hi));
+    points.push(hi);
+    points.dedup();
+    let touches =
+        |i: usize| 0 < i && i < points.len() - 1 && f(points[i]).abs() <= tolerance(points[i]);
+    let mut roots = Vec::new();
+    for i in 1..points.len() {
+        let (a, b) = (points[i - 1], points[i]);
+        let below = f(a) < T::zero();
+        if !touches(i - 1) && !touches(i) && below != (f(b) < T::zero()) {
+            let (mut a, mut b) = (a, b);
+            for _ in 0..BISECTIONS {
+                let mid = (a + b) / T::from(2).unwrap();
+                if (f(mid) < T::zero()) == below {
+                    a = mid;
+                } else {
+                    b = mid;
+                }
+            }
+            roots.push((a + b) / T::from(2).unwrap());
+        }
+        if touches(i) {
+            roots.extend([b, b]);
+        }
+    }
+    roots
+}
+
+impl<T: BaseFloat> Torus<T> {
+    pub fn bounds(&self) -> Bounds<T> {
+        let (outer, r) = (self.major_radius + self.minor_radius, self.minor_radius);
+        Bounds::new(
+            Point3::new(-outer, -r, -outer),
+            Point3::new(outer, r, outer),
+        )
+    }
+
+    // The ray meets the tube where a quartic in t is zero. Its roots are isolated exactly within
+    // the bounding box, with t measured from where the ray enters it to keep the terms small.
+    pub fn local_intersect(&self, ray: Ray<T>) -> Vec<Intersection<T>> {
+        self.local_intersect_with(ray, || get_rc(Shape::Torus(self.clone())))
+    }
+
+    pub fn local_intersect_with(
+        &self,
+        ray: Ray<T>,
+        object: impl FnOnce() -> ShapeRc<T>,
+    ) -> Vec<Intersection<T>> {
+        // Padded so that rays running along a face of the box, which can only touch the tube,
+        // are not lost to 0 / 0 in the slab test.
+        let b = self.bounds();
+        let pad = Vector3::from_value(self.minor_radius * T::epsilon().sqrt());
+        let (tmin, tmax) = Bounds::new(b.minimum - pad, b.maximum + pad)
+            .check_axes(ray)
+            .minmax();
+        if tmin > tmax || !tmin.is_finite() || !tmax.is_finite() {
+            return vec![];
+        }
+        let (o, d) = (ray.position(tmin).to_vec(), ray.direction);
+        let (two, four) = (T::from(2).unwrap(), T::from(4).unwrap());
+        let r2 = self.major_radius.powi(2);
+        let (dd, od) = (d.magnitude2(), o.dot(d));
+        let k = o.magnitude2() + r2 - self.minor_radius.powi(2);
+        let quartic = [
+            k.powi(2) - four * r2 * (o.x.powi(2) + o.z.powi(2)),
+            four * od * k - two * four * r2 * (o.x * d.x + o.z * d.z),
+            four * od.powi(2) + two * dd * k - four * r2 * (d.x.powi(2) + d.z.powi(2)),
+            four * dd * od,
+            dd.powi(2),
+        ];
+        let ts = roots_between(&quartic, T::zero(), tmax - tmin)
+            .into_iter()
+            .map(|t| tmin + t);
+        intersections(ts, object)
+    }
+
+    pub fn local_normal_at(&self, point: Point3<T>) -> Vector3<T> {
+        let r2 = self.major_radius.powi(2);
+        let s = point.to_vec().magnitude2() + r2 - self.minor_radius.powi(2);
+        let radial = s - T::from(2).unwrap() * r2;
+        Vector3::new(point.x * radial, point.y * s, point.z * radial)
+    }
+}
+
+mod tests {
+    use super::*;
+    use cgmath::assert_relative_eq;
+
+    #[test]
+    fn local_intersect() {
+        let torus = Torus::<f32>::default();
+        // Straight down through the hole.
+        assert_eq!(
+            torus.local_intersect(Ray::new(Point3::new(0., 5., 0.), -Vector3::unit_y())),
+            vec![]
+        );
+        // Straight down through the tube.
+        let xs = torus.local_intersect(Ray::new(Point3::new(1., 5., 0.), -Vector3::unit_y()));
+        assert_eq!(xs.len(), 2);
+        assert_relative_eq!(xs[0].t, 4.75, max_relative = 0.0001);
+        assert_relative_eq!(xs[1].t, 5.25, max_relative = 0.0001);
+        // Along the x axis through both sides of the ring.
+        let xs = torus.local_intersect(Ray::new(Point3::new(-5., 0., 0.), Vector3::unit_x()));
+        let ts = xs.iter().map(|i| i.t).collect::<Vec<_>>();
+        assert_eq!(ts.len(), 4);
+        for (t, expected) in ts.into_iter().zip([3.75, 4.25, 5.75, 6.25]) {
+            assert_relative_eq!(t, expected, max_relative = 0.0001);
+        }
+        // Passing outside the ring.
+        assert_eq!(
+            torus.local_intersect(Ray::new(Point3::new(-5., 0.5, 0.), Vector3::unit_x())),
+            vec![]
+        );
+        // Grazing the top of the tube, where each pass is far shorter than the tube is wide.
+        let d = Vector3::new(1., 0., 1.).normalize();
+        let xs =
+            Torus::<f64>::default().local_intersect(Ray::new(Point3::new(-5., 0.2499, -5.), d));
+        let ts = xs.iter().map(|i| i.t).collect::<Vec<_>>();
+        assert_eq!(ts.len(), 4);
+        let (center, half) = (
+            50_f64.sqrt(),
+            (0.25_f64.powi(2) - 0.2499_f64.powi(2)).sqrt(),
+        );
+        let expected = [-1. - half, -1. + half, 1. - half, 1. + half].map(|x| center + x);
+        for (t, expected) in ts.into_iter().zip(expected) {
+            assert_relative_eq!(t, expected, max_relative = 1e-9);
+        }
+        // Touching it, which counts as entering and leaving at once.
+        let xs = torus.local_intersect(Ray::new(Point3::new(-5., 0.25, 0.), Vector3::unit_x()));
+        let ts = xs.iter().map(|i| i.t).collect::<Vec<_>>();
+        assert_eq!(ts.len(), 4);
+        for (t, expected) in ts.into_iter().zip([4., 4., 6., 6.]) {
+            assert_relative_eq!(t, expected, max_relative = 0.001);
+        }
+    }
+
+    #[test]
+    fn local_normal_at() {
+        let torus = Torus::<f32>::default();
+        for (point, normal) in [
+            (Point3::new(1.25, 0., 0.), Vector3::unit_x()),
+            (Point3::new(0.75, 0., 0.), -Vector3::unit_x()),
+            (Point3::new(0., 0.25, 1.), Vector3::unit_y()),
+            (Point3::new(0., 0., -1.25), -Vector3::unit_z()),
+        ] {
+            assert_relative_eq!(torus.local_normal_at(point).normalize(), normal);
+        }
+    }
+}
@@ -3,13 +3,12 @@ use crate::{
     intersection::Intersection,
     material::Material,
     ray::Ray,
-    shape::{Shape, ShapeWeak},
-};
-use cgmath::{
-    abs_diff_ne, BaseFloat, EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3,
+    shape::{face_normal, get_rc, Shape, ShapeRc, ShapeWeak},
 };
+use cgmath::{BaseFloat, EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3};
 
 #[derive(Clone, derive_more::Constructor, Debug, derivative::Derivative)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derivative(PartialEq)]
 pub struct Triangle<T> {
     pub transform: Matrix4<T>,
@@ -20,6 +19,14 @@ pub struct Triangle<T> {
     pub e1: Vector3<T>,
     pub e2: Vector3<T>,
     pub normal: Vector3<T>,
+    // Texture coordinates at p1, p2 and p3, as given by the vt lines of an OBJ file.
+    pub texture_coords: Option<[(T, T); 3]>,
+    // Normals at p1, p2 and p3 averaged over the faces of its OBJ group sharing them, set by
+    // the parser for smooth shading. They shade the triangle without changing its shape, so
+    // equality ignores them.
+    #[derivative(PartialEq = "ignore")]
+    pub vertex_normals: Option<[Vector3<T>; 3]>,
+    #[cfg_attr(feature = "serde", serde(skip, default = "Option::default"))]
     #[derivative(PartialEq = "ignore")]
     pub parent: Option<ShapeWeak<T>>,
 }
@@ -30,6 +37,33 @@ impl<T: BaseFloat> Default for Triangle<T> {
     }
 }
 
+// Möller–Trumbore: the t and (u, v) where the ray crosses the triangle at p1 spanned by the
+// edges e1 and e2, if it does.
+pub(crate) fn intersect_edges<T: BaseFloat>(
+    p1: Point3<T>,
+    e1: Vector3<T>,
+    e2: Vector3<T>,
+    ray: Ray<T>,
+) -> Option<(T, (T, T))> {
+    let dir_cross_e2 = ray.direction.cross(e2);
+    let det = e1.dot(dir_cross_e2);
+    if det.abs() < T::epsilon() {
+        return None;
+    }
+    let f = T::one() / det;
+    let p1_to_origin = ray.origin - p1;
+    let u = f * p1_to_origin.dot(dir_cross_e2);
+    if !(T::zero()..=T::one()).contains(&u) {
+        return None;
+    }
+    let origin_cross_e1 = p1_to_origin.cross(e1);
+    let v = f * ray.direction.dot(origin_cross_e1);
+    if !(v >= T::zero() && u + v <= T::one()) {
+        return None;
+    }
+    Some((f * e2.dot(origin_cross_e1), (u, v)))
+}
+
 impl<T: BaseFloat> Triangle<T> {
     pub fn from(p1: Point3<T>, p2: Point3<T>, p3: Point3<T>) -> Triangle<T> {
         let (e1, e2) = (p2 - p1, p3 - p1);
@@ -41,7 +75,9 @@ impl<T: BaseFloat> Triangle<T> {
             p3,
             e1,
             e2,
-            normal: e2.cross(e1).normalize(),
+            normal: face_normal(p1, p2, p3),
+            texture_coords: None,
+            vertex_normals: None,
             parent: None,
         }
     }
@@ -53,35 +89,42 @@ impl<T: BaseFloat> Triangle<T> {
     }
 
     pub fn local_intersect(&self, ray: Ray<T>) -> Vec<Intersection<T>> {
-        let dir_cross_e2 = ray.direction.cross(self.e2);
-        let det = self.e1.dot(dir_cross_e2);
-        if abs_diff_ne!(det, T::epsilon()) {
-            let f = T::one() / det;
-            let p1_to_origin = ray.origin - self.p1;
-            let u = f * p1_to_origin.dot(dir_cross_e2);
-            if T::zero() <= u && u <= T::one() {
-                let origin_cross_e1 = p1_to_origin.cross(self.e1);
-                let v = f * ray.direction.dot(origin_cross_e1);
-                if v >= T::zero() && (u + v) <= T::one() {
-                    let t = f * self.e2.dot(origin_cross_e1);
-                    return vec![Intersection::new(
-                        t,
-                        Shape::Triangle(self.clone()),
-                        Some((u, v)),
-                    )];
-                }
-            }
+        self.local_intersect_with(ray, || get_rc(Shape::Triangle(self.clone())))
+    }
+
+    pub fn local_intersect_with(
+        &self,
+        ray: Ray<T>,
+        object: impl FnOnce() -> ShapeRc<T>,
+    ) -> Vec<Intersection<T>> {
+        match intersect_edges(self.p1, self.e1, self.e2, ray) {
+            Some((t, uv)) => vec![Intersection::new(t, object(), Some(uv))],
+            None => vec![],
         }
-        vec![]
     }
 
     pub fn local_normal_at(&self, _point: Point3<T>) -> Vector3<T> {
         self.normal
     }
+
+    // The hit lies at p1 + u * e1 + v * e2.
+    pub fn local_uv_derivatives(&self) -> (Vector3<T>, Vector3<T>) {
+        (self.e1, self.e2)
+    }
+
+    // Blend the normals at p1, p2 and p3 so a faceted mesh can shade smoothly.
+    pub fn interpolated_normal(&self, [n1, n2, n3]: [Vector3<T>; 3], (u, v): (T, T)) -> Vector3<T> {
+        (n2 * u + n3 * v + n1 * (T::one() - u - v)).normalize()
+    }
 }
 
 mod tests {
     use super::*;
+    use crate::shape::{
+        get_rc,
+        group::{push, Group},
+        smooth_triangle::SmoothTriangle,
+    };
     use cgmath::assert_relative_eq;
 
     #[test]
@@ -125,4 +168,77 @@ mod tests {
         assert_eq!(t.local_normal_at(Point3::new(-0.5, 0.75, 0.)), t.normal);
         assert_eq!(t.local_normal_at(Point3::new(0.5, 0.25, 0.)), t.normal);
     }
+
+    #[test]
+    fn normal() {
+        let t = Triangle::from(
+            Point3::new(0., 1., 0.),
+            Point3::new(-1., 0., 0.),
+            Point3::new(1., 0., 0.),
+        );
+        // Clockwise as seen from -z.
+        assert_eq!(t.normal, -Vector3::unit_z());
+        assert_eq!(t.normal, t.e2.cross(t.e1).normalize());
+    }
+
+    #[test]
+    fn parallel_ray() {
+        let t = Triangle::<f32>::from(
+            Point3::new(0., 1., 0.),
+            Point3::new(-1., 0., 0.),
+            Point3::new(1., 0., 0.),
+        );
+        for direction in [
+            Vector3::unit_y(),
+            -Vector3::unit_y(),
+            Vector3::new(0., 1., 1e-8),
+            Vector3::new(0., 1., -1e-8),
+        ] {
+            assert_eq!(
+                t.local_intersect(Ray::new(Point3::new(0., -1., -1.5e-8), direction)),
+                vec![]
+            );
+        }
+        // Smooth triangles intersect through Triangle.
+        let smooth = SmoothTriangle::from(
+            t.p1,
+            t.p2,
+            t.p3,
+            Vector3::unit_y(),
+            -Vector3::unit_x(),
+            Vector3::unit_x(),
+        );
+        let ray = Ray::new(Point3::new(0., -1., -1.5e-8), Vector3::new(0., 1., 1e-8));
+        assert_eq!(smooth.local_intersect(ray), vec![]);
+    }
+
+    #[test]
+    fn bounds() {
+        let t = Triangle::<f32>::from(
+            Point3::new(-3., 7., 2.),
+            Point3::new(6., 2., -4.),
+            Point3::new(2., -1., -1.),
+        );
+        assert_eq!(
+            t.bounds(),
+            Bounds::new(Point3::new(-3., -1., -4.), Point3::new(6., 7., 2.))
+        );
+        let rc = get_rc(Shape::Group(Group::default()));
+        push(&rc, Shape::Triangle(t));
+        push(
+            &rc,
+            Shape::Triangle(Triangle::from(
+                Point3::new(0., 0., 5.),
+                Point3::new(1., 0., 5.),
+                Point3::new(0., -2., 3.),
+            )),
+        );
+        assert_eq!(
+            rc.borrow().bounds(),
+            Some(Bounds::new(
+                Point3::new(-3., -2., -4.),
+                Point3::new(6., 7., 5.)
+            ))
+        );
+    }
 }
@@ -1,43 +1,221 @@
 use crate::{
+    bounds::Bounds,
     computation::Computation,
     intersection::{hit, Intersection},
-    light::Light,
-    material::Material,
+    light::{EnvironmentLight, Light},
+    material::{Material, MaterialLibrary},
     pattern::Pattern,
+    random::Rng,
     ray::Ray,
-    shape::{sphere::Sphere, Shape},
+    shape::{get_rc, group::VertexNormals, intersect_shared, sphere::Sphere, Shape, ShapeRc},
 };
-use cgmath::{BaseFloat, InnerSpace, Matrix4, Point3, SquareMatrix};
+use cgmath::{BaseFloat, InnerSpace, Matrix3, Matrix4, Point3, Rad, SquareMatrix, Vector3};
 use rgb::RGB;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
 
 const RECURSION_LIMIT: u8 = 5;
+const GLOSSY_SAMPLES: usize = 16;
+// Samples along each side of the grid spread over an emissive triangle.
+const AREA_LIGHT_SAMPLES: usize = 4;
+
+// Groups by address, with the group itself and the vertex normals of its triangles.
+type VertexNormalCache<T> = HashMap<*const RefCell<Shape<T>>, (ShapeRc<T>, VertexNormals<T>)>;
+
+// Spread samples evenly over the cone of the given half-angle around the unit vector axis.
+pub(crate) fn cone_samples<T: BaseFloat>(axis: Vector3<T>, angle: T, n: usize) -> Vec<Vector3<T>> {
+    let helper = if axis.x.abs() < T::from(0.9).unwrap() {
+        Vector3::unit_x()
+    } else {
+        Vector3::unit_y()
+    };
+    let u = axis.cross(helper).normalize();
+    let v = axis.cross(u);
+    let golden_angle =
+        T::from(std::f64::consts::PI).unwrap() * (T::from(3).unwrap() - T::from(5).unwrap().sqrt());
+    let count = T::from(n).unwrap();
+    (0..n)
+        .map(|k| {
+            let k = T::from(k).unwrap();
+            let fraction = (k + T::from(0.5).unwrap()) / count;
+            let cos_theta = T::one() - fraction * (T::one() - angle.cos());
+            let sin_theta = (T::one() - cos_theta.powi(2)).sqrt();
+            let phi = k * golden_angle;
+            u * (sin_theta * phi.cos()) + v * (sin_theta * phi.sin()) + axis * cos_theta
+        })
+        .collect()
+}
+
+// The direction along which the shadow ray origin is nudged off the surface.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ShadowOffset<T> {
+    Normal,
+    Light,
+    // Interpolate from the normal (0) to the light direction (1).
+    Blend(T),
+}
+
+// A likely mistake in a scene, pointing at the index of the offending top-level object.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Warning {
+    SingularTransform(usize),
+    // Diffuse, reflective and transparent parts add up to more than the incoming light.
+    ExcessEnergy(usize),
+    LightInsideOpaqueObject(usize),
+    DegenerateTriangle(usize),
+}
+
+// Exponential fog that hides surfaces the further they are from the ray origin.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fog<T> {
+    pub color: RGB<T>,
+    pub density: T,
+}
+
+// How a ray in a traced path came about.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RayKind {
+    Camera,
+    Reflected,
+    Refracted,
+}
+
+// One hit along a traced path, with the surface color lit at that point before any bounces.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PathVertex<T> {
+    pub point: Point3<T>,
+    pub object: Shape<T>,
+    pub color: RGB<T>,
+    pub kind: RayKind,
+}
 
-#[derive(derive_more::Constructor, Debug)]
+// How many more reflected and refracted rays a ray may still spawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Remaining {
+    reflections: u8,
+    refractions: u8,
+    // Reflections and refractions together.
+    total: u8,
+}
+
+impl Remaining {
+    // The budget left to a reflected ray, or None if it may not be cast.
+    fn reflected(self) -> Option<Remaining> {
+        if self.reflections == 0 || self.total == 0 {
+            None
+        } else {
+            Some(Remaining {
+                reflections: self.reflections - 1,
+                total: self.total - 1,
+                ..self
+            })
+        }
+    }
+
+    fn refracted(self) -> Option<Remaining> {
+        if self.refractions == 0 || self.total == 0 {
+            None
+        } else {
+            Some(Remaining {
+                refractions: self.refractions - 1,
+                total: self.total - 1,
+                ..self
+            })
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct World<T> {
     pub light: Light<T>,
-    pub objects: Vec<Shape<T>>,
-    recursion: u8,
+    pub objects: Vec<ShapeRc<T>>,
+    pub shadow_offset: ShadowOffset<T>,
+    // The most reflected and refracted rays in a row, counted together and per kind.
+    pub max_depth: u8,
+    pub max_reflection_depth: u8,
+    pub max_refraction_depth: u8,
+    // Shade flat triangles with normals blended across the neighbouring faces of their group.
+    pub smooth_shading: bool,
+    // The color seen by rays that hit nothing.
+    pub background: RGB<T>,
+    // Seeds every random sampler, so a render is the same on every run.
+    pub seed: u64,
+    pub fog: Option<Fog<T>>,
+    // Image-based light added to the diffuse shading of every surface, unshadowed.
+    pub environment: Option<EnvironmentLight<T>>,
+    // Shared materials that shapes refer to through Material::from_library.
+    pub materials: MaterialLibrary<T>,
+    // World-space bounds of each top-level object, or None for objects that must always be
+    // tested. A flat list rather than a hierarchy: groups keep their own bounds checks.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    accel: Option<Vec<Option<Bounds<T>>>>,
+    // The vertex normals of each group whose triangles smooth shading has met without normals of
+    // their own, found on the first hit. Like accel, it is not updated when the groups change.
+    // Holding the group keeps its address from being reused by another.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    vertex_normals: RefCell<VertexNormalCache<T>>,
+    // How many colors from color_at had NaN or infinite channels replaced.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    invalid_colors: Cell<usize>,
+}
+
+impl<T: Default> World<T> {
+    pub fn new(light: Light<T>, objects: Vec<Shape<T>>) -> World<T> {
+        World {
+            light,
+            objects: objects.into_iter().map(get_rc).collect(),
+            shadow_offset: ShadowOffset::Normal,
+            max_depth: RECURSION_LIMIT,
+            max_reflection_depth: RECURSION_LIMIT,
+            max_refraction_depth: RECURSION_LIMIT,
+            smooth_shading: false,
+            background: RGB::default(),
+            seed: 0,
+            fog: None,
+            environment: None,
+            materials: MaterialLibrary::new(),
+            accel: None,
+            vertex_normals: RefCell::new(HashMap::new()),
+            invalid_colors: Cell::new(0),
+        }
+    }
+
+    pub fn with_max_depth(mut self, depth: u8) -> World<T> {
+        self.max_depth = depth;
+        self
+    }
+
+    pub fn with_smooth_shading(mut self) -> World<T> {
+        self.smooth_shading = true;
+        self
+    }
 }
 
 impl<T: BaseFloat + Default> Default for World<T> {
     fn default() -> World<T> {
         let neg10 = T::from(-10).unwrap();
         let one = T::one();
-        World::<T> {
-            light: Light::new(Point3::new(neg10, -neg10, neg10), RGB::new(one, one, one)),
-            objects: vec![
+        World::new(
+            Light::new(Point3::new(neg10, -neg10, neg10), RGB::new(one, one, one)),
+            vec![
                 Shape::Sphere(Sphere::new(
                     Matrix4::identity(),
-                    Material::new(
-                        Pattern::Solid(RGB::new(T::from(0.8).unwrap(), one, T::from(0.6).unwrap())),
-                        T::from(0.1).unwrap(),
-                        T::from(0.7).unwrap(),
-                        T::from(0.2).unwrap(),
-                        T::from(200).unwrap(),
-                        T::zero(),
-                        T::zero(),
-                        one,
-                    ),
+                    Material {
+                        pattern: Pattern::Solid(RGB::new(
+                            T::from(0.8).unwrap(),
+                            one,
+                            T::from(0.6).unwrap(),
+                        )),
+                        diffuse: T::from(0.7).unwrap(),
+                        specular: T::from(0.2).unwrap(),
+                        ..Material::default()
+                    },
                     None,
                 )),
                 Shape::Sphere(Sphere::new(
@@ -46,37 +224,214 @@ impl<T: BaseFloat + Default> Default for World<T> {
                     None,
                 )),
             ],
-            recursion: RECURSION_LIMIT,
+        )
+    }
+}
+
+impl<T: BaseFloat> World<T> {
+    // Check the object and everything grouped under it.
+    fn validate_shape(index: usize, shape: &Shape<T>, warnings: &mut Vec<Warning>) {
+        if !shape.transform().is_invertible() {
+            warnings.push(Warning::SingularTransform(index));
+        }
+        if let Some(m) = shape.material() {
+            if m.diffuse + m.reflective + m.transparency > T::one() {
+                warnings.push(Warning::ExcessEnergy(index));
+            }
+        }
+        match shape {
+            Shape::Group(g) => {
+                for child in &g.children {
+                    World::validate_shape(index, &child.borrow(), warnings);
+                }
+            }
+            Shape::ConstructiveSolidGeometry(csg) => {
+                World::validate_shape(index, &csg.left.borrow(), warnings);
+                World::validate_shape(index, &csg.right.borrow(), warnings);
+            }
+            Shape::Instanced(i) => World::validate_shape(index, &i.child.borrow(), warnings),
+            Shape::Triangle(t) if t.e1.cross(t.e2).magnitude2() <= T::epsilon() => {
+                warnings.push(Warning::DegenerateTriangle(index));
+            }
+            Shape::SmoothTriangle(t)
+                if (t.p2 - t.p1).cross(t.p3 - t.p1).magnitude2() <= T::epsilon() =>
+            {
+                warnings.push(Warning::DegenerateTriangle(index));
+            }
+            _ => {}
+        }
+    }
+
+    // Cache the world-space bounds of every top-level object so rays skip the objects they miss.
+    // This is one flat list of boxes, not a bounding volume hierarchy. The cache is not updated
+    // when objects change, so build it again after editing them; objects pushed since are always
+    // tested.
+    pub fn build_accel(&mut self) {
+        self.accel = Some(
+            self.objects
+                .iter()
+                .map(|s| World::world_bounds(&s.borrow()))
+                .collect(),
+        );
+    }
+
+    fn world_bounds(shape: &Shape<T>) -> Option<Bounds<T>> {
+        if shape.as_constructive_solid_geometry().is_some() {
+            // CSG bounds are not implemented yet.
+            return None;
+        }
+        let bounds = Bounds::from_all_points(&shape.bounds()?.transform(shape.transform()))?;
+        // Unbounded shapes like planes can overflow when transformed.
+        let finite = |p: Point3<T>| p.x.is_finite() && p.y.is_finite() && p.z.is_finite();
+        if finite(bounds.minimum) && finite(bounds.maximum) {
+            Some(bounds)
+        } else {
+            None
         }
     }
+
+    // Report likely mistakes in the scene before spending time on rendering it.
+    pub fn validate(&self) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        for (index, rc) in self.objects.iter().enumerate() {
+            let shape = &*rc.borrow();
+            World::validate_shape(index, shape, &mut warnings);
+            let closed = match shape {
+                Shape::Sphere(_) | Shape::Cube(_) => true,
+                Shape::Cone(c) => c.closed,
+                Shape::Cylinder(c) => c.closed,
+                _ => false,
+            };
+            let opaque = shape
+                .material()
+                .is_some_and(|m| m.transparency == T::zero());
+            if closed && opaque {
+                // A ray leaving a point inside a closed shape crosses its surface an odd number of times.
+                let ray = Ray::new(self.light.position(), Vector3::unit_y());
+                let crossings = intersect_shared(rc, ray, &Cell::new(0))
+                    .iter()
+                    .filter(|i| i.t > T::zero())
+                    .count();
+                if crossings % 2 == 1 {
+                    warnings.push(Warning::LightInsideOpaqueObject(index));
+                }
+            }
+        }
+        warnings
+    }
 }
 
 impl<T: BaseFloat + Default> World<T> {
-    fn shade_hit(&mut self, comps: &Computation<T>) -> RGB<T> {
-        let shadowed = self.is_shadowed(comps.over_point());
-        let material = comps.object.material().unwrap();
-        let surface = material.lighting(
+    fn shadow_point(&self, comps: &Computation<T>) -> Point3<T> {
+        let lightv = (self.light.position() - comps.point).normalize();
+        let direction = match self.shadow_offset {
+            ShadowOffset::Normal => comps.normalv,
+            ShadowOffset::Light => lightv,
+            ShadowOffset::Blend(f) => (comps.normalv * (T::one() - f) + lightv * f).normalize(),
+        };
+        comps.point + direction * comps.surface_offset()
+    }
+
+    fn remaining(&self) -> Remaining {
+        Remaining {
+            reflections: self.max_reflection_depth,
+            refractions: self.max_refraction_depth,
+            total: self.max_depth,
+        }
+    }
+
+    fn shade_hit(&self, comps: &Computation<T>, remaining: Remaining) -> RGB<T> {
+        let shadowed = self.is_shadowed(self.shadow_point(comps));
+        let object = comps.object.borrow();
+        let material = self.material_of(&object).unwrap();
+        let color = material.color_at(comps.over_point(), comps.normalv, comps.texture_uv);
+        let surface = material.lighting_of(
+            color,
             self.light,
             comps.over_point(),
             comps.eyev,
             comps.normalv,
             shadowed,
         );
-        let reflected = self.reflected_color(comps);
-        let refracted = self.refracted_color(comps);
-        if material.reflective > T::zero() && material.transparency > T::zero() {
+        let mut surface = surface + material.emission;
+        if let Some(environment) = &self.environment {
+            surface += material.environment_lighting_of(color, environment, comps.normalv);
+        }
+        for (emitter, light) in self.emitter_lights(comps.point) {
+            if !Rc::ptr_eq(&emitter, &comps.object)
+                && !self.is_shadowed_from(comps.over_point(), light.position(), Some(&emitter))
+            {
+                surface += material.direct_lighting(
+                    color,
+                    light,
+                    comps.over_point(),
+                    comps.eyev,
+                    comps.normalv,
+                );
+            }
+        }
+        let reflected = self.reflected_color(comps, remaining);
+        let refracted = self.refracted_color(comps, remaining);
+        let color = if material.reflective > T::zero() && material.transparency > T::zero() {
             let reflectance = comps.schlick();
             surface + reflected * reflectance + refracted * (T::one() - reflectance)
         } else {
             surface + reflected + refracted
+        };
+        let alpha = comps
+            .texture_uv
+            .and_then(|uv| material.pattern.alpha_at_texture_uv(uv))
+            .unwrap_or_else(|| {
+                material
+                    .pattern
+                    .alpha_at_object(&object, comps.over_point() - comps.offset)
+            });
+        let color = if alpha < T::one() {
+            // Whatever lies behind shows through the uncovered part of the surface.
+            let behind = self.color_at_depth(Ray::new(comps.under_point(), -comps.eyev), remaining);
+            color * alpha + behind * (T::one() - alpha)
+        } else {
+            color
+        };
+        match self.fog {
+            Some(fog) => {
+                let fogged = T::one() - (-fog.density * comps.t).exp();
+                color * (T::one() - fogged) + fog.color * fogged
+            }
+            None => color,
         }
     }
 
-    fn intersect(&self, ray: Ray<T>) -> Vec<Intersection<T>> {
+    // The shape's material, looked up in the library if it refers to one.
+    fn material_of<'a>(&'a self, shape: &'a Shape<T>) -> Option<&'a Material<T>> {
+        shape.material().map(|m| self.materials.resolve(m))
+    }
+
+    // False only if the cached bounds show the ray misses objects[i].
+    fn may_hit(&self, i: usize, ray: Ray<T>) -> bool {
+        self.accel
+            .as_ref()
+            .and_then(|accel| accel.get(i).copied().flatten())
+            .is_none_or(|b| b.is_intersected_with(ray))
+    }
+
+    // The nearest hit along with the index of the top-level object it belongs to.
+    pub fn nearest_hit(&self, ray: Ray<T>) -> Option<(Intersection<T>, usize)> {
+        self.objects
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| self.may_hit(i, ray))
+            .filter_map(|(i, s)| hit(&intersect_shared(s, ray, &Cell::new(0))).map(|h| (h, i)))
+            .min_by(|(a, _), (b, _)| a.t.partial_cmp(&b.t).unwrap_or(std::cmp::Ordering::Less))
+    }
+
+    pub(crate) fn intersect(&self, ray: Ray<T>) -> Vec<Intersection<T>> {
         let mut xs = self
             .objects
             .iter()
-            .map(|s| s.intersect(ray))
+            .enumerate()
+            .filter(|&(i, _)| self.may_hit(i, ray))
+            .map(|(_, s)| intersect_shared(s, ray, &Cell::new(0)))
             .into_iter()
             .flatten()
             .collect::<Vec<_>>();
@@ -84,79 +439,311 @@ impl<T: BaseFloat + Default> World<T> {
         xs
     }
 
-    pub fn color_at(&mut self, ray: Ray<T>) -> RGB<T> {
+    // Channels that come out NaN or infinite, say from a degenerate refraction, are set to 0 so
+    // that one bad ray cannot spoil the image.
+    pub fn color_at(&self, ray: Ray<T>) -> RGB<T> {
+        let color = self.color_at_depth(ray, self.remaining());
+        let finite = |c: T| if c.is_finite() { c } else { T::zero() };
+        let sanitized = RGB::new(finite(color.r), finite(color.g), finite(color.b));
+        if sanitized != color {
+            self.invalid_colors.set(self.invalid_colors.get() + 1);
+        }
+        sanitized
+    }
+
+    pub fn invalid_colors(&self) -> usize {
+        self.invalid_colors.get()
+    }
+
+    fn color_at_depth(&self, ray: Ray<T>, remaining: Remaining) -> RGB<T> {
         let xs = self.intersect(ray);
         if let Some(i) = hit(&xs) {
-            if let Some(comps) = i.precompute(ray, &xs) {
-                self.shade_hit(&comps)
+            if let Some(comps) = i.precompute_with(ray, &xs, &self.materials) {
+                let smoothed = self
+                    .smooth_shading
+                    .then(|| self.smoothed_normal_at(&comps.object.borrow(), i.uv))
+                    .flatten();
+                let comps = match smoothed {
+                    Some(normalv) => comps.with_normal(normalv),
+                    None => comps,
+                };
+                self.shade_hit(&comps, remaining)
             } else {
-                RGB::default()
+                self.background
             }
         } else {
-            RGB::default()
+            self.background
         }
     }
 
+    // The smoothed normal of a hit on a flat triangle. Triangles without vertex normals of their
+    // own, as built by hand, take those averaged over the group they are in.
+    fn smoothed_normal_at(&self, object: &Shape<T>, uv: Option<(T, T)>) -> Option<Vector3<T>> {
+        object.smoothed_normal_at(uv, |t| {
+            let group = t.parent.as_ref()?.upgrade()?;
+            let mut cache = self.vertex_normals.borrow_mut();
+            let (_, normals) = cache.entry(Rc::as_ptr(&group)).or_insert_with(|| {
+                let normals = group.borrow().as_group().map(VertexNormals::of);
+                (group.clone(), normals.unwrap_or_default())
+            });
+            normals.at(t)
+        })
+    }
+
     fn is_shadowed(&self, point: Point3<T>) -> bool {
-        let v = self.light.position - point;
+        self.is_shadowed_from(point, self.light.position(), None)
+    }
+
+    // Whether anything but the emitter itself or a surface that casts no shadow lies between the
+    // point and the light position. Where a surface is less than half covered, as set by the
+    // alpha of its pattern, the light passes.
+    fn is_shadowed_from(
+        &self,
+        point: Point3<T>,
+        position: Point3<T>,
+        emitter: Option<&ShapeRc<T>>,
+    ) -> bool {
+        let v = position - point;
         let distance = v.magnitude();
         let direction = v.normalize();
-        let intersections = self.intersect(Ray::new(point, direction));
+        let ray = Ray::new(point, direction);
+        let half = T::from(0.5).unwrap();
+        let intersections = self
+            .intersect(ray)
+            .into_iter()
+            .filter(|i| emitter.is_none_or(|e| !Rc::ptr_eq(e, &i.object)))
+            .filter(|i| {
+                let object = i.object.borrow();
+                self.material_of(&object).is_none_or(|m| {
+                    let alpha = object
+                        .texture_uv(i.uv)
+                        .and_then(|uv| m.pattern.alpha_at_texture_uv(uv))
+                        .unwrap_or_else(|| {
+                            m.pattern
+                                .alpha_at_object(&object, ray.position(i.t) - i.offset)
+                        });
+                    m.casts_shadow && alpha >= half
+                })
+            })
+            .collect::<Vec<_>>();
         let h = hit(&intersections);
         h.is_some() && h.unwrap().t < distance
     }
 
-    fn reflected_color(&mut self, comps: &Computation<T>) -> RGB<T> {
-        let r = comps.object.material().unwrap().reflective;
-        if self.recursion == 0 || r == T::zero() {
-            // Terminate the calling loop
-            self.recursion = RECURSION_LIMIT;
-            RGB::default()
-        } else {
-            let reflect_ray = Ray::new(comps.over_point(), comps.reflectv);
-            self.recursion -= 1;
-            let color = self.color_at(reflect_ray);
-            color * r
+    // A random stream for sampling at the point, fixed by the world seed.
+    fn rng_at(&self, point: Point3<T>) -> Rng {
+        let bits = |c: T| c.to_f64().unwrap().to_bits();
+        Rng::stream(self.seed, &[bits(point.x), bits(point.y), bits(point.z)])
+    }
+
+    // Point lights spread evenly over each emissive triangle, sharing its emission.
+    // The samples are jittered within their grid cells by a stream seeded from the world seed
+    // and the lit point, so the noise changes with the seed but not between runs.
+    fn emitter_lights(&self, lit: Point3<T>) -> Vec<(ShapeRc<T>, Light<T>)> {
+        let mut lights = Vec::new();
+        let mut rng = self.rng_at(lit);
+        for object in &self.objects {
+            self.push_emitter_lights(object, Matrix4::identity(), &mut rng, &mut lights);
         }
+        lights
     }
 
-    fn refracted_color(&mut self, comps: &Computation<T>) -> RGB<T> {
-        let material = comps.object.material().unwrap();
-        if self.recursion == 0 || material.transparency == T::zero() {
-            self.recursion = RECURSION_LIMIT;
-            RGB::default()
+    // Add the lights of the object if it is an emissive triangle, or of the emissive triangles
+    // grouped under it, placed in world space by to_world, the transform of its parent space.
+    fn push_emitter_lights(
+        &self,
+        object: &ShapeRc<T>,
+        to_world: Matrix4<T>,
+        rng: &mut Rng,
+        lights: &mut Vec<(ShapeRc<T>, Light<T>)>,
+    ) {
+        let shape = object.borrow();
+        let to_world = to_world * shape.transform();
+        let triangle = match &*shape {
+            Shape::Triangle(t) => t,
+            Shape::Group(g) => {
+                for child in &g.children {
+                    self.push_emitter_lights(child, to_world, rng, lights);
+                }
+                return;
+            }
+            _ => return,
+        };
+        let emission = self.materials.resolve(&triangle.material).emission;
+        if emission == RGB::default() {
+            return;
+        }
+        let n = T::from(AREA_LIGHT_SAMPLES).unwrap();
+        let intensity = emission * (T::one() / (n * n));
+        for i in 0..AREA_LIGHT_SAMPLES {
+            for j in 0..AREA_LIGHT_SAMPLES {
+                let mut jitter = || T::from(rng.next_f64()).unwrap();
+                let mut u = (T::from(i).unwrap() + jitter()) / n;
+                let mut v = (T::from(j).unwrap() + jitter()) / n;
+                // Fold the half of the unit square outside the triangle back onto it.
+                if u + v > T::one() {
+                    u = T::one() - u;
+                    v = T::one() - v;
+                }
+                let point = triangle.p1 + triangle.e1 * u + triangle.e2 * v;
+                let position = Point3::from_homogeneous(to_world * point.to_homogeneous());
+                lights.push((object.clone(), Light::new(position, intensity)));
+            }
+        }
+    }
+
+    // Every surface the ray and its mirror and refracted continuations hit, depth first.
+    pub fn trace_path(&self, ray: Ray<T>) -> Vec<PathVertex<T>> {
+        let mut path = Vec::new();
+        self.trace(ray, RayKind::Camera, self.remaining(), &mut path);
+        path
+    }
+
+    fn trace(
+        &self,
+        ray: Ray<T>,
+        kind: RayKind,
+        remaining: Remaining,
+        path: &mut Vec<PathVertex<T>>,
+    ) {
+        let xs = self.intersect(ray);
+        let comps = match hit(&xs).and_then(|i| i.precompute_with(ray, &xs, &self.materials)) {
+            Some(comps) => comps,
+            None => return,
+        };
+        let object = comps.object.borrow();
+        let material = self.material_of(&object).unwrap();
+        let color = material.lighting(
+            self.light,
+            comps.over_point(),
+            comps.eyev,
+            comps.normalv,
+            self.is_shadowed(self.shadow_point(&comps)),
+        );
+        path.push(PathVertex {
+            point: comps.point,
+            object: object.clone(),
+            color,
+            kind,
+        });
+        if let Some(remaining) = remaining.reflected() {
+            if material.reflective > T::zero() {
+                let reflect_ray = Ray::new(comps.over_point(), comps.reflectv);
+                self.trace(reflect_ray, RayKind::Reflected, remaining, path);
+            }
+        }
+        if let (Some(remaining), Some(direction)) = (remaining.refracted(), comps.refractv()) {
+            if material.transparency > T::zero() {
+                let refracted_ray = Ray::new(comps.under_point(), direction);
+                self.trace(refracted_ray, RayKind::Refracted, remaining, path);
+            }
+        }
+    }
+
+    fn reflected_color(&self, comps: &Computation<T>, remaining: Remaining) -> RGB<T> {
+        let object = comps.object.borrow();
+        let material = self.material_of(&object).unwrap();
+        let coat = material.clearcoat_reflectance(comps.eyev, comps.normalv);
+        let r = material.reflective * (T::one() - coat);
+        if r == T::zero() && coat == T::zero() {
+            return RGB::default();
+        }
+        let remaining = match remaining.reflected() {
+            Some(remaining) => remaining,
+            // A ray that may not be followed any further sees only the background.
+            None => return self.background * (r + coat),
+        };
+        let mut color = RGB::default();
+        if r > T::zero() {
+            color += self.reflection_lobe(comps, remaining, material.reflection_roughness) * r;
+        }
+        if coat > T::zero() {
+            color += self.reflection_lobe(comps, remaining, material.clearcoat_roughness) * coat;
+        }
+        color
+    }
+
+    // The light arriving from around the mirror direction, within a cone of the given half-angle.
+    // The spiral of samples is spun by a random angle seeded like the area lights, so
+    // neighbouring points trade banding for noise.
+    fn reflection_lobe(
+        &self,
+        comps: &Computation<T>,
+        remaining: Remaining,
+        roughness: T,
+    ) -> RGB<T> {
+        if roughness == T::zero() {
+            let reflect_ray = Ray::new(comps.over_point(), comps.reflectv);
+            self.color_at_depth(reflect_ray, remaining)
         } else {
-            let one = T::one();
-            if comps.sin2_t() > one {
-                RGB::default()
-            } else {
-                let direction = comps.normalv * (comps.n_ratio() * comps.cos_i() - comps.cos_t())
-                    - comps.eyev * comps.n_ratio();
+            let axis = comps.reflectv.normalize();
+            let two_pi = T::from(std::f64::consts::TAU).unwrap();
+            let spin = T::from(self.rng_at(comps.over_point()).next_f64()).unwrap() * two_pi;
+            let spin = Matrix3::from_axis_angle(axis, Rad(spin));
+            let mut color = RGB::default();
+            for direction in cone_samples(axis, roughness, GLOSSY_SAMPLES) {
+                let ray = Ray::new(comps.over_point(), spin * direction);
+                color += self.color_at_depth(ray, remaining);
+            }
+            color / T::from(GLOSSY_SAMPLES).unwrap()
+        }
+    }
+
+    fn refracted_color(&self, comps: &Computation<T>, remaining: Remaining) -> RGB<T> {
+        let object = comps.object.borrow();
+        let material = self.material_of(&object).unwrap();
+        if material.transparency == T::zero() {
+            return RGB::default();
+        }
+        match (remaining.refracted(), comps.refractv()) {
+            (None, Some(_)) => self.background * material.transparency,
+            (Some(remaining), Some(direction)) => {
                 let refracted_ray = Ray::new(comps.under_point(), direction);
-                self.recursion -= 1;
-                self.color_at(refracted_ray) * material.transparency
+                let color = self.color_at_depth(refracted_ray, remaining) * material.transparency;
+                match comps.exit_distance() {
+                    // The refracted ray is taken to cross the same chord as the incoming one.
+                    Some(d) if !comps.inside => {
+                        let a = material.absorption;
+                        color * RGB::new((-a.r * d).exp(), (-a.g * d).exp(), (-a.b * d).exp())
+                    }
+                    _ => color,
+                }
             }
+            _ => RGB::default(),
         }
     }
 }
 
 mod tests {
     use super::*;
-    use crate::{pattern::test::Test, shape::plane::Plane};
+    use crate::camera::{Camera, Quality};
+    use crate::{
+        canvas::Canvas,
+        pattern::{image_texture::ImageTexture, test::Test, uv::UvMapping},
+        shape::{
+            cube::Cube,
+            get_rc,
+            group::{push, Group},
+            plane::Plane,
+            triangle::Triangle,
+        },
+    };
     use approx::assert_relative_eq;
-    use cgmath::{EuclideanSpace, Vector3};
-    use std::f32::consts::FRAC_1_SQRT_2;
+    use cgmath::{EuclideanSpace, InnerSpace, Rad, Vector3};
+    use rgb::RGBA;
+    use std::f32::consts::{FRAC_1_SQRT_2, FRAC_PI_2, PI};
 
     #[test]
     fn shade_hit() {
         {
-            let mut w = World::default();
+            let w = World::default();
             let xs = vec![Intersection::new(4., w.objects[0].clone(), None)];
             assert_relative_eq!(
                 w.shade_hit(
                     &xs[0]
                         .precompute(Ray::new(Point3::new(0., 0., -5.), Vector3::unit_z()), &xs)
                         .unwrap(),
+                    w.remaining(),
                 ),
                 RGB::new(0.38066, 0.47583, 0.2855),
                 max_relative = 0.0001
@@ -171,6 +758,7 @@ mod tests {
                     &xs[0]
                         .precompute(Ray::new(Point3::origin(), Vector3::unit_z()), &xs)
                         .unwrap(),
+                    w.remaining(),
                 ),
                 RGB::new(0.90498, 0.90498, 0.90498),
                 max_relative = 0.00001
@@ -182,7 +770,7 @@ mod tests {
             plane.transform = Matrix4::from_translation(-Vector3::unit_y());
             plane.material.reflective = 0.5;
             let shape = Shape::Plane(plane);
-            w.objects.push(shape.clone());
+            w.objects.push(get_rc(shape.clone()));
             let r = Ray::new(
                 Point3::from_vec(Vector3::unit_z() * -3.),
                 Vector3::new(0., -FRAC_1_SQRT_2, FRAC_1_SQRT_2),
@@ -190,7 +778,7 @@ mod tests {
             let xs = vec![Intersection::new(2.0_f32.sqrt(), shape, None)];
             let comps = xs[0].precompute(r, &xs).unwrap();
             assert_relative_eq!(
-                w.shade_hit(&comps),
+                w.shade_hit(&comps, w.remaining()),
                 RGB::new(0.87677, 0.92436, 0.82918),
                 max_relative = 0.0001
             );
@@ -206,8 +794,8 @@ mod tests {
             ball.material.ambient = 0.5;
             ball.transform = Matrix4::from_translation(Vector3::new(0., -3.5, -0.5));
             let shape = Shape::Plane(floor);
-            w.objects.push(shape.clone());
-            w.objects.push(Shape::Sphere(ball));
+            w.objects.push(get_rc(shape.clone()));
+            w.objects.push(get_rc(Shape::Sphere(ball)));
             let r = Ray::new(
                 Point3::from_vec(Vector3::unit_z() * -3.),
                 Vector3::new(0., -FRAC_1_SQRT_2, FRAC_1_SQRT_2),
@@ -215,7 +803,7 @@ mod tests {
             let xs = vec![Intersection::new(2.0_f32.sqrt(), shape, None)];
             let comps = xs[0].precompute(r, &xs).unwrap();
             assert_relative_eq!(
-                w.shade_hit(&comps),
+                w.shade_hit(&comps, w.remaining()),
                 RGB::new(0.93642, 0.68642, 0.68642),
                 max_relative = 0.00001
             );
@@ -232,8 +820,8 @@ mod tests {
             ball.material.ambient = 0.5;
             ball.transform = Matrix4::from_translation(Vector3::new(0., -3.5, -0.5));
             let shape = Shape::Plane(floor);
-            w.objects.push(shape.clone());
-            w.objects.push(Shape::Sphere(ball));
+            w.objects.push(get_rc(shape.clone()));
+            w.objects.push(get_rc(Shape::Sphere(ball)));
             let r = Ray::new(
                 Point3::from_vec(Vector3::unit_z() * -3.),
                 Vector3::new(0., -FRAC_1_SQRT_2, FRAC_1_SQRT_2),
@@ -241,7 +829,7 @@ mod tests {
             let xs = vec![Intersection::new(2.0_f32.sqrt(), shape, None)];
             let comps = xs[0].precompute(r, &xs).unwrap();
             assert_relative_eq!(
-                w.shade_hit(&comps),
+                w.shade_hit(&comps, w.remaining()),
                 RGB::new(0.93391, 0.69643, 0.69243),
                 max_relative = 0.00001
             );
@@ -250,7 +838,7 @@ mod tests {
 
     #[test]
     fn color_at() {
-        let mut w = World::default();
+        let w = World::default();
         assert_eq!(
             w.color_at(Ray::new(Point3::new(0., 0., -5.), Vector3::unit_y())),
             RGB::default()
@@ -260,11 +848,22 @@ mod tests {
             RGB::new(0.38066, 0.47583, 0.2855),
             max_relative = 0.0001
         );
-        w.objects[0].as_sphere_mut().unwrap().material.ambient = 1.;
-        w.objects[1].as_sphere_mut().unwrap().material.ambient = 1.;
+        w.objects[0]
+            .borrow_mut()
+            .as_sphere_mut()
+            .unwrap()
+            .material
+            .ambient = 1.;
+        w.objects[1]
+            .borrow_mut()
+            .as_sphere_mut()
+            .unwrap()
+            .material
+            .ambient = 1.;
         assert_eq!(
             w.color_at(Ray::new(Point3::new(0., 0., 0.75), -Vector3::unit_z())),
             w.objects[1]
+                .borrow()
                 .material()
                 .unwrap()
                 .pattern
@@ -272,6 +871,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn invalid_colors() {
+        let mut w = World::<f32>::default();
+        let ray = Ray::new(Point3::new(0., 0., -5.), Vector3::unit_z());
+        let expected = w.color_at(ray);
+        // An infinite channel turns into infinities and NaN (inf * 0) while shading.
+        w.light = Light::new(w.light.position(), RGB::new(f32::INFINITY, 1., 1.));
+        let color = w.color_at(ray);
+        assert_eq!(color.r, 0.);
+        assert_eq!((color.g, color.b), (expected.g, expected.b));
+        assert_eq!(w.invalid_colors(), 1);
+        let mut c = Camera::from(3, 3, FRAC_PI_2);
+        c.transform = Matrix4::look_at_rh(
+            Point3::new(0., 0., -5.),
+            Point3::origin(),
+            Vector3::unit_y(),
+        );
+        let (image, stats) = c.render_preset(&mut w, Quality::Draft);
+        assert!(image
+            .pixels
+            .iter()
+            .flatten()
+            .all(|p| p.r.is_finite() && p.g.is_finite() && p.b.is_finite()));
+        assert!(stats.invalid_colors > 0);
+        assert_eq!(w.invalid_colors(), 1 + stats.invalid_colors);
+    }
+
     #[test]
     fn is_shadowed() {
         let w = World::default();
@@ -287,28 +913,33 @@ mod tests {
         let xs = Vec::new();
         {
             let r = Ray::new(Point3::origin(), Vector3::unit_z());
-            w.objects[1].as_sphere_mut().unwrap().material.ambient = 1.;
+            w.objects[1]
+                .borrow_mut()
+                .as_sphere_mut()
+                .unwrap()
+                .material
+                .ambient = 1.;
             let i = Intersection::new(1., w.objects[1].clone(), None);
             let comps = i.precompute(r, &xs).unwrap();
-            assert_eq!(w.reflected_color(&comps), RGB::default());
+            assert_eq!(w.reflected_color(&comps, w.remaining()), RGB::default());
         }
         {
             let mut plane = Plane::default();
             plane.transform = Matrix4::from_translation(-Vector3::unit_y());
             plane.material.reflective = 0.5;
             let shape = Shape::Plane(plane);
-            w.objects.push(shape.clone());
+            w.objects.push(get_rc(shape.clone()));
             let r = Ray::new(
                 Point3::from_vec(Vector3::unit_z() * -3.),
                 Vector3::new(0., -FRAC_1_SQRT_2, FRAC_1_SQRT_2),
             );
             let i = Intersection::new(2.0_f32.sqrt(), shape, None);
             let comps = i.precompute(r, &xs).unwrap();
-            w.recursion = 0;
-            assert_eq!(w.reflected_color(&comps), RGB::default());
-            w.recursion = 1;
+            w.max_reflection_depth = 0;
+            assert_eq!(w.reflected_color(&comps, w.remaining()), RGB::default());
+            w.max_reflection_depth = 1;
             assert_relative_eq!(
-                w.reflected_color(&comps),
+                w.reflected_color(&comps, w.remaining()),
                 RGB::new(0.19032, 0.2379, 0.14274),
                 max_relative = 0.0001
             );
@@ -323,13 +954,13 @@ mod tests {
             let mut plane = Plane::default();
             plane.transform = Matrix4::from_translation(-Vector3::unit_y());
             plane.material.reflective = 1.;
-            w.objects[0] = Shape::Plane(plane);
+            w.objects[0] = get_rc(Shape::Plane(plane));
         }
         {
             let mut plane = Plane::default();
             plane.transform = Matrix4::from_translation(Vector3::unit_y());
             plane.material.reflective = 1.;
-            w.objects[1] = Shape::Plane(plane);
+            w.objects[1] = get_rc(Shape::Plane(plane));
         }
         let r = Ray::new(Point3::origin(), Vector3::unit_y());
         let _ = w.color_at(r);
@@ -339,7 +970,7 @@ mod tests {
     fn refracted_color() {
         let vz = Vector3::unit_z();
         {
-            let mut w = World::default();
+            let w = World::default();
             let shape = w.objects[0].clone();
             let xs = vec![
                 Intersection::new(4., shape.clone(), None),
@@ -348,12 +979,18 @@ mod tests {
             let comps = xs[0]
                 .precompute(Ray::new(Point3::from_vec(vz * -5.), vz), &xs)
                 .unwrap();
-            assert_eq!(w.refracted_color(&comps), RGB::default());
+            assert_eq!(w.refracted_color(&comps, w.remaining()), RGB::default());
         }
         {
-            let mut w = World::default();
-            w.objects[0].as_sphere_mut().unwrap().material.transparency = 1.0;
+            let w = World::default();
             w.objects[0]
+                .borrow_mut()
+                .as_sphere_mut()
+                .unwrap()
+                .material
+                .transparency = 1.0;
+            w.objects[0]
+                .borrow_mut()
                 .as_sphere_mut()
                 .unwrap()
                 .material
@@ -369,15 +1006,30 @@ mod tests {
                     &xs,
                 )
                 .unwrap();
-            assert_eq!(w.refracted_color(&comps), RGB::default());
+            assert_eq!(w.refracted_color(&comps, w.remaining()), RGB::default());
         }
         {
-            let mut w = World::default();
-            w.objects[0].as_sphere_mut().unwrap().material.ambient = 1.;
-            w.objects[0].as_sphere_mut().unwrap().material.pattern =
-                Pattern::Test(Test::new(Matrix4::identity()));
-            w.objects[1].as_sphere_mut().unwrap().material.transparency = 1.;
+            let w = World::default();
+            w.objects[0]
+                .borrow_mut()
+                .as_sphere_mut()
+                .unwrap()
+                .material
+                .ambient = 1.;
+            w.objects[0]
+                .borrow_mut()
+                .as_sphere_mut()
+                .unwrap()
+                .material
+                .pattern = Pattern::Test(Test::new(Matrix4::identity()));
             w.objects[1]
+                .borrow_mut()
+                .as_sphere_mut()
+                .unwrap()
+                .material
+                .transparency = 1.;
+            w.objects[1]
+                .borrow_mut()
                 .as_sphere_mut()
                 .unwrap()
                 .material
@@ -392,10 +1044,737 @@ mod tests {
                 .precompute(Ray::new(Point3::from_vec(vz * 0.1), Vector3::unit_y()), &xs)
                 .unwrap();
             assert_relative_eq!(
-                w.refracted_color(&comps),
+                w.refracted_color(&comps, w.remaining()),
                 RGB::new(0., 0.99888, 0.04725),
                 max_relative = 0.001
             );
         }
     }
+
+    #[test]
+    fn shadow_offset() {
+        // A floor meeting a ceiling that slopes down to it at a shallow angle.
+        let (alpha, beta) = (0.1_f32, 0.05_f32);
+        let floor = Shape::Plane(Plane::default());
+        let ceiling = Shape::Plane(Plane::new(
+            Matrix4::from_angle_z(Rad(alpha)) * Matrix4::from_angle_x(Rad(PI)),
+            Material::default(),
+            None,
+        ));
+        let mut w = World::new(
+            Light::new(
+                Point3::new(beta.cos(), beta.sin(), 0.) * 100.,
+                RGB::new(1., 1., 1.),
+            ),
+            vec![floor.clone(), ceiling],
+        );
+        let i = Intersection::new(1., floor, None);
+        let comps = i
+            .precompute(
+                Ray::new(Point3::new(0., 1., 0.), -Vector3::unit_y()),
+                std::slice::from_ref(&i),
+            )
+            .unwrap();
+        // Offsetting along the normal pushes the shadow ray origin past the ceiling.
+        assert!(w.is_shadowed(w.shadow_point(&comps)));
+        w.shadow_offset = ShadowOffset::Light;
+        assert!(!w.is_shadowed(w.shadow_point(&comps)));
+    }
+
+    #[test]
+    fn casts_shadow() {
+        let ball = |casts_shadow| {
+            Shape::Sphere(Sphere::new(
+                Matrix4::from_translation(Vector3::unit_y() * 2.),
+                Material {
+                    casts_shadow,
+                    ..Material::default()
+                },
+                None,
+            ))
+        };
+        let floor_point = Point3::new(0., 0.0001, 0.);
+        let light = Light::new(Point3::new(0., 10., 0.), RGB::new(1., 1., 1.));
+        let w = World::new(light, vec![Shape::Plane(Plane::default()), ball(true)]);
+        assert!(w.is_shadowed(floor_point));
+        let w = World::new(light, vec![Shape::Plane(Plane::default()), ball(false)]);
+        assert!(!w.is_shadowed(floor_point));
+        // The floor is lit as brightly as with nothing in the way.
+        let down = Ray::new(
+            Point3::new(0., 1., -1.),
+            Vector3::new(0., -1., 1.).normalize(),
+        );
+        let lit = World::new(light, vec![Shape::Plane(Plane::default())]).color_at(down);
+        let w = World::new(light, vec![Shape::Plane(Plane::default()), ball(false)]);
+        assert_eq!(w.color_at(down), lit);
+    }
+
+    #[test]
+    fn glossy_reflection() {
+        let mirror = |roughness, seed| {
+            let floor = Shape::Plane(Plane::new(
+                Matrix4::identity(),
+                Material {
+                    pattern: Pattern::Solid(RGB::default()),
+                    reflective: 1.,
+                    reflection_roughness: roughness,
+                    ..Material::default()
+                },
+                None,
+            ));
+            // The reflected ray just misses the edge of the red ball.
+            let ball = Shape::Sphere(Sphere::new(
+                Matrix4::from_translation(Vector3::new(1.05, 2., 2.)),
+                Material {
+                    pattern: Pattern::Solid(RGB::new(1., 0., 0.)),
+                    ambient: 1.,
+                    ..Material::default()
+                },
+                None,
+            ));
+            let mut w = World::new(
+                Light::new(Point3::new(-10., 10., -10.), RGB::new(1., 1., 1.)),
+                vec![floor, ball],
+            );
+            w.seed = seed;
+            w.color_at(Ray::new(
+                Point3::new(0., 1., -1.),
+                Vector3::new(0., -FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+            ))
+        };
+        assert_relative_eq!(mirror(0., 0), RGB::default());
+        assert!(mirror(0.2, 0).r > 0.);
+        // Rough reflections follow the seed, a perfect mirror ignores it.
+        assert_eq!(mirror(0.2, 7), mirror(0.2, 7));
+        assert_ne!(mirror(0.2, 7), mirror(0.2, 8));
+        assert_eq!(mirror(0., 7), mirror(0., 8));
+    }
+
+    #[test]
+    fn validate() {
+        assert_eq!(World::<f32>::default().validate(), vec![]);
+        let flat = Shape::Sphere(Sphere::new(
+            Matrix4::from_nonuniform_scale(10., 0., 10.),
+            Material::default(),
+            None,
+        ));
+        let bright = Shape::Sphere(Sphere::new(
+            Matrix4::from_translation(Vector3::unit_x() * 5.),
+            Material {
+                reflective: 0.5,
+                ..Material::default()
+            },
+            None,
+        ));
+        let w = World::new(
+            Light::new(Point3::new(-10., 10., -10.), RGB::new(1., 1., 1.)),
+            vec![flat, bright],
+        );
+        assert_eq!(
+            w.validate(),
+            vec![Warning::SingularTransform(0), Warning::ExcessEnergy(1)]
+        );
+        let w = World::new(
+            Light::new(Point3::new(0.2, 0., 0.), RGB::new(1., 1., 1.)),
+            vec![Shape::Sphere(Sphere::default())],
+        );
+        assert_eq!(w.validate(), vec![Warning::LightInsideOpaqueObject(0)]);
+    }
+
+    #[test]
+    fn shade_hit_emission() {
+        // The book's shadowed sphere, now glowing: only ambient light and the emission remain.
+        let glow = Shape::Sphere(Sphere {
+            transform: Matrix4::from_translation(Vector3::unit_z() * 10.),
+            material: Material {
+                emission: RGB::new(0.2, 0.4, 0.6),
+                ..Material::default()
+            },
+            ..Sphere::default()
+        });
+        let w = World::new(
+            Light::new(Point3::new(0., 0., -10.), RGB::new(1., 1., 1.)),
+            vec![Shape::Sphere(Sphere::default()), glow.clone()],
+        );
+        let xs = vec![Intersection::new(4., glow, None)];
+        let comps = xs[0]
+            .precompute(Ray::new(Point3::new(0., 0., 5.), Vector3::unit_z()), &xs)
+            .unwrap();
+        assert!(w.is_shadowed(comps.over_point()));
+        assert_relative_eq!(w.shade_hit(&comps, w.remaining()), RGB::new(0.3, 0.5, 0.7));
+    }
+
+    #[test]
+    fn absorption() {
+        let glass = Shape::Sphere(Sphere::new(
+            Matrix4::identity(),
+            Material {
+                ambient: 0.,
+                diffuse: 0.,
+                specular: 0.,
+                transparency: 1.,
+                absorption: RGB::new(0.5, 0.5, 0.5),
+                ..Material::default()
+            },
+            None,
+        ));
+        let wall = Shape::Plane(Plane::new(
+            Matrix4::from_translation(Vector3::unit_z() * 5.) * Matrix4::from_angle_x(Rad(PI / 2.)),
+            Material {
+                ambient: 1.,
+                diffuse: 0.,
+                specular: 0.,
+                ..Material::default()
+            },
+            None,
+        ));
+        let mut w = World::new(
+            Light::new(Point3::new(-10., 10., -10.), RGB::new(1., 1., 1.)),
+            vec![glass, wall],
+        );
+        let through = |w: &mut World<f32>, x| {
+            w.color_at(Ray::new(Point3::new(x, 0., -5.), Vector3::unit_z()))
+                .r
+        };
+        let center = through(&mut w, 0.);
+        let edge = through(&mut w, 0.9);
+        assert_relative_eq!(center, (-1.0_f32).exp(), max_relative = 0.0001);
+        assert!(center < edge && edge < 1.);
+    }
+
+    #[test]
+    fn absorption_slab() {
+        let slab = |thickness: f32, absorption| {
+            Shape::Cube(Cube::new(
+                Matrix4::from_nonuniform_scale(2., 2., thickness / 2.),
+                Material {
+                    ambient: 0.,
+                    diffuse: 0.,
+                    specular: 0.,
+                    transparency: 1.,
+                    absorption,
+                    ..Material::default()
+                },
+                None,
+            ))
+        };
+        let wall = Shape::Plane(Plane::new(
+            Matrix4::from_translation(Vector3::unit_z() * 5.) * Matrix4::from_angle_x(Rad(PI / 2.)),
+            Material {
+                ambient: 1.,
+                diffuse: 0.,
+                specular: 0.,
+                ..Material::default()
+            },
+            None,
+        ));
+        let through = |slab| {
+            let w = World::new(
+                Light::new(Point3::new(-10., 10., -10.), RGB::new(1., 1., 1.)),
+                vec![slab, wall.clone()],
+            );
+            w.color_at(Ray::new(Point3::new(0., 0., -5.), Vector3::unit_z()))
+        };
+        let red_glass = RGB::new(0., 1., 1.);
+        let thin = through(slab(0.2, red_glass));
+        let thick = through(slab(2., red_glass));
+        // Red passes untouched, the other channels fade with the distance travelled inside.
+        assert_relative_eq!(thin, RGB::new(1., (-0.2_f32).exp(), (-0.2_f32).exp()));
+        assert_relative_eq!(thick, RGB::new(1., (-2.0_f32).exp(), (-2.0_f32).exp()));
+        assert_eq!(
+            through(slab(2., RGB::default())),
+            through(slab(0.2, RGB::default()))
+        );
+    }
+
+    #[test]
+    fn recursion_depths() {
+        let glass = |scale| {
+            Shape::Sphere(Sphere::new(
+                Matrix4::from_scale(scale),
+                Material {
+                    ambient: 0.,
+                    diffuse: 0.,
+                    specular: 0.,
+                    reflective: 0.5,
+                    transparency: 1.,
+                    ..Material::default()
+                },
+                None,
+            ))
+        };
+        let wall = Shape::Plane(Plane::new(
+            Matrix4::from_translation(Vector3::unit_z() * 10.)
+                * Matrix4::from_angle_x(Rad(PI / 2.)),
+            Material {
+                ambient: 1.,
+                diffuse: 0.,
+                specular: 0.,
+                ..Material::default()
+            },
+            None,
+        ));
+        // Four nested shells put eight refractions between the eye and the wall.
+        let mut w = World::new(
+            Light::new(Point3::new(-10., 10., -10.), RGB::new(1., 1., 1.)),
+            vec![glass(1.), glass(2.), glass(3.), glass(4.), wall],
+        );
+        w.max_reflection_depth = 0;
+        let r = Ray::new(Point3::new(0., 0., -10.), Vector3::unit_z());
+        assert_eq!(w.color_at(r), RGB::default());
+        w.max_refraction_depth = 8;
+        // The combined depth still stops the ray after five refractions.
+        assert_eq!(w.color_at(r), RGB::default());
+        let w = w.with_max_depth(8);
+        assert_relative_eq!(w.color_at(r), RGB::new(1., 1., 1.), max_relative = 0.0001);
+        let xs = w.intersect(r);
+        let comps = xs[0].precompute(r, &xs).unwrap();
+        assert_eq!(w.reflected_color(&comps, w.remaining()), RGB::default());
+    }
+
+    #[test]
+    fn trace_path() {
+        let w = World::default();
+        let path = w.trace_path(Ray::new(Point3::new(0., 0., -5.), Vector3::unit_z()));
+        assert_eq!(path.len(), 1);
+        assert_eq!(path[0].kind, RayKind::Camera);
+        assert_eq!(path[0].object, *w.objects[0].borrow());
+        assert_relative_eq!(
+            path[0].color,
+            RGB::new(0.38066, 0.47583, 0.2855),
+            max_relative = 0.0001
+        );
+
+        // A mirror box: the ray bounces between the floor and the ceiling until the depth runs out.
+        let mirror = |y| {
+            Shape::Plane(Plane {
+                transform: Matrix4::from_translation(Vector3::unit_y() * y),
+                material: Material {
+                    reflective: 1.,
+                    ..Material::default()
+                },
+                ..Plane::default()
+            })
+        };
+        let w = World {
+            light: Light::new(Point3::origin(), RGB::new(1., 1., 1.)),
+            objects: vec![get_rc(mirror(-1.)), get_rc(mirror(1.))],
+            ..World::default()
+        };
+        let path = w.trace_path(Ray::new(Point3::origin(), Vector3::unit_y()));
+        assert_eq!(path.len(), 1 + RECURSION_LIMIT as usize);
+        assert_eq!(path[0].kind, RayKind::Camera);
+        assert!(path[1..].iter().all(|v| v.kind == RayKind::Reflected));
+        assert_eq!(path[1].object, *w.objects[0].borrow());
+    }
+
+    #[test]
+    fn emissive_triangle() {
+        let floor = Shape::Plane(Plane::default());
+        let mut lamp = Triangle::from(
+            Point3::new(-1., 1., -1.),
+            Point3::new(1., 1., -1.),
+            Point3::new(0., 1., 1.),
+        );
+        lamp.material.emission = RGB::new(1., 1., 1.);
+        let mut w = World::new(
+            Light::new(Point3::new(0., 10., 0.), RGB::default()),
+            vec![floor, Shape::Triangle(lamp)],
+        );
+        let r = Ray::new(Point3::new(0., 0.5, 0.), -Vector3::unit_y());
+        // The point light is black, so without the lamp the floor gets nothing, not even ambient.
+        assert!(w.color_at(r).r > 0.5);
+        w.objects.truncate(1);
+        assert_eq!(w.color_at(r), RGB::default());
+    }
+
+    #[test]
+    fn emissive_triangle_in_group() {
+        let mut lamp = Triangle::from(
+            Point3::new(-1., 0., -1.),
+            Point3::new(1., 0., -1.),
+            Point3::new(0., 0., 1.),
+        );
+        lamp.material.emission = RGB::new(1., 1., 1.);
+        // The lamp only clears the floor once its group lifts it.
+        let group = get_rc(Shape::Group(Group {
+            transform: Matrix4::from_translation(Vector3::unit_y()),
+            ..Group::default()
+        }));
+        push(&group, Shape::Triangle(lamp));
+        let mut w = World::new(
+            Light::new(Point3::new(0., 10., 0.), RGB::default()),
+            vec![Shape::Plane(Plane::default())],
+        );
+        w.objects.push(group);
+        let lights = w.emitter_lights(Point3::origin());
+        assert_eq!(lights.len(), AREA_LIGHT_SAMPLES * AREA_LIGHT_SAMPLES);
+        for (_, light) in &lights {
+            assert_relative_eq!(light.position().y, 1., epsilon = 0.00001);
+        }
+        let r = Ray::new(Point3::new(0., 0.5, 0.), -Vector3::unit_y());
+        assert!(w.color_at(r).r > 0.5);
+    }
+
+    #[test]
+    fn mesh_texture() {
+        let (red, green, blue, white) = (
+            RGB::new(1., 0., 0.),
+            RGB::new(0., 1., 0.),
+            RGB::new(0., 0., 1.),
+            RGB::new(1., 1., 1.),
+        );
+        let mut canvas = Canvas::new(2, 2);
+        canvas.pixels = vec![vec![red, green], vec![blue, white]];
+        let mut triangle = Triangle::from(
+            Point3::new(0., 0., 0.),
+            Point3::new(2., 0., 0.),
+            Point3::new(0., 2., 0.),
+        );
+        // Upside down, so that planar mapping would pick other pixels.
+        triangle.texture_coords = Some([(0., 1.), (1., 1.), (0., 0.)]);
+        triangle.material.pattern = Pattern::ImageTexture(ImageTexture::new(
+            canvas,
+            UvMapping::Mesh,
+            Matrix4::identity(),
+        ));
+        triangle.material.ambient = 1.;
+        triangle.material.diffuse = 0.;
+        triangle.material.specular = 0.;
+        let w = World::new(
+            Light::new(Point3::new(0., 0., -10.), RGB::new(1., 1., 1.)),
+            vec![Shape::Triangle(triangle)],
+        );
+        let color = |x, y| w.color_at(Ray::new(Point3::new(x, y, -5.), Vector3::unit_z()));
+        assert_relative_eq!(color(1.5, 0.2), green);
+        assert_relative_eq!(color(0.2, 1.5), blue);
+    }
+
+    #[test]
+    fn build_accel() {
+        let mut w = World::default();
+        w.objects.push(get_rc(Shape::Plane(Plane::default())));
+        w.objects.push(get_rc(Shape::Sphere(Sphere::new(
+            Matrix4::from_translation(Vector3::new(3., 0., 0.)),
+            Material::default(),
+            None,
+        ))));
+        let rays = [
+            Ray::new(Point3::new(0., 0., -5.), Vector3::unit_z()),
+            Ray::new(Point3::new(3., 0.5, -5.), Vector3::unit_z()),
+            Ray::new(Point3::new(0., 5., 0.), -Vector3::unit_y()),
+            Ray::new(
+                Point3::new(-5., 0., -5.),
+                Vector3::new(1., 0., 1.).normalize(),
+            ),
+        ];
+        let expected = rays.map(|r| w.intersect(r));
+        w.build_accel();
+        let accel = w.accel.clone();
+        w.build_accel();
+        assert_eq!(w.accel, accel);
+        assert_eq!(rays.map(|r| w.intersect(r)), expected);
+        // Objects pushed after the cache was built are still hit.
+        w.objects.push(get_rc(Shape::Sphere(Sphere::new(
+            Matrix4::from_translation(Vector3::new(-3., 0., 0.)),
+            Material::default(),
+            None,
+        ))));
+        let ray = Ray::new(Point3::new(-3., 0.5, -5.), Vector3::unit_z());
+        assert_eq!(w.intersect(ray).len(), 2);
+    }
+
+    #[test]
+    fn smooth_shading() {
+        let octahedron = get_rc(Shape::Group(Group::default()));
+        for (sx, sy, sz) in [
+            (1., 1., 1.),
+            (-1., 1., 1.),
+            (1., -1., 1.),
+            (1., 1., -1.),
+            (-1., -1., 1.),
+            (-1., 1., -1.),
+            (1., -1., -1.),
+            (-1., -1., -1.),
+        ] {
+            let (x, y, z) = (
+                Point3::new(sx, 0., 0.),
+                Point3::new(0., sy, 0.),
+                Point3::new(0., 0., sz),
+            );
+            // Keep the face normals pointing outwards.
+            let face = if sx * sy * sz > 0. {
+                Triangle::from(x, z, y)
+            } else {
+                Triangle::from(x, y, z)
+            };
+            push(&octahedron, Shape::Triangle(face));
+        }
+        // The mesh is added after smooth shading is turned on.
+        let mut w = World::new(
+            Light::new(Point3::new(-10., 10., -10.), RGB::new(1., 1., 1.)),
+            vec![],
+        )
+        .with_smooth_shading();
+        w.objects.push(get_rc(octahedron.borrow().clone()));
+        // The largest step in brightness between neighbouring samples across the front faces.
+        let largest_step = |w: &World<f32>| {
+            let colors = (-8..=8)
+                .map(|i| {
+                    let r = Ray::new(Point3::new(i as f32 * 0.1, 0.1, -5.), Vector3::unit_z());
+                    w.color_at(r).g
+                })
+                .collect::<Vec<_>>();
+            colors
+                .windows(2)
+                .map(|pair| (pair[1] - pair[0]).abs())
+                .fold(0., f32::max)
+        };
+        let smoothed = largest_step(&w);
+        w.smooth_shading = false;
+        let faceted = largest_step(&w);
+        assert!(smoothed < faceted / 2.);
+        // Smoothing leaves the shapes as they were.
+        for child in &octahedron.borrow().as_group().unwrap().children {
+            assert_eq!(child.borrow().as_triangle().unwrap().vertex_normals, None);
+        }
+        w.smooth_shading = true;
+        assert_eq!(largest_step(&w), smoothed);
+    }
+
+    #[test]
+    fn alpha() {
+        let decal = |alpha| {
+            Shape::Sphere(Sphere::new(
+                Matrix4::identity(),
+                Material {
+                    pattern: Pattern::SolidAlpha(RGBA::new(1., 0., 0., alpha)),
+                    ..Material::default()
+                },
+                None,
+            ))
+        };
+        let wall = Shape::Plane(Plane::new(
+            Matrix4::from_translation(Vector3::unit_z() * 5.) * Matrix4::from_angle_x(Rad(PI / 2.)),
+            Material {
+                pattern: Pattern::Solid(RGB::new(0., 0., 1.)),
+                ambient: 1.,
+                diffuse: 0.,
+                specular: 0.,
+                ..Material::default()
+            },
+            None,
+        ));
+        let light = Light::new(Point3::new(-10., 10., -10.), RGB::new(1., 1., 1.));
+        let r = Ray::new(Point3::new(0., 0., -5.), Vector3::unit_z());
+        let w = World::new(light, vec![decal(0.), wall.clone()]);
+        assert_relative_eq!(w.color_at(r), RGB::new(0., 0., 1.));
+        let w = World::new(light, vec![decal(1.), wall.clone()]);
+        assert_eq!(w.color_at(r).b, 0.);
+        // Behind the decal, the wall is in shadow only where the decal mostly covers it.
+        let light = Light::new(Point3::new(0., 0., -10.), RGB::new(1., 1., 1.));
+        let behind = Point3::new(0., 0., 4.99);
+        for (alpha, shadowed) in [(0., false), (0.4, false), (0.6, true), (1., true)] {
+            let w = World::new(light, vec![decal(alpha), wall.clone()]);
+            assert_eq!(w.is_shadowed(behind), shadowed);
+        }
+    }
+
+    #[test]
+    fn environment() {
+        // With a black point light, only the environment lights the sphere.
+        let mut w = World::new(
+            Light::new(Point3::new(-10., 10., -10.), RGB::new(0., 0., 0.)),
+            vec![Shape::Sphere(Sphere::default())],
+        );
+        let r = Ray::new(Point3::new(0., 0., -5.), Vector3::unit_z());
+        assert_eq!(w.color_at(r), RGB::new(0., 0., 0.));
+        let mut map = Canvas::new(4, 2);
+        map.pixels.fill(vec![RGB::new(1., 0.5, 0.25); 4]);
+        w.environment = Some(EnvironmentLight::from(map));
+        assert_relative_eq!(
+            w.color_at(r),
+            RGB::new(0.9, 0.45, 0.225),
+            max_relative = 0.0001
+        );
+    }
+
+    #[test]
+    fn nearest_hit() {
+        let mut w = World::default();
+        let r = Ray::new(Point3::origin(), Vector3::unit_z());
+        let (i, index) = w.nearest_hit(r).unwrap();
+        assert_eq!((i.t, index), (0.5, 1));
+        // The hit shares the Rc the world keeps the sphere in.
+        assert!(Rc::ptr_eq(&i.object, &w.objects[1]));
+        // A triangle inside a group still reports the group's index.
+        let group = get_rc(Shape::Group(Group::default()));
+        push(
+            &group,
+            Shape::Triangle(Triangle::from(
+                Point3::new(-1., -1., 0.25),
+                Point3::new(0., 1., 0.25),
+                Point3::new(1., -1., 0.25),
+            )),
+        );
+        w.objects.push(group);
+        let (i, index) = w.nearest_hit(r).unwrap();
+        assert_eq!((i.t, index), (0.25, 2));
+        assert!(w
+            .nearest_hit(Ray::new(Point3::new(0., 0., -5.), -Vector3::unit_z()))
+            .is_none());
+    }
+
+    #[test]
+    fn background() {
+        let mut w = World {
+            background: RGB::new(0.2, 0.4, 0.8),
+            ..World::default()
+        };
+        let r = Ray::new(Point3::new(0., 0., -5.), Vector3::unit_y());
+        assert_eq!(w.color_at(r), w.background);
+        // A mirror that may not reflect any further falls back to the background too.
+        let shape = Shape::Plane(Plane {
+            transform: Matrix4::from_translation(-Vector3::unit_y()),
+            material: Material {
+                reflective: 0.5,
+                ..Material::default()
+            },
+            ..Plane::default()
+        });
+        let r = Ray::new(
+            Point3::from_vec(Vector3::unit_z() * -3.),
+            Vector3::new(0., -FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+        );
+        let xs = vec![Intersection::new(2.0_f32.sqrt(), shape, None)];
+        let comps = xs[0].precompute(r, &xs).unwrap();
+        w.max_reflection_depth = 0;
+        assert_relative_eq!(
+            w.reflected_color(&comps, w.remaining()),
+            RGB::new(0.1, 0.2, 0.4)
+        );
+    }
+
+    #[test]
+    fn seed() {
+        let mut lamp = Triangle::from(
+            Point3::new(-1., 2., -1.),
+            Point3::new(1., 2., -1.),
+            Point3::new(0., 2., 1.),
+        );
+        lamp.material.emission = RGB::new(1., 1., 1.);
+        // A small ball between the lamp and the floor casts a soft shadow.
+        let blocker = Shape::Sphere(Sphere::new(
+            Matrix4::from_translation(Vector3::unit_y()) * Matrix4::from_scale(0.3),
+            Material::default(),
+            None,
+        ));
+        let mut w = World::new(
+            Light::new(Point3::new(0., 10., 0.), RGB::default()),
+            vec![
+                Shape::Plane(Plane::default()),
+                blocker,
+                Shape::Triangle(lamp),
+            ],
+        );
+        let mut c = Camera::from(8, 8, PI / 3.);
+        c.transform = Matrix4::look_at_rh(
+            Point3::new(0., 1.5, -0.01),
+            Point3::origin(),
+            Vector3::unit_y(),
+        );
+        w.seed = 7;
+        let first = c.render(&w);
+        assert_eq!(c.render(&w), first);
+        w.seed = 8;
+        assert_ne!(c.render(&w), first);
+    }
+
+    #[test]
+    fn fog() {
+        let mut w = World::<f32>::default();
+        let ray = Ray::new(Point3::new(0., 0., -5.), Vector3::unit_z());
+        let clear = w.color_at(ray);
+        let fog = Fog {
+            color: RGB::new(1., 1., 1.),
+            density: 0.1,
+        };
+        w.fog = Some(fog);
+        let near = w.color_at(ray);
+        assert!(near.r > clear.r);
+        let far = w.color_at(Ray::new(Point3::new(0., 0., -20.), Vector3::unit_z()));
+        assert!(far.r > near.r && far.r < 1.);
+        assert_relative_eq!(
+            near,
+            clear * (-0.4f32).exp() + fog.color * (1. - (-0.4f32).exp())
+        );
+    }
+
+    #[test]
+    fn material_library() {
+        let mut w = World::<f32>::default();
+        let paint = w.materials.define(
+            "paint",
+            Material {
+                pattern: Pattern::Solid(RGB::new(1., 0., 0.)),
+                ..Material::default()
+            },
+        );
+        assert_eq!(w.materials.handle("paint"), Some(paint));
+        for object in &w.objects {
+            object
+                .borrow_mut()
+                .map_materials(|m| *m = Material::from_library(paint));
+        }
+        let rays = [
+            Ray::new(Point3::new(0., 0., -5.), Vector3::unit_z()),
+            // From inside the outer sphere towards the inner one.
+            Ray::new(Point3::new(0., 0., -0.75), Vector3::unit_z()),
+        ];
+        for ray in rays {
+            let color = w.color_at(ray);
+            assert!(color.r > 0. && color.g == 0. && color.b == 0.);
+        }
+        w.materials.get_mut(paint).unwrap().pattern = Pattern::Solid(RGB::new(0., 0., 1.));
+        for ray in rays {
+            let color = w.color_at(ray);
+            assert!(color.r == 0. && color.g == 0. && color.b > 0.);
+        }
+    }
+
+    #[test]
+    fn self_shadowing() {
+        fn check<T: BaseFloat + Default + std::fmt::Debug>() {
+            let mut w = World::<T>::default();
+            w.objects.push(get_rc(Shape::Plane(Plane {
+                transform: Matrix4::from_translation(-Vector3::unit_y()),
+                ..Plane::default()
+            })));
+            let origin = Point3::new(-3., 4., -6.).cast::<T>().unwrap();
+            let steps = 24;
+            for i in 0..steps {
+                for j in 0..steps {
+                    let f = |k: usize| T::from(k as f64 / steps as f64 - 0.5).unwrap();
+                    let target =
+                        Point3::new(f(i) * T::from(3).unwrap(), -T::one() + f(j), T::zero());
+                    let ray = Ray::new(origin, (target - origin).normalize());
+                    let xs = w.intersect(ray);
+                    let Some(comps) = hit(&xs).and_then(|i| i.precompute(ray, &xs)) else {
+                        continue;
+                    };
+                    let lightv = w.light.position() - comps.point;
+                    let to_light = Ray::new(comps.point, lightv.normalize());
+                    // Only points that nothing else blocks from the light.
+                    let blocked = w.intersect(to_light).iter().any(|i| {
+                        i.object != comps.object && i.t > T::zero() && i.t < lightv.magnitude()
+                    });
+                    if comps.normalv.dot(lightv) > T::zero() && !blocked {
+                        assert!(!w.is_shadowed(w.shadow_point(&comps)), "{:?}", comps.point);
+                    }
+                }
+            }
+        }
+        check::<f32>();
+        check::<f64>();
+    }
 }
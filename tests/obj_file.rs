@@ -0,0 +1,46 @@
+use cgmath::Point3;
+use ray_tracer_challenge::{
+    pattern::{uv::UvMapping, Pattern},
+    shape::{obj_file::Parser, triangle::Triangle, Shape},
+};
+use rgb::RGB;
+use std::path::Path;
+
+#[test]
+fn from_path() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/quad.obj");
+    let group = Parser::<f64>::from_path(&path).unwrap().obj_to_group();
+    let group = group.borrow();
+    let quad = group.as_group().unwrap().children[0].borrow();
+    let triangles = &quad.as_group().unwrap().children;
+    assert_eq!(triangles.len(), 2);
+    assert_eq!(
+        *triangles[1].borrow(),
+        Shape::Triangle(Triangle::from(
+            Point3::new(0., 0., 0.),
+            Point3::new(1., 1., 0.),
+            Point3::new(0., 1., 0.),
+        ))
+    );
+    assert!(Parser::<f64>::from_path(Path::new("missing.obj")).is_err());
+}
+
+#[test]
+fn map_kd() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/textured.obj");
+    let group = Parser::<f64>::from_path(&path).unwrap().obj_to_group();
+    let group = group.borrow();
+    let default = group.as_group().unwrap().children[0].borrow();
+    let triangle = default.as_group().unwrap().children[0].borrow();
+    match &triangle.material().unwrap().pattern {
+        Pattern::ImageTexture(texture) => {
+            let (white, black) = (RGB::new(1., 1., 1.), RGB::new(0., 0., 0.));
+            assert_eq!(
+                texture.canvas.pixels,
+                vec![vec![white, black], vec![black, white]]
+            );
+            assert_eq!(texture.mapping, UvMapping::Mesh);
+        }
+        pattern => panic!("expected an image texture, got {pattern:?}"),
+    }
+}